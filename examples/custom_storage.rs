@@ -0,0 +1,85 @@
+// Runs the engine on a storage backend of your own. This one keeps transactions in a
+// BTreeMap and counts lookups; a real backend would talk to a database in the same methods.
+//
+//     cargo run --example custom_storage
+use exchange_test::{
+    EngineConfig, PaymentsEngine, Record, Storage, StorageError, TransactionId, TxType,
+};
+use rust_decimal::Decimal;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Default)]
+struct CountingStorage {
+    transactions: BTreeMap<TransactionId, Record>,
+    disputes: BTreeSet<TransactionId>,
+    lookups: Cell<usize>,
+}
+
+impl Storage for CountingStorage {
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        self.lookups.set(self.lookups.get() + 1);
+        Ok(self.transactions.get(&tx).copied())
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        self.transactions.insert(record.tx, *record);
+        Ok(())
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.disputes.contains(&tx))
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.insert(tx);
+        Ok(())
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.remove(&tx);
+        Ok(())
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        Ok(self.disputes.len())
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine =
+        PaymentsEngine::with_storage(CountingStorage::default(), EngineConfig::default())?;
+    let record = |tx_type, client, tx, amount| Record {
+        tx_type,
+        client,
+        tx,
+        amount,
+    };
+
+    for r in [
+        record(TxType::Deposit, 1, 1, Some(Decimal::new(100, 0))),
+        record(TxType::Deposit, 2, 2, Some(Decimal::new(50, 0))),
+        record(TxType::Withdrawal, 1, 3, Some(Decimal::new(30, 0))),
+        record(TxType::Dispute, 2, 2, None),
+        // Refused: the deposit id is taken
+        record(TxType::Deposit, 2, 1, Some(Decimal::new(5, 0))),
+    ] {
+        if let Err(e) = engine.process(&r) {
+            println!("refused tx {}: {}", r.tx, e);
+        }
+    }
+
+    println!("open disputes: {}", engine.open_disputes()?);
+    let mut accounts: Vec<_> = engine.accounts().iter().collect();
+    accounts.sort_by_key(|(client, _)| **client);
+    for (client, account) in accounts {
+        println!(
+            "client {}: available {} held {} total {}",
+            client,
+            account.available,
+            account.held(),
+            account.total
+        );
+    }
+    Ok(())
+}
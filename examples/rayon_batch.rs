@@ -0,0 +1,59 @@
+// Processes a transactions CSV on all cores with rayon. Records are split by client id into
+// one batch per thread; each batch keeps its input order and runs on an engine of its own,
+// and the accounts of the batches, which never share a client, are merged at the end.
+// Transaction ids must be unique across clients, as a reused id is only caught within a batch.
+//
+//     cargo run --example rayon_batch -- tests/data/test_data.csv
+use exchange_test::{PaymentsEngine, Record};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or("usage: rayon_batch <transactions.csv>")?;
+
+    let batches = rayon::current_num_threads();
+    let mut records: Vec<Vec<Record>> = vec![Vec::new(); batches];
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(&path)?;
+    for (i, row) in rdr.deserialize::<Record>().enumerate() {
+        match row {
+            Ok(record) => records[usize::from(record.client) % batches].push(record),
+            // Line 1 is the header
+            Err(e) => eprintln!("{} line {}: {}", path, i + 2, e),
+        }
+    }
+
+    let accounts: HashMap<_, _> = records
+        .into_par_iter()
+        .flat_map_iter(|batch| {
+            let mut engine = PaymentsEngine::new();
+            // Refusals are part of normal processing; they leave the accounts as they were
+            for (record, result) in batch.iter().zip(engine.process_batch(&batch)) {
+                if let Err(e) = result {
+                    eprintln!("refused tx {}: {}", record.tx, e);
+                }
+            }
+            engine.finalize()
+        })
+        .collect();
+
+    let mut clients: Vec<_> = accounts.keys().copied().collect();
+    clients.sort_unstable();
+    println!("client,available,held,total,locked");
+    for client in clients {
+        let account = &accounts[&client];
+        println!(
+            "{},{:.4},{:.4},{:.4},{}",
+            client,
+            account.available,
+            account.held(),
+            account.total,
+            account.locked
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+// Embeds the engine in a tokio service: producer tasks send transactions over a channel, as a
+// network consumer would, and the engine applies them as the channel yields them. A second
+// source reads a CSV body through the async reader.
+//
+//     cargo run --example tokio_service --features async
+use exchange_test::{read_records, PaymentsEngine, Record, TxType};
+use futures_util::stream::{self, StreamExt};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, mut receiver) = mpsc::channel::<Record>(64);
+    for client in 1..=3u16 {
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            for i in 0..5u32 {
+                let record = Record {
+                    tx_type: TxType::Deposit,
+                    client,
+                    tx: u32::from(client) * 100 + i,
+                    amount: Some(Decimal::new(10, 0)),
+                };
+                if sender.send(record).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    // The stream ends once every producer is done and has dropped its sender
+    drop(sender);
+    let records = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+
+    let mut engine = PaymentsEngine::new();
+    engine
+        .process_stream(records, |record, e| {
+            eprintln!("refused tx {}: {}", record.tx, e)
+        })
+        .await?;
+
+    // Rows of a CSV body, from an HTTP upload say; rows that do not parse are skipped
+    let body = "type,client,tx,amount\nwithdrawal,1,900,2.5\ndispute,2,200,\nbogus,3,901,1\n";
+    let records = read_records(body.as_bytes())
+        .filter_map(|row| async move { row.map_err(|e| eprintln!("skipped {}", e)).ok() });
+    engine
+        .process_stream(records, |record, e| {
+            eprintln!("refused tx {}: {}", record.tx, e)
+        })
+        .await?;
+
+    let mut accounts: Vec<_> = engine.accounts().iter().collect();
+    accounts.sort_by_key(|(client, _)| **client);
+    for (client, account) in accounts {
+        println!(
+            "client {}: available {} held {} total {}",
+            client,
+            account.available,
+            account.held(),
+            account.total
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use rust_decimal::Decimal;
+
+use crate::{EngineError, TxType};
+
+// Funds held on an account, split by the reason they are unavailable
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Holds {
+    // Held because of open disputes
+    pub dispute: Decimal,
+    // Held back as the risk reserve of EngineConfig::reserve
+    pub reserve: Decimal,
+}
+
+impl Holds {
+    pub fn total(&self) -> Decimal {
+        self.dispute + self.reserve
+    }
+}
+
+// Represents a client's account, storing/managing balances and status
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub available: Decimal,
+    pub holds: Holds,
+    pub total: Decimal,
+    pub locked: bool,
+    // Sum of every amount ever disputed on this account, whatever the outcome
+    pub disputed_lifetime: Decimal,
+}
+
+impl Account {
+    pub(crate) fn new() -> Account {
+        Account {
+            available: Decimal::new(0, 0),
+            holds: Holds::default(),
+            total: Decimal::new(0, 0),
+            locked: false,
+            disputed_lifetime: Decimal::new(0, 0),
+        }
+    }
+
+    // All held funds, whatever the reason; this is the `held` balance of the output
+    pub fn held(&self) -> Decimal {
+        self.holds.total()
+    }
+
+    // What the client could withdraw right now: nothing from a locked account, otherwise its
+    // funds less everything held for disputes or the reserve, plus the overdraft it may run
+    pub fn withdrawable(&self, overdraft_limit: Decimal) -> Decimal {
+        if self.locked {
+            Decimal::ZERO
+        } else {
+            (self.total - self.holds.total() + overdraft_limit).max(Decimal::ZERO)
+        }
+    }
+
+    // Adds another account's balances, holds and dispute history to this one; a lock on
+    // either account carries over
+    pub(crate) fn absorb(&mut self, other: Account) {
+        self.available += other.available;
+        self.holds.dispute += other.holds.dispute;
+        self.holds.reserve += other.holds.reserve;
+        self.total += other.total;
+        self.locked |= other.locked;
+        self.disputed_lifetime += other.disputed_lifetime;
+    }
+
+    // Moves funds between available and the reserve until the reserve holds `target`, or as
+    // much of it as the account has outside of disputes
+    pub(crate) fn hold_reserve(&mut self, target: Decimal) {
+        let free = self.available + self.holds.reserve;
+        self.holds.reserve = free.clamp(Decimal::ZERO, target);
+        self.available = free - self.holds.reserve;
+    }
+
+    pub(crate) fn deposit(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        if self.locked {
+            return Err(EngineError::AccountLocked(TxType::Deposit));
+        }
+        self.available += amount;
+        self.total += amount;
+        Ok(())
+    }
+
+    // Takes the available balance down to at most `overdraft_limit` below zero
+    pub(crate) fn withdraw(
+        &mut self,
+        amount: Decimal,
+        overdraft_limit: Decimal,
+    ) -> Result<(), EngineError> {
+        if self.locked {
+            return Err(EngineError::AccountLocked(TxType::Withdrawal));
+        }
+        if self.withdrawable(overdraft_limit) >= amount {
+            self.available -= amount;
+            self.total -= amount;
+            Ok(())
+        } else {
+            Err(EngineError::InsufficientFunds(TxType::Withdrawal))
+        }
+    }
+
+    pub(crate) fn apply_dispute(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        if self.locked {
+            return Err(EngineError::AccountLocked(TxType::Dispute));
+        }
+        // The disputed funds have to be held whatever the reserve, so it is drawn on too
+        let free = self.available + self.holds.reserve;
+        if free >= amount {
+            self.available = free - amount;
+            self.holds.reserve = Decimal::ZERO;
+            self.holds.dispute += amount;
+            self.disputed_lifetime += amount;
+            Ok(())
+        } else {
+            Err(EngineError::InsufficientFunds(TxType::Dispute))
+        }
+    }
+
+    pub(crate) fn resolve_dispute(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        if self.locked {
+            return Err(EngineError::AccountLocked(TxType::Resolve));
+        }
+        if self.holds.dispute >= amount {
+            self.holds.dispute -= amount;
+            self.available += amount;
+            Ok(())
+        } else {
+            Err(EngineError::InsufficientFunds(TxType::Resolve))
+        }
+    }
+
+    pub(crate) fn chargeback(&mut self, amount: Decimal) -> Result<(), EngineError> {
+        if self.locked {
+            return Err(EngineError::AccountLocked(TxType::Chargeback));
+        }
+        if self.holds.dispute >= amount {
+            self.total -= amount;
+            self.holds.dispute -= amount;
+            self.locked = true;
+            Ok(())
+        } else {
+            Err(EngineError::InsufficientFunds(TxType::Chargeback))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_deposit_and_withdrawal() {
+        let mut account = Account::new();
+        account.deposit(Decimal::new(1000, 2)).unwrap();
+        account
+            .withdraw(Decimal::new(500, 2), Decimal::ZERO)
+            .unwrap();
+
+        assert_eq!(account.available, Decimal::new(500, 2));
+        assert_eq!(account.total, Decimal::new(500, 2));
+        assert_eq!(account.held(), Decimal::new(0, 2));
+    }
+
+    #[test]
+    fn test_account_dispute_and_resolve() {
+        let mut account = Account::new();
+        account.deposit(Decimal::new(1000, 2)).unwrap();
+        account.apply_dispute(Decimal::new(1000, 2)).unwrap();
+        account.resolve_dispute(Decimal::new(1000, 2)).unwrap();
+
+        assert_eq!(account.available, Decimal::new(1000, 2));
+        assert_eq!(account.held(), Decimal::new(0, 2));
+        assert_eq!(account.total, Decimal::new(1000, 2));
+        assert_eq!(account.holds.dispute, Decimal::new(0, 2));
+        assert_eq!(account.disputed_lifetime, Decimal::new(1000, 2));
+    }
+
+    #[test]
+    fn test_account_chargeback() {
+        let mut account = Account::new();
+        account.deposit(Decimal::new(1000, 2)).unwrap();
+        account.apply_dispute(Decimal::new(1000, 2)).unwrap();
+        account.chargeback(Decimal::new(1000, 2)).unwrap();
+
+        assert_eq!(account.available, Decimal::new(0, 2));
+        assert_eq!(account.held(), Decimal::new(0, 2));
+        assert_eq!(account.total, Decimal::new(0, 2));
+        assert!(account.locked);
+        assert_eq!(account.withdrawable(Decimal::ONE), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_account_reserve() {
+        let mut account = Account::new();
+        account.deposit(Decimal::new(1000, 2)).unwrap();
+        account.hold_reserve(Decimal::new(400, 2));
+        assert_eq!(account.available, Decimal::new(600, 2));
+        assert_eq!(account.held(), Decimal::new(400, 2));
+        assert!(account
+            .withdraw(Decimal::new(700, 2), Decimal::ZERO)
+            .is_err());
+
+        // A dispute draws on the reserve, which is then held again from what is left
+        account.apply_dispute(Decimal::new(800, 2)).unwrap();
+        account.hold_reserve(Decimal::new(400, 2));
+        assert_eq!(account.available, Decimal::ZERO);
+        assert_eq!(account.holds.reserve, Decimal::new(200, 2));
+        assert_eq!(account.holds.dispute, Decimal::new(800, 2));
+        assert_eq!(account.total, Decimal::new(1000, 2));
+    }
+
+    #[test]
+    fn test_account_withdrawable() {
+        let mut account = Account::new();
+        account.deposit(Decimal::new(1000, 2)).unwrap();
+        assert_eq!(account.withdrawable(Decimal::ZERO), Decimal::new(1000, 2));
+
+        // Disputed funds and the reserve are not withdrawable
+        account.apply_dispute(Decimal::new(300, 2)).unwrap();
+        assert_eq!(account.withdrawable(Decimal::ZERO), Decimal::new(700, 2));
+        account.hold_reserve(Decimal::new(200, 2));
+        assert_eq!(account.withdrawable(Decimal::ZERO), Decimal::new(500, 2));
+
+        // An overdraft adds to it, and withdrawing into it leaves only what remains of it
+        assert_eq!(
+            account.withdrawable(Decimal::new(400, 2)),
+            Decimal::new(900, 2)
+        );
+        assert!(account
+            .withdraw(Decimal::new(950, 2), Decimal::new(400, 2))
+            .is_err());
+        account
+            .withdraw(Decimal::new(600, 2), Decimal::new(400, 2))
+            .unwrap();
+        assert_eq!(account.available, Decimal::new(-100, 2));
+        assert_eq!(account.withdrawable(Decimal::ZERO), Decimal::ZERO);
+        assert_eq!(
+            account.withdrawable(Decimal::new(400, 2)),
+            Decimal::new(300, 2)
+        );
+    }
+}
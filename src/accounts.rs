@@ -0,0 +1,151 @@
+use exchange_test::{Account, Accounts, ClientId, Holds};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::path::Path;
+
+// A row of the accounts CSV written by `write_accounts`. Only the original five columns are
+// required, so older accounts files can still be read.
+#[derive(Debug, Deserialize)]
+pub struct AccountRow {
+    pub client: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+    #[serde(default)]
+    pub disputed_held: Option<Decimal>,
+    #[serde(default)]
+    pub disputed_lifetime: Option<Decimal>,
+}
+
+impl AccountRow {
+    // The output only breaks out disputed funds, so any other held funds come back as a reserve
+    fn into_account(self) -> Account {
+        let dispute = self.disputed_held.unwrap_or(self.held);
+        Account {
+            available: self.available,
+            holds: Holds {
+                dispute,
+                reserve: self.held - dispute,
+            },
+            total: self.total,
+            locked: self.locked,
+            disputed_lifetime: self.disputed_lifetime.unwrap_or(Decimal::ZERO),
+        }
+    }
+}
+
+// A row of an alias table: transactions for `alias` belong to the account of `client`
+#[derive(Debug, Deserialize)]
+pub struct AliasRow {
+    pub alias: ClientId,
+    pub client: ClientId,
+}
+
+pub fn read_aliases(path: &Path) -> Result<Vec<AliasRow>, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    Ok(rdr.deserialize().collect::<Result<_, _>>()?)
+}
+
+// Reads an accounts CSV back into account states, refusing rows whose balances do not add up
+// and clients listed more than once
+pub fn read_accounts(path: &Path) -> Result<Accounts, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let mut accounts = Accounts::default();
+
+    for (i, result) in rdr.deserialize().enumerate() {
+        // Line 1 is the header
+        let line = i + 2;
+        let row: AccountRow = result?;
+        let invalid = |reason: String| format!("{} line {}: {}", path.display(), line, reason);
+        if row.available + row.held != row.total {
+            return Err(invalid(format!(
+                "total {} is not available {} plus held {}",
+                row.total, row.available, row.held
+            ))
+            .into());
+        }
+        if row
+            .disputed_held
+            .is_some_and(|disputed| disputed > row.held)
+        {
+            return Err(invalid("disputed_held exceeds held".to_string()).into());
+        }
+        match accounts.entry(row.client) {
+            Entry::Occupied(_) => {
+                return Err(invalid(format!("client {} is listed twice", row.client)).into())
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(row.into_account());
+            }
+        }
+    }
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{write_accounts, OutputFormat};
+    use exchange_test::{EngineConfig, PaymentsEngine, Record, TxType};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_written_accounts_read_back() {
+        let mut engine = PaymentsEngine::new();
+        let record = |tx_type, client, tx, amount| Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+        for r in [
+            record(
+                TxType::Deposit,
+                1,
+                1,
+                Some(Decimal::new(1234567890121234, 4)),
+            ),
+            record(TxType::Deposit, 2, 2, Some(Decimal::new(5, 0))),
+            record(TxType::Deposit, 2, 3, Some(Decimal::new(2, 0))),
+            record(TxType::Dispute, 2, 3, None),
+        ] {
+            engine.process(&r).unwrap();
+        }
+        let accounts = engine.finalize();
+
+        let mut file = NamedTempFile::new().unwrap();
+        write_accounts(
+            &accounts,
+            OutputFormat::Csv,
+            true,
+            Decimal::ZERO,
+            file.as_file_mut(),
+        )
+        .unwrap();
+        assert_eq!(read_accounts(file.path()).unwrap(), accounts);
+
+        // The loaded balances are the starting point for further transactions
+        let mut engine = PaymentsEngine::with_accounts(accounts, EngineConfig::default());
+        engine
+            .process(&record(TxType::Withdrawal, 2, 4, Some(Decimal::new(5, 0))))
+            .unwrap();
+        assert_eq!(engine.accounts()[&2].total, Decimal::new(2, 0));
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "client,available,held,total,locked\n3,1.5,0,2,false").unwrap();
+        let err = read_accounts(file.path()).unwrap_err().to_string();
+        assert!(
+            err.ends_with("line 2: total 2 is not available 1.5 plus held 0"),
+            "{err}"
+        );
+    }
+}
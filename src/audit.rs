@@ -0,0 +1,56 @@
+use exchange_test::{ClientId, TransactionId};
+use serde::Serialize;
+use std::error::Error;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::output::{AccountState, AtomicFile};
+
+// One entry of the audit trail, written as a JSON object tagged with its event name
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    // Both accounts before a merge and the surviving account after it
+    MergeAccounts {
+        from: Box<AccountState>,
+        into: Box<AccountState>,
+        merged: Box<AccountState>,
+    },
+    // A transaction for a merged-away or migrated client id went to the surviving account
+    AliasResolved {
+        file: String,
+        line: u64,
+        tx: TransactionId,
+        client: ClientId,
+        resolved: ClientId,
+    },
+}
+
+impl AuditEvent {
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+// Writes the audit events of a run as JSON lines
+pub struct AuditLog {
+    wtr: BufWriter<AtomicFile>,
+}
+
+impl AuditLog {
+    pub fn create(path: &Path) -> Result<AuditLog, Box<dyn Error>> {
+        Ok(AuditLog {
+            wtr: BufWriter::new(AtomicFile::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, event: &AuditEvent) -> Result<(), Box<dyn Error>> {
+        event.write_to(&mut self.wtr)
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.wtr.into_inner().map_err(|e| e.into_error())?.commit()
+    }
+}
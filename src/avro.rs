@@ -0,0 +1,219 @@
+use apache_avro::schema::Schema;
+use apache_avro::types::Value;
+use apache_avro::Reader;
+use exchange_test::{ClientId, Record, TransactionId, TxType};
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::source::{record_fields, RecordSource, Row};
+
+// Reads an Avro object container file, mapping each record to a `Record` by field name,
+// whatever the writer schema. This tolerates schema evolution in both directions: fields
+// the engine does not know are ignored, and a missing `amount` field reads as no amount.
+// Records are numbered from 1 in place of line numbers.
+pub struct AvroSource {
+    reader: Reader<'static, Box<dyn Read>>,
+    // Scale of the `amount` field when the writer schema declares it as an Avro decimal
+    amount_scale: Option<u32>,
+    record: u64,
+    last: Option<Record>,
+}
+
+impl AvroSource {
+    pub fn new(input: Box<dyn Read>) -> Result<AvroSource, Box<dyn Error>> {
+        let reader = Reader::new(input)?;
+        let amount_scale = decimal_scale(reader.writer_schema(), "amount");
+        Ok(AvroSource {
+            reader,
+            amount_scale,
+            record: 0,
+            last: None,
+        })
+    }
+
+    fn to_record(&self, value: Value) -> Result<Record, String> {
+        let Value::Record(fields) = value else {
+            return Err("Expected an Avro record".to_string());
+        };
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| unwrap_union(value))
+                .filter(|value| **value != Value::Null)
+        };
+
+        let tx_type = match field("type") {
+            Some(Value::String(s) | Value::Enum(_, s)) => {
+                TxType::from_str(s).map_err(|e| e.to_string())?
+            }
+            _ => return Err("Missing or invalid field: type".to_string()),
+        };
+        let client = integer::<ClientId>(field("client"), "client")?;
+        let tx = integer::<TransactionId>(field("tx"), "tx")?;
+        let amount = match field("amount") {
+            None => None,
+            Some(value) => Some(self.amount(value)?),
+        };
+
+        Ok(Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        })
+    }
+
+    fn amount(&self, value: &Value) -> Result<Decimal, String> {
+        let invalid = |e: &dyn std::fmt::Display| format!("Invalid amount: {}", e);
+        match (value, self.amount_scale) {
+            (Value::String(s), _) => Decimal::from_str(s).map_err(|e| invalid(&e)),
+            (Value::Int(n), _) => Ok(Decimal::from(*n)),
+            (Value::Long(n), _) => Ok(Decimal::from(*n)),
+            // Floating point amounts are taken at their shortest round-tripping decimal form
+            (Value::Double(n), _) => Decimal::from_str(&n.to_string()).map_err(|e| invalid(&e)),
+            (Value::Decimal(d), Some(scale)) => {
+                let bytes = Vec::<u8>::try_from(d).map_err(|e| invalid(&e))?;
+                let unscaled = be_bytes_to_i128(&bytes).ok_or_else(|| invalid(&"too large"))?;
+                Decimal::try_from_i128_with_scale(unscaled, scale).map_err(|e| invalid(&e))
+            }
+            _ => Err(invalid(&"unsupported Avro type")),
+        }
+    }
+}
+
+impl RecordSource for AvroSource {
+    fn next_row(&mut self) -> Result<Option<(u64, Row)>, Box<dyn Error>> {
+        self.last = None;
+        // A decoding error means the container itself is damaged, so reading stops there
+        let Some(value) = self.reader.next().transpose()? else {
+            return Ok(None);
+        };
+        self.record += 1;
+
+        let row = match self.to_record(value) {
+            Ok(record) => {
+                self.last = Some(record);
+                Row::Parsed(record)
+            }
+            Err(reason) => Row::Invalid {
+                code: "invalid_record",
+                reason,
+            },
+        };
+        Ok(Some((self.record, row)))
+    }
+
+    fn raw_fields(&self) -> Vec<String> {
+        self.last.as_ref().map_or_else(Vec::new, record_fields)
+    }
+}
+
+// Optional fields are written as unions with null
+fn unwrap_union(value: &Value) -> &Value {
+    match value {
+        Value::Union(_, inner) => inner,
+        value => value,
+    }
+}
+
+fn integer<T: TryFrom<i64>>(value: Option<&Value>, name: &str) -> Result<T, String> {
+    let n = match value {
+        Some(Value::Int(n)) => i64::from(*n),
+        Some(Value::Long(n)) => *n,
+        _ => return Err(format!("Missing or invalid field: {}", name)),
+    };
+    T::try_from(n).map_err(|_| format!("Field {} is out of range: {}", name, n))
+}
+
+fn decimal_scale(schema: &Schema, field: &str) -> Option<u32> {
+    let Schema::Record(record) = schema else {
+        return None;
+    };
+    let field = record.fields.iter().find(|f| f.name == field)?;
+    let schemas = match &field.schema {
+        Schema::Union(union) => union.variants(),
+        schema => std::slice::from_ref(schema),
+    };
+    schemas.iter().find_map(|schema| match schema {
+        Schema::Decimal(decimal) => u32::try_from(decimal.scale).ok(),
+        _ => None,
+    })
+}
+
+// Sign-extends a big-endian two's complement integer of up to 16 bytes
+fn be_bytes_to_i128(bytes: &[u8]) -> Option<i128> {
+    if bytes.len() > 16 {
+        return None;
+    }
+    let fill = match bytes.first() {
+        Some(byte) if byte & 0x80 != 0 => 0xff,
+        _ => 0,
+    };
+    let mut buf = [fill; 16];
+    buf[16 - bytes.len()..].copy_from_slice(bytes);
+    Some(i128::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apache_avro::types::Record as AvroRecord;
+    use apache_avro::Writer;
+
+    #[test]
+    fn test_records_are_mapped_across_schema_versions() {
+        // A newer writer schema: decimal amounts, plus a field the engine does not know
+        let schema = Schema::parse_str(
+            r#"{
+                "type": "record",
+                "name": "Transaction",
+                "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "client", "type": "int"},
+                    {"name": "tx", "type": "long"},
+                    {"name": "amount", "type": ["null", {
+                        "type": "bytes", "logicalType": "decimal", "precision": 18, "scale": 4
+                    }]},
+                    {"name": "source", "type": "string"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut writer = Writer::new(&schema, Vec::new()).unwrap();
+        for (tx_type, amount) in [("deposit", Some(25_000)), ("dispute", None)] {
+            let mut record = AvroRecord::new(&schema).unwrap();
+            record.put("type", tx_type);
+            record.put("client", 1);
+            record.put("tx", 7i64);
+            let amount = match amount {
+                Some(unscaled) => Value::Union(
+                    1,
+                    Box::new(Value::Decimal(i64::to_be_bytes(unscaled).to_vec().into())),
+                ),
+                None => Value::Union(0, Box::new(Value::Null)),
+            };
+            record.put("amount", amount);
+            record.put("source", "kafka");
+            writer.append_value(record).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut source = AvroSource::new(Box::new(std::io::Cursor::new(data))).unwrap();
+        let Some((1, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+            panic!("expected a deposit as record 1");
+        };
+        assert_eq!(deposit.amount, Some(Decimal::new(25_000, 4)));
+        let Some((2, Row::Parsed(dispute))) = source.next_row().unwrap() else {
+            panic!("expected a dispute as record 2");
+        };
+        assert_eq!(
+            (dispute.tx_type, dispute.tx, dispute.amount),
+            (TxType::Dispute, 7, None)
+        );
+        assert!(source.next_row().unwrap().is_none());
+    }
+}
@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::cli::CompareArgs;
+
+// The parts of a criterion `estimates.json` the comparison needs; times are in nanoseconds
+#[derive(Debug, Deserialize)]
+struct Estimates {
+    mean: Estimate,
+}
+
+#[derive(Debug, Deserialize)]
+struct Estimate {
+    point_estimate: f64,
+}
+
+fn read_mean(path: &Path) -> Result<f64, Box<dyn Error>> {
+    let estimates: Estimates =
+        serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(|e| {
+            format!(
+                "{} is not a criterion estimates.json: {}",
+                path.display(),
+                e
+            )
+        })?;
+    Ok(estimates.mean.point_estimate)
+}
+
+// Throughput change of `current` relative to `baseline`, in percent; negative is slower
+fn throughput_change(baseline_ns: f64, current_ns: f64) -> f64 {
+    (baseline_ns / current_ns - 1.0) * 100.0
+}
+
+// Prints how the mean iteration time moved and fails if throughput dropped by more than the
+// allowed percentage
+pub fn compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    let baseline = read_mean(&args.baseline)?;
+    let current = read_mean(&args.current)?;
+    let change = throughput_change(baseline, current);
+
+    println!("mean time: {:.1} ns -> {:.1} ns", baseline, current);
+    println!("throughput: {:+.2}%", change);
+    if -change > args.max_regression {
+        return Err(format!(
+            "throughput regressed by {:.2}%, over the allowed {}%",
+            -change, args.max_regression
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use tempfile::NamedTempFile;
+
+    fn estimates(mean_ns: f64) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"{{"mean":{{"confidence_interval":{{"confidence_level":0.95,"lower_bound":0.0,"upper_bound":0.0}},"point_estimate":{},"standard_error":1.0}},"median":{{"point_estimate":0.0}}}}"#,
+            mean_ns
+        )
+        .unwrap();
+        file
+    }
+
+    #[test]
+    fn test_regressions_beyond_the_threshold_fail() {
+        let baseline = estimates(1000.0);
+        let args = |current: &NamedTempFile| CompareArgs {
+            baseline: baseline.path().to_path_buf(),
+            current: current.path().to_path_buf(),
+            max_regression: 5.0,
+        };
+
+        // About 4.8% less throughput is within the threshold, 9.1% is not
+        assert!(compare(&args(&estimates(1050.0))).is_ok());
+        let err = compare(&args(&estimates(1100.0))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "throughput regressed by 9.09%, over the allowed 5%"
+        );
+        assert!(compare(&CompareArgs {
+            current: PathBuf::from("Cargo.toml"),
+            ..args(&baseline)
+        })
+        .is_err());
+    }
+}
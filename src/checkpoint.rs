@@ -0,0 +1,259 @@
+use bincode::{Decode, Encode};
+use exchange_test::{EngineConfig, MemoryStorage, PaymentsEngine};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::output::write_atomically;
+use crate::source::{RecordSource, ResumePoint};
+use crate::wal::WalWriter;
+
+// Checkpoint files start with this magic and a format version, followed by the position
+// and then the engine state in the --save-state format
+const MAGIC: &[u8; 4] = b"PECK";
+const VERSION: u32 = 2;
+
+#[derive(Encode, Decode)]
+struct Position {
+    // The inputs of the run after glob expansion, so a checkpoint is not resumed against other files
+    inputs: Vec<String>,
+    input: u64,
+    line: u64,
+    byte: u64,
+    // The length of the run's --wal, if it had one
+    wal: Option<u64>,
+}
+
+// The position of version 1 checkpoints, which predate the --wal length
+#[derive(Decode)]
+struct PositionV1 {
+    inputs: Vec<String>,
+    input: u64,
+    line: u64,
+    byte: u64,
+}
+
+// How a run reads one of its inputs
+pub enum Start {
+    Beginning,
+    At(ResumePoint),
+    // Already processed before the checkpoint the run resumed from
+    Skip,
+}
+
+// The checkpoints of a run over engines with storage `S`. Only the in-memory storage can be
+// saved in a checkpoint, so Checkpoints implement this for it alone and runs over other
+// storages cannot be given any.
+pub trait Checkpointing<S> {
+    // How the run reads its `input`-th input
+    fn start(&self, input: usize) -> Start;
+
+    // Opens the --wal of the run; a resumed run continues the log of the run it resumes
+    fn open_wal(&self, path: &Path) -> Result<WalWriter, Box<dyn Error>>;
+
+    // Counts a processed row of the `input`-th input, writing a checkpoint once one is due
+    // and the source can say where to resume
+    fn row_done(
+        &mut self,
+        input: usize,
+        source: &dyn RecordSource,
+        engine: &PaymentsEngine<S>,
+        wal: Option<&WalWriter>,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+// Periodic checkpoints of a run over in-memory storage, and the position it resumed from
+pub struct Checkpoints {
+    inputs: Vec<String>,
+    target: Option<(PathBuf, u64)>,
+    since: u64,
+    // The input index and point within it the run resumed from
+    resumed: Option<(usize, ResumePoint)>,
+    // The --wal length recorded in the checkpoint the run resumed from
+    resumed_wal: Option<u64>,
+}
+
+impl Checkpoints {
+    // Writes a checkpoint to `path` every `every` rows, if given
+    pub fn new(inputs: &[PathBuf], target: Option<(PathBuf, u64)>) -> Checkpoints {
+        Checkpoints {
+            inputs: inputs.iter().map(|i| i.display().to_string()).collect(),
+            target,
+            since: 0,
+            resumed: None,
+            resumed_wal: None,
+        }
+    }
+
+    // Loads the engine saved in a checkpoint; the run then picks up at its position
+    pub fn resume(
+        &mut self,
+        path: &Path,
+        config: EngineConfig,
+    ) -> Result<PaymentsEngine, Box<dyn Error>> {
+        let (position, engine) = read_checkpoint(path, config)?;
+        if position.inputs != self.inputs {
+            return Err(format!(
+                "Checkpoint {} was taken over the inputs {}",
+                path.display(),
+                position.inputs.join(" ")
+            )
+            .into());
+        }
+        let point = ResumePoint {
+            line: position.line,
+            byte: position.byte,
+        };
+        self.resumed = Some((position.input as usize, point));
+        self.resumed_wal = position.wal;
+        Ok(engine)
+    }
+}
+
+// Reads a checkpoint's position and the engine saved after it
+fn read_checkpoint(
+    path: &Path,
+    config: EngineConfig,
+) -> Result<(Position, PaymentsEngine), Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(format!("{} is not a checkpoint file", path.display()).into());
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let encoding = bincode::config::standard();
+    let position: Position = match version {
+        1 => {
+            let v1: PositionV1 = bincode::decode_from_std_read(&mut reader, encoding)?;
+            Position {
+                inputs: v1.inputs,
+                input: v1.input,
+                line: v1.line,
+                byte: v1.byte,
+                wal: None,
+            }
+        }
+        VERSION => bincode::decode_from_std_read(&mut reader, encoding)?,
+        _ => return Err(format!("Unsupported checkpoint version {}", version).into()),
+    };
+    Ok((position, PaymentsEngine::load_state(reader, config)?))
+}
+
+// The entries of a --wal written after a checkpoint, which `recover` replays on top of the
+// engine saved in it rather than replaying the whole log
+pub struct WalTail {
+    point: ResumePoint,
+}
+
+impl WalTail {
+    // Loads the engine saved in `checkpoint`, with where the entries it does not hold begin
+    // in the log at `wal`
+    pub fn from_checkpoint(
+        checkpoint: &Path,
+        wal: &Path,
+        config: EngineConfig,
+    ) -> Result<(PaymentsEngine, WalTail), Box<dyn Error>> {
+        let (position, engine) = read_checkpoint(checkpoint, config)?;
+        let Some(len) = position.wal else {
+            return Err(
+                "the checkpoint was taken without --wal, so it has no place in the log".into(),
+            );
+        };
+        // Log entries are single lines, so the lines before the tail are its newlines
+        let mut head = BufReader::new(File::open(wal)?).take(len);
+        let (mut line, mut byte) = (0, 0);
+        let mut buf = Vec::new();
+        while head.read_until(b'\n', &mut buf)? > 0 {
+            line += 1;
+            byte += buf.len() as u64;
+            buf.clear();
+        }
+        if byte < len {
+            return Err(format!(
+                "{} is shorter than when the checkpoint was taken",
+                wal.display()
+            )
+            .into());
+        }
+        let point = ResumePoint { line, byte };
+        Ok((engine, WalTail { point }))
+    }
+}
+
+impl Checkpointing<MemoryStorage> for WalTail {
+    fn start(&self, _: usize) -> Start {
+        Start::At(self.point)
+    }
+
+    fn open_wal(&self, _: &Path) -> Result<WalWriter, Box<dyn Error>> {
+        Err("recovery does not write a log".into())
+    }
+
+    fn row_done(
+        &mut self,
+        _: usize,
+        _: &dyn RecordSource,
+        _: &PaymentsEngine,
+        _: Option<&WalWriter>,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl Checkpointing<MemoryStorage> for Checkpoints {
+    fn open_wal(&self, path: &Path) -> Result<WalWriter, Box<dyn Error>> {
+        match (self.resumed, self.resumed_wal) {
+            (None, _) => WalWriter::create(path),
+            (Some(_), Some(len)) => WalWriter::resume(path, len),
+            (Some(_), None) => {
+                Err("the checkpoint was taken without --wal, so there is no log to continue".into())
+            }
+        }
+    }
+
+    fn start(&self, input: usize) -> Start {
+        match self.resumed {
+            Some((resumed, _)) if input < resumed => Start::Skip,
+            Some((resumed, point)) if input == resumed => Start::At(point),
+            _ => Start::Beginning,
+        }
+    }
+
+    fn row_done(
+        &mut self,
+        input: usize,
+        source: &dyn RecordSource,
+        engine: &PaymentsEngine,
+        wal: Option<&WalWriter>,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some((path, every)) = &self.target else {
+            return Ok(());
+        };
+        self.since += 1;
+        if self.since < *every {
+            return Ok(());
+        }
+        let Some(point) = source.resume_point() else {
+            return Ok(());
+        };
+
+        let position = Position {
+            inputs: self.inputs.clone(),
+            input: input as u64,
+            line: point.line,
+            byte: point.byte,
+            wal: wal.map(WalWriter::position).transpose()?,
+        };
+        write_atomically(path, |file| {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&VERSION.to_le_bytes())?;
+            bincode::encode_into_std_write(position, &mut writer, bincode::config::standard())?;
+            Ok(engine.save_state(writer)?)
+        })?;
+        self.since = 0;
+        Ok(())
+    }
+}
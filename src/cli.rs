@@ -0,0 +1,735 @@
+use clap::{Args, Parser, Subcommand};
+use exchange_test::{
+    ClientId, DedupWindow, EngineConfig, ExcessPrecision, LockedPolicy, MaxAmount, TransactionId,
+    TxType, Validators, ZeroAmountPolicy, STATE_VERSION,
+};
+use log::LevelFilter;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::enrich::{CurrencyCode, Enrichers, MemoCategory, RiskScore};
+use crate::input::{DEFAULT_MAX_FIELD_SIZE, DEFAULT_MAX_LINE_LENGTH};
+use crate::output::OutputFormat;
+use crate::routes::Routes;
+use crate::sample::parse_sample_rate;
+use crate::source::InputFormat;
+
+// Command line interface. Running without a subcommand behaves like `process`, so the
+// original `cargo run -- transactions.csv > accounts.csv` invocation keeps working.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Toy payments engine",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Transactions files or glob patterns to process in order; reads stdin when omitted or "-"
+    pub inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub options: ProcessOptions,
+
+    /// Logging level for diagnostics written to stderr (off, error, warn, info, debug, trace)
+    #[arg(long, global = true, default_value = "warn")]
+    pub log_level: LevelFilter,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Process transactions and output the final account states
+    Process(Box<ProcessArgs>),
+    /// Process transactions and report rejected rows without emitting account states
+    Validate(ValidateArgs),
+    /// Re-run historical transactions with corrections applied and diff the outcome
+    Replay(ReplayArgs),
+    /// Generate a synthetic transactions CSV
+    Generate(GenerateArgs),
+    /// Summarize an accounts CSV produced by `process`
+    Report(ReportArgs),
+    /// Compare two accounts CSVs or saved engine states and report the per-client changes
+    Diff(DiffArgs),
+    /// Merge a duplicate client's account into another in a saved engine state
+    MergeAccounts(MergeArgs),
+    /// Rebuild the state of an interrupted run from its starting state and write-ahead log
+    Recover(RecoverArgs),
+    /// Work with engine state files written by --save-state
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Inspect transaction files
+    #[command(subcommand)]
+    Tx(TxCommand),
+    /// Work with the event journal written by --journal
+    #[command(subcommand)]
+    Journal(JournalCommand),
+    /// Work with benchmark results
+    #[command(subcommand)]
+    Bench(BenchCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BenchCommand {
+    /// Compare two criterion estimates.json files; fails if throughput regressed too far
+    Compare(CompareArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JournalCommand {
+    /// Rebuild the account states from the journal alone and output them
+    Replay(JournalReplayArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Verify a state file and print its format version, checksum and contents as JSON
+    Inspect(InspectArgs),
+    /// Rewrite a state file from an older format version in a newer one
+    Migrate(MigrateArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TxCommand {
+    /// Print the transactions matching all of the given filters
+    Search(SearchArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ProcessArgs {
+    /// Transactions files or glob patterns to process in order, or "-" for stdin
+    #[arg(default_value = "-")]
+    pub inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub options: ProcessOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct ProcessOptions {
+    #[command(flatten)]
+    pub input_args: InputArgs,
+
+    /// Write account states to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Encoding of the account states
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Also write the disputed_held, disputed_lifetime and withdrawable columns after the
+    /// standard five
+    #[arg(long)]
+    pub extended_output: bool,
+
+    /// Write the accounts matching a `rule,output` row of this CSV to that row's file
+    /// instead, by the first matching rule; rules are `locked`, `unlocked` and
+    /// `clients:LOW-HIGH`
+    #[arg(long, value_name = "CSV", value_parser = parse_routes)]
+    pub routes: Option<Routes>,
+
+    /// Also upsert the final accounts into the `accounts` table of this postgres:// database;
+    /// requires the `postgres` feature
+    #[arg(long, value_name = "URL", value_parser = parse_sink)]
+    pub sink: Option<String>,
+
+    /// Write every rejected row, with its line number and reason code, to this CSV file
+    #[arg(long)]
+    pub rejects: Option<PathBuf>,
+
+    /// Write an end-of-run JSON summary to this file, or "-" for stderr
+    #[arg(long)]
+    pub summary: Option<PathBuf>,
+
+    /// Abort on the first transaction that fails to process
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Fraction ("0.001") or percentage ("0.1%") of applied transactions to write to --sample-out
+    #[arg(long, value_parser = parse_sample_rate, requires = "sample_out")]
+    pub sample: Option<f64>,
+
+    /// Write the sampled transactions, with the balances they left, to this CSV file
+    #[arg(long, requires = "sample")]
+    pub sample_out: Option<PathBuf>,
+
+    /// Seed for choosing the sample; the same seed and input always give the same sample
+    #[arg(long, default_value_t = 0)]
+    pub sample_seed: u64,
+
+    /// Start from the engine state saved by an earlier run's --save-state
+    #[arg(long, value_name = "PATH")]
+    pub load_state: Option<PathBuf>,
+
+    /// Start from the balances in an accounts CSV written by an earlier run
+    #[arg(long, value_name = "CSV", conflicts_with = "load_state")]
+    pub initial_accounts: Option<PathBuf>,
+
+    /// CSV of client id aliases (alias,client); transactions for an alias apply to the client
+    #[arg(long, value_name = "CSV")]
+    pub aliases: Option<PathBuf>,
+
+    /// Write audit events, such as transactions applied through an alias, as JSON lines
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Keep the transaction history in a temporary sled database under this directory
+    /// instead of in memory; requires the `sled` feature
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["load_state", "save_state", "initial_accounts", "checkpoint", "resume"]
+    )]
+    pub sled_dir: Option<PathBuf>,
+
+    /// Persist accounts, transactions and open disputes in this SQLite database, committing
+    /// after each input, and start from what it holds; requires the `sqlite` feature
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "load_state", "save_state", "initial_accounts", "sled_dir", "checkpoint", "resume"
+        ]
+    )]
+    pub db: Option<PathBuf>,
+
+    /// Keep at most N recent transactions in memory for duplicate and dispute lookups,
+    /// moving older ones to the --sled-dir or --db storage
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub dedup_window: Option<u64>,
+
+    /// Move transactions older than this many seconds from memory to the --sled-dir or --db
+    /// storage, where replays of them are still caught
+    #[arg(long, value_name = "SECONDS")]
+    pub dedup_ttl: Option<u64>,
+
+    /// Read the inputs twice: first for the ids of disputed transactions, then to process
+    /// them storing the amounts of only those transactions and the ids of the others
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "load_state", "save_state", "initial_accounts", "checkpoint", "resume", "sled_dir",
+            "db", "dedup_window", "dedup_ttl", "memory_budget"
+        ]
+    )]
+    pub two_pass: bool,
+
+    /// Write each client's end-of-day balances to this CSV, for every day its account
+    /// changed; needs inputs in date order with a `timestamp` column of ISO 8601 dates
+    #[arg(long, value_name = "PATH")]
+    pub balance_series: Option<PathBuf>,
+
+    /// Keep about this many MiB of recent transactions in memory and spill older ones to a
+    /// temporary file indexed by transaction id, read back when disputed
+    #[arg(
+        long,
+        value_name = "MIB",
+        // Bounded so the budget in bytes fits a u64
+        value_parser = clap::value_parser!(u64).range(1..=u64::MAX >> 20),
+        conflicts_with_all = [
+            "load_state", "save_state", "initial_accounts", "checkpoint", "resume", "sled_dir",
+            "db", "dedup_window", "dedup_ttl"
+        ]
+    )]
+    pub memory_budget: Option<u64>,
+
+    /// Directory for the --memory-budget spill file instead of the system temp directory
+    #[arg(long, value_name = "DIR", requires = "memory_budget")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Save the engine state after processing so a later run can continue from it
+    #[arg(long, value_name = "PATH")]
+    pub save_state: Option<PathBuf>,
+
+    /// Log every transaction to this file before applying it, so `recover` can rebuild the
+    /// state of a run that died part way through. Every entry is synced to disk, which makes
+    /// the run much slower; a run resumed with --resume continues the log
+    #[arg(long, value_name = "PATH")]
+    pub wal: Option<PathBuf>,
+
+    /// Annotate records with a `category` from the first `pattern,category` rule in this CSV
+    /// whose pattern occurs in their `memo` column
+    #[arg(long, value_name = "CSV")]
+    pub categories: Option<PathBuf>,
+
+    /// Annotate deposits and withdrawals with a `risk_score`, reaching 100 at this amount
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_positive_amount)]
+    pub risk_threshold: Option<Decimal>,
+
+    /// Annotate records with their `currency` column as an upper-case currency code
+    #[arg(long)]
+    pub normalize_currency: bool,
+
+    /// Append every state transition applied to an account to this JSON lines journal
+    #[arg(long, value_name = "PATH")]
+    pub journal: Option<PathBuf>,
+
+    /// Log every transaction that takes at least this many milliseconds to apply, and count
+    /// them per type in the summary
+    #[arg(long, value_name = "MS")]
+    pub slow_tx_ms: Option<u64>,
+
+    /// Every N rows, write the engine state and the position in the input to --checkpoint
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "checkpoint",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub checkpoint_every: Option<u64>,
+
+    /// Checkpoint file kept up to date by --checkpoint-every
+    #[arg(long, value_name = "PATH", requires = "checkpoint_every")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Continue an interrupted run over the same inputs from its last checkpoint; side outputs
+    /// such as --rejects and --summary only cover the rows after it
+    #[arg(
+        long,
+        value_name = "CHECKPOINT",
+        conflicts_with_all = ["load_state", "initial_accounts"]
+    )]
+    pub resume: Option<PathBuf>,
+
+    /// Apply the records on N threads, each owning the accounts of the clients whose id
+    /// modulo N is its index. Every client's records keep their order; a run in which clients
+    /// of different shards use the same transaction id fails, as it could not be checked for
+    /// duplicates and disputes like a serial run
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with_all = [
+            "rejects", "summary", "sample", "load_state", "aliases", "audit_log", "sled_dir",
+            "db", "save_state", "wal", "categories", "risk_threshold", "normalize_currency",
+            "journal", "checkpoint", "resume", "slow_tx_ms", "memory_budget",
+            "balance_series", "two_pass"
+        ]
+    )]
+    pub shards: Option<u16>,
+
+    /// Parse the inputs of a --shards run on N threads. With more threads than inputs, each
+    /// uncompressed CSV file is split into runs of whole records parsed side by side. Every
+    /// shard still applies the records in input order, so the result is that of a serial run.
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "shards",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub parse_threads: Option<u16>,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Transactions files or glob patterns to validate in order, or "-" for stdin
+    #[arg(default_value = "-")]
+    pub inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub input_args: InputArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// Transactions files or glob patterns to replay in order, or "-" for stdin
+    #[arg(default_value = "-")]
+    pub inputs: Vec<PathBuf>,
+
+    /// CSV of corrections (action,type,client,tx,amount) replacing or voiding transactions
+    #[arg(long = "override", value_name = "CORRECTIONS")]
+    pub corrections: PathBuf,
+
+    /// Write the corrected account states to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Encoding of the corrected account states
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Also write the disputed_held, disputed_lifetime and withdrawable columns after the
+    /// standard five
+    #[arg(long)]
+    pub extended_output: bool,
+
+    /// Write the accounts whose state the corrections changed to this CSV file, or "-" for stderr
+    #[arg(long, default_value = "-")]
+    pub diff: PathBuf,
+
+    #[command(flatten)]
+    pub input_args: InputArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /// Number of transaction rows to generate
+    #[arg(long, default_value_t = 1000)]
+    pub transactions: usize,
+
+    /// Number of distinct clients
+    #[arg(long, default_value_t = 100)]
+    pub clients: u16,
+
+    /// Seed for the random generator; the same seed always yields the same file
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Write the generated CSV to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// Accounts CSV to summarize
+    pub accounts: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Earlier accounts CSV, or engine state written by --save-state
+    pub before: PathBuf,
+
+    /// Later accounts CSV, or engine state written by --save-state
+    pub after: PathBuf,
+
+    /// Write the differences to this CSV file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Engine state written by --save-state; it is updated in place
+    #[arg(long, value_name = "PATH")]
+    pub state: PathBuf,
+
+    /// Client whose account is merged away; its id becomes an alias
+    pub from: ClientId,
+
+    /// Client whose account survives
+    pub into: ClientId,
+
+    /// Append the merge to this event journal
+    #[arg(long, value_name = "PATH")]
+    pub journal: Option<PathBuf>,
+
+    /// Overdraft limit to count in the withdrawable funds of the audit record
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_positive_amount)]
+    pub overdraft_limit: Option<Decimal>,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// Engine state written by --save-state, by this or an earlier version
+    pub state: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    /// State file to migrate
+    pub input: PathBuf,
+
+    /// Where to write the migrated state; may be the input itself
+    pub output: PathBuf,
+
+    /// Format version the input is expected to be in, e.g. "v1"
+    #[arg(long, value_parser = parse_state_version)]
+    pub from: u32,
+
+    /// Format version to write; defaults to the newest this build supports
+    #[arg(long, value_parser = parse_state_version, default_value_t = STATE_VERSION)]
+    pub to: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct RecoverArgs {
+    /// Write-ahead log written by the run's --wal
+    pub wal: PathBuf,
+
+    /// Engine state the run started from, if it was given --load-state
+    #[arg(long, value_name = "PATH")]
+    pub load_state: Option<PathBuf>,
+
+    /// Accounts CSV the run started from, if it was given --initial-accounts
+    #[arg(long, value_name = "CSV", conflicts_with = "load_state")]
+    pub initial_accounts: Option<PathBuf>,
+
+    /// Client id aliases the run was given
+    #[arg(long, value_name = "CSV")]
+    pub aliases: Option<PathBuf>,
+
+    /// Last checkpoint the run wrote; only the log entries after it are replayed, on top of
+    /// the state saved in it
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["load_state", "initial_accounts", "aliases"]
+    )]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Write the recovered account states to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Encoding of the recovered account states
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Also write the disputed_held, disputed_lifetime and withdrawable columns after the
+    /// standard five
+    #[arg(long)]
+    pub extended_output: bool,
+
+    /// Save the recovered engine state, to continue with the rest of the input from it
+    #[arg(long, value_name = "PATH")]
+    pub save_state: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct JournalReplayArgs {
+    /// Journal written by --journal
+    pub journal: PathBuf,
+
+    /// Write the account states to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Encoding of the account states
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Also write the disputed_held, disputed_lifetime and withdrawable columns after the
+    /// standard five
+    #[arg(long)]
+    pub extended_output: bool,
+
+    /// Overdraft limit the journaled run was given
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_positive_amount)]
+    pub overdraft_limit: Option<Decimal>,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    /// estimates.json of the baseline run
+    pub baseline: PathBuf,
+
+    /// estimates.json of the run to check
+    pub current: PathBuf,
+
+    /// Largest allowed drop in throughput, in percent
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.0)]
+    pub max_regression: f64,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Transactions CSV to search, or "-" for stdin
+    #[arg(default_value = "-")]
+    pub input: PathBuf,
+
+    #[command(flatten)]
+    pub input_args: InputArgs,
+
+    /// Only transactions for this client
+    #[arg(long)]
+    pub client: Option<ClientId>,
+
+    /// Only rows referencing this transaction id (including its disputes)
+    #[arg(long)]
+    pub tx: Option<TransactionId>,
+
+    /// Only transactions of this type
+    #[arg(long = "type")]
+    pub tx_type: Option<TxType>,
+
+    /// Only transactions with at least this amount
+    #[arg(long)]
+    pub min_amount: Option<Decimal>,
+
+    /// Only transactions with at most this amount
+    #[arg(long)]
+    pub max_amount: Option<Decimal>,
+}
+
+// Options that shape how the engine treats incoming transactions
+#[derive(Debug, Default, Args)]
+pub struct EngineArgs {
+    /// How to treat amounts with more than four decimal places (reject, round, truncate)
+    #[arg(long, default_value = "reject")]
+    pub excess_precision: ExcessPrecision,
+
+    /// What to do with deposits and resolves for locked accounts (reject, queue); queued
+    /// transactions never applied are reported at the end of the run
+    #[arg(long, default_value = "reject")]
+    pub locked_accounts: LockedPolicy,
+
+    /// What to do with zero-amount deposits and withdrawals (reject; noop, recording them
+    /// without opening an account; open-account, letting a zero deposit open one as usual)
+    #[arg(long, default_value = "open-account")]
+    pub zero_amounts: ZeroAmountPolicy,
+
+    /// Reject deposits and withdrawals above this amount
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_amount: Option<Decimal>,
+
+    /// Hold back this much of every account as a risk reserve, out of its available funds
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_positive_amount)]
+    pub reserve: Option<Decimal>,
+
+    /// Let withdrawals take an account's available funds down to this far below zero
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_positive_amount)]
+    pub overdraft_limit: Option<Decimal>,
+}
+
+#[derive(Debug, Args)]
+pub struct InputArgs {
+    /// Encoding of the input
+    #[arg(long, value_enum, default_value_t)]
+    pub input_format: InputFormat,
+
+    /// CSV field delimiter: a single ASCII character, or "tab"
+    #[arg(long, value_parser = parse_delimiter, default_value = ",")]
+    pub delimiter: u8,
+
+    /// Abort if any input line or protobuf message is longer than this many bytes; a quoted
+    /// field still open past this many bytes (or 32 lines) is quarantined as a stray quote
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_LINE_LENGTH)]
+    pub max_line_length: u64,
+
+    /// Reject CSV rows with a field longer than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_FIELD_SIZE)]
+    pub max_field_size: usize,
+
+    /// Memory-map input files instead of reading them through a buffer, which is faster on
+    /// large local files; stdin and pipes are read as usual. Requires the `mmap` feature.
+    #[arg(long)]
+    pub mmap: bool,
+}
+
+impl Default for InputArgs {
+    fn default() -> InputArgs {
+        InputArgs {
+            input_format: InputFormat::default(),
+            delimiter: b',',
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            max_field_size: DEFAULT_MAX_FIELD_SIZE,
+            mmap: false,
+        }
+    }
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ => match value.as_bytes() {
+            [byte] if byte.is_ascii() && !matches!(byte, b'"' | b'\n' | b'\r') => Ok(*byte),
+            _ => Err(format!(
+                "expected a single ASCII character, got {:?}",
+                value
+            )),
+        },
+    }
+}
+
+fn parse_state_version(value: &str) -> Result<u32, String> {
+    let version = value.strip_prefix('v').unwrap_or(value);
+    match version.parse() {
+        Ok(version) if (1..=STATE_VERSION).contains(&version) => Ok(version),
+        _ => Err(format!("expected a version from v1 to v{STATE_VERSION}")),
+    }
+}
+
+fn parse_routes(value: &str) -> Result<Routes, String> {
+    Routes::from_path(Path::new(value)).map_err(|e| e.to_string())
+}
+
+fn parse_sink(value: &str) -> Result<String, String> {
+    if value.starts_with("postgres://") || value.starts_with("postgresql://") {
+        Ok(value.to_string())
+    } else {
+        Err("only postgres:// URLs are supported".to_string())
+    }
+}
+
+impl EngineArgs {
+    pub fn config(&self) -> EngineConfig {
+        let mut validators = Validators::default();
+        if let Some(limit) = self.max_amount {
+            validators.push(MaxAmount(limit));
+        }
+        EngineConfig {
+            excess_precision: self.excess_precision,
+            locked_accounts: self.locked_accounts,
+            zero_amounts: self.zero_amounts,
+            validators,
+            reserve: self.reserve.unwrap_or_default(),
+            overdraft_limit: self.overdraft_limit.unwrap_or_default(),
+            journal: false,
+        }
+    }
+}
+
+fn parse_positive_amount(s: &str) -> Result<Decimal, String> {
+    match s.parse::<Decimal>() {
+        Ok(amount) if amount > Decimal::ZERO => Ok(amount),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl ProcessOptions {
+    // The enrichment stage the options ask for, which may be empty
+    pub fn enrichers(&self) -> Result<Enrichers, Box<dyn Error>> {
+        let mut enrichers = Enrichers::default();
+        if let Some(path) = &self.categories {
+            enrichers.push(MemoCategory::from_path(path)?);
+        }
+        if let Some(threshold) = self.risk_threshold {
+            enrichers.push(RiskScore { threshold });
+        }
+        if self.normalize_currency {
+            enrichers.push(CurrencyCode);
+        }
+        Ok(enrichers)
+    }
+
+    // The engine config, recording journal events when there is a journal to write them to
+    pub fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            journal: self.journal.is_some(),
+            ..self.engine.config()
+        }
+    }
+
+    // The bounds on the in-memory transaction window, if any were given
+    pub fn dedup_window(&self) -> Option<DedupWindow> {
+        if self.dedup_window.is_none() && self.dedup_ttl.is_none() {
+            return None;
+        }
+        Some(DedupWindow {
+            max_entries: self.dedup_window.map(|n| n as usize),
+            ttl: self.dedup_ttl.map(Duration::from_secs),
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use exchange_test::{Account, ClientId};
+use parquet::data_type::{BoolType, FixedLenByteArray, FixedLenByteArrayType, Int32Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::output::AmountColumn;
+
+// The columns up to `locked`; the amount columns follow it. Amounts are stored as 16-byte
+// two's complement DECIMAL(38, 4), which holds any rust_decimal value at the engine's four
+// decimal places.
+const SCHEMA: &str = "
+    required int32 client (INTEGER(16, false));
+    required fixed_len_byte_array(16) available (DECIMAL(38, 4));
+    required fixed_len_byte_array(16) held (DECIMAL(38, 4));
+    required fixed_len_byte_array(16) total (DECIMAL(38, 4));
+    required boolean locked;
+";
+
+// Writes the accounts, already in output order, as a single row group with the extra
+// amount columns after `locked`
+pub fn write_accounts_parquet<W: Write + Send>(
+    rows: &[(&ClientId, &Account)],
+    extra_columns: &[AmountColumn],
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut message = format!("message accounts {{{}", SCHEMA);
+    for (name, _) in extra_columns {
+        message += &format!(
+            "    required fixed_len_byte_array(16) {} (DECIMAL(38, 4));\n",
+            name
+        );
+    }
+    message += "}";
+    let schema = Arc::new(parse_message_type(&message)?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut file = SerializedFileWriter::new(writer, schema, props)?;
+
+    let clients: Vec<i32> = rows.iter().map(|(client, _)| i32::from(**client)).collect();
+    let locked: Vec<bool> = rows.iter().map(|(_, account)| account.locked).collect();
+    let amounts = |amount: &dyn Fn(&Account) -> Decimal| -> Vec<FixedLenByteArray> {
+        rows.iter()
+            .map(|(_, account)| decimal_bytes(amount(account)))
+            .collect()
+    };
+
+    let mut group = file.next_row_group()?;
+    let mut columns = 0;
+    while let Some(mut column) = group.next_column()? {
+        match columns {
+            0 => {
+                column
+                    .typed::<Int32Type>()
+                    .write_batch(&clients, None, None)?;
+            }
+            4 => {
+                column
+                    .typed::<BoolType>()
+                    .write_batch(&locked, None, None)?;
+            }
+            n => {
+                let values = match n {
+                    1 => amounts(&|a| a.available),
+                    2 => amounts(&Account::held),
+                    3 => amounts(&|a| a.total),
+                    n => amounts(&extra_columns[n - 5].1),
+                };
+                column
+                    .typed::<FixedLenByteArrayType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column.close()?;
+        columns += 1;
+    }
+    group.close()?;
+    file.close()?;
+    Ok(())
+}
+
+// Encodes an amount as the big-endian unscaled value of a scale-4 decimal
+fn decimal_bytes(amount: Decimal) -> FixedLenByteArray {
+    let mut amount = amount.round_dp(4);
+    amount.rescale(4);
+    amount.mantissa().to_be_bytes().to_vec().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::{PaymentsEngine, Record, TxType};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_accounts_round_trip_through_parquet() {
+        let mut engine = PaymentsEngine::new();
+        engine
+            .process(&Record {
+                tx_type: TxType::Deposit,
+                client: 3,
+                tx: 1,
+                amount: Some(Decimal::new(15, 1)),
+            })
+            .unwrap();
+        let accounts = engine.finalize();
+        let rows: Vec<_> = accounts.iter().collect();
+
+        let mut output = tempfile::tempfile().unwrap();
+        write_accounts_parquet(
+            &rows,
+            &crate::output::extended_columns(Decimal::ZERO),
+            &mut output,
+        )
+        .unwrap();
+
+        let reader = SerializedFileReader::new(output).unwrap();
+        let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(
+            row.to_string(),
+            "{client: 3, available: 1.5000, held: 0.0000, total: 1.5000, locked: false, \
+             disputed_held: 0.0000, disputed_lifetime: 0.0000, withdrawable: 1.5000}"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+use crate::{Record, Validators};
+
+// Maximum number of decimal places an amount may carry
+pub const MAX_PRECISION: u32 = 4;
+
+// What to do with amounts that carry more decimal places than MAX_PRECISION
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExcessPrecision {
+    #[default]
+    Reject,
+    // Banker's rounding (half to even) to MAX_PRECISION places
+    Round,
+    // Drops the extra digits, rounding towards zero
+    Truncate,
+}
+
+impl ExcessPrecision {
+    // Normalizes the record's amount according to the policy. Rejection is left to the
+    // deposit/withdrawal validation so the error names the offending transaction type.
+    pub(crate) fn apply(self, record: &Record) -> Record {
+        let mut record = *record;
+        if let Some(amount) = record.amount.filter(|a| a.scale() > MAX_PRECISION) {
+            record.amount = match self {
+                ExcessPrecision::Reject => Some(amount),
+                ExcessPrecision::Round => Some(amount.round_dp(MAX_PRECISION)),
+                ExcessPrecision::Truncate => Some(amount.trunc_with_scale(MAX_PRECISION)),
+            };
+        }
+        record
+    }
+}
+
+impl FromStr for ExcessPrecision {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(ExcessPrecision::Reject),
+            "round" => Ok(ExcessPrecision::Round),
+            "truncate" => Ok(ExcessPrecision::Truncate),
+            _ => Err("Unknown excess precision mode, expected reject|round|truncate"),
+        }
+    }
+}
+
+// What to do with deposits and resolves for a locked account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockedPolicy {
+    #[default]
+    Reject,
+    // Holds them back, in arrival order, until the account is unlocked
+    Queue,
+}
+
+impl FromStr for LockedPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(LockedPolicy::Reject),
+            "queue" => Ok(LockedPolicy::Queue),
+            _ => Err("Unknown locked account policy, expected reject|queue"),
+        }
+    }
+}
+
+// What to do with deposits and withdrawals of exactly zero, which some feeds send to signal
+// that an account was touched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZeroAmountPolicy {
+    Reject,
+    // Accepts them into the transaction history without opening an account
+    Noop,
+    // Accepts them, a zero deposit opening the account of a new client as any deposit would
+    #[default]
+    OpenAccount,
+}
+
+impl FromStr for ZeroAmountPolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(ZeroAmountPolicy::Reject),
+            "noop" => Ok(ZeroAmountPolicy::Noop),
+            "open-account" => Ok(ZeroAmountPolicy::OpenAccount),
+            _ => Err("Unknown zero amount policy, expected reject|noop|open-account"),
+        }
+    }
+}
+
+// Tunable behaviour of the PaymentsEngine; the defaults follow the original specification
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    pub excess_precision: ExcessPrecision,
+    pub locked_accounts: LockedPolicy,
+    pub zero_amounts: ZeroAmountPolicy,
+    pub validators: Validators,
+    // Held back on every account as a risk reserve, out of the funds not held by disputes
+    pub reserve: Decimal,
+    // How far below zero withdrawals may take an account's available balance
+    pub overdraft_limit: Decimal,
+    // Record a journal Event for every applied state transition, for `take_events`
+    pub journal: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TxType;
+
+    fn deposit(amount: Decimal) -> Record {
+        Record {
+            tx_type: TxType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(amount),
+        }
+    }
+
+    #[test]
+    fn test_excess_precision_modes() {
+        let record = deposit(Decimal::new(1_234_565, 6));
+
+        let rejected = ExcessPrecision::Reject.apply(&record);
+        assert_eq!(rejected.amount, Some(Decimal::new(1_234_565, 6)));
+
+        let rounded = ExcessPrecision::Round.apply(&record);
+        assert_eq!(rounded.amount, Some(Decimal::new(12_346, 4)));
+
+        let truncated = ExcessPrecision::Truncate.apply(&record);
+        assert_eq!(truncated.amount, Some(Decimal::new(12_345, 4)));
+
+        // Amounts within the allowed precision are untouched
+        let valid = deposit(Decimal::new(15, 1));
+        assert_eq!(ExcessPrecision::Round.apply(&valid).amount, valid.amount);
+    }
+}
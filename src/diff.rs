@@ -0,0 +1,143 @@
+use exchange_test::{Account, Accounts, EngineConfig, PaymentsEngine, StateError};
+use rust_decimal::Decimal;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::accounts::read_accounts;
+use crate::cli::DiffArgs;
+use crate::output::write_atomically;
+
+// Reads the accounts of an engine state saved with --save-state, or else of an accounts CSV
+fn read_snapshot(path: &Path) -> Result<Accounts, Box<dyn Error>> {
+    let file = BufReader::new(File::open(path)?);
+    match PaymentsEngine::load_state(file, EngineConfig::default()) {
+        Ok(engine) => Ok(engine.finalize()),
+        Err(StateError::NotAStateFile) => read_accounts(path),
+        Err(e) => Err(format!("Failed to load state from {}: {}", path.display(), e).into()),
+    }
+}
+
+pub fn diff(args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let before = read_snapshot(&args.before)?;
+    let after = read_snapshot(&args.after)?;
+    match &args.output {
+        Some(path) => write_atomically(path, |file| write_deltas(&before, &after, file)),
+        None => write_deltas(&before, &after, io::stdout()),
+    }
+}
+
+// Writes one row per client whose balances or lock differ, in client order, with the change
+// in each balance from `before` to `after`. A client missing on one side counts as an empty,
+// unlocked account there and is reported as `opened` or `closed`.
+fn write_deltas<W: io::Write>(
+    before: &Accounts,
+    after: &Accounts,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "change",
+        "available_delta",
+        "held_delta",
+        "total_delta",
+        "lock_change",
+    ])?;
+
+    let clients: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+    for client in clients {
+        let (old, new) = (before.get(client), after.get(client));
+        let balances = |a: &Account| (a.available, a.held(), a.total, a.locked);
+        if old.map(balances) == new.map(balances) {
+            continue;
+        }
+        let change = match (old, new) {
+            (None, _) => "opened",
+            (_, None) => "closed",
+            _ => "changed",
+        };
+        let delta = |field: fn(&Account) -> Decimal| {
+            let value = |a: Option<&Account>| a.map_or(Decimal::ZERO, field);
+            format!("{:.4}", value(new) - value(old))
+        };
+        let locked = |a: Option<&Account>| a.is_some_and(|a| a.locked);
+        let lock_change = match (locked(old), locked(new)) {
+            (false, true) => "locked",
+            (true, false) => "unlocked",
+            _ => "",
+        };
+        wtr.write_record([
+            &client.to_string(),
+            change,
+            &delta(|a| a.available),
+            &delta(Account::held),
+            &delta(|a| a.total),
+            lock_change,
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::{Record, TxType};
+    use std::fs;
+
+    #[test]
+    fn test_diff_reports_deltas_between_accounts_and_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = dir.path().join("before.csv");
+        fs::write(
+            &before,
+            "client,available,held,total,locked\n\
+             1,10.0,0.0,10.0,false\n\
+             2,5.0,0.0,5.0,false\n\
+             3,1.0,0.0,1.0,false\n",
+        )
+        .unwrap();
+
+        // Client 1 deposits and has part of it disputed, client 2 is unchanged, client 3 is
+        // charged back into a locked account and client 4 is new
+        let mut engine =
+            PaymentsEngine::with_accounts(read_accounts(&before).unwrap(), EngineConfig::default());
+        let record = |tx_type, client, tx, amount| Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+        for r in [
+            record(TxType::Deposit, 1, 1, Some(Decimal::new(25, 1))),
+            record(TxType::Dispute, 1, 1, None),
+            record(TxType::Deposit, 3, 2, Some(Decimal::ONE)),
+            record(TxType::Dispute, 3, 2, None),
+            record(TxType::Chargeback, 3, 2, None),
+            record(TxType::Deposit, 4, 3, Some(Decimal::new(7, 0))),
+        ] {
+            engine.process(&r).unwrap();
+        }
+        let after = dir.path().join("after.state");
+        engine.save_state(File::create(&after).unwrap()).unwrap();
+
+        let output = dir.path().join("diff.csv");
+        diff(&DiffArgs {
+            before,
+            after,
+            output: Some(output.clone()),
+        })
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            "client,change,available_delta,held_delta,total_delta,lock_change\n\
+             1,changed,0.0000,2.5000,2.5000,\n\
+             3,changed,0.0000,0.0000,0.0000,locked\n\
+             4,opened,7.0000,0.0000,7.0000,\n"
+        );
+    }
+}
@@ -0,0 +1,1036 @@
+use rust_decimal::Decimal;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::journal::{self, Event};
+use crate::{
+    Account, Accounts, ClientId, EngineConfig, EngineError, HashState, LockedPolicy, MemoryStorage,
+    MergeError, Record, Storage, StorageError, TransactionId, TxType, ZeroAmountPolicy,
+};
+
+// Holds all engine state: client accounts, the storage of processed transactions and open
+// disputes, the ids of refused deposits and withdrawals, any transactions queued for locked
+// accounts and the aliases left by merged accounts. Accounts are kept in a HashMap, while
+// transactions and disputes go through the `Storage` parameter, MemoryStorage by default.
+#[derive(Debug, Default)]
+pub struct PaymentsEngine<S = MemoryStorage> {
+    pub(crate) accounts: Accounts,
+    pub(crate) storage: S,
+    pub(crate) rejected: HashSet<TransactionId, HashState>,
+    pub(crate) queued: HashMap<ClientId, Vec<Record>>,
+    // Merged-away client id -> the client that absorbed it
+    pub(crate) aliases: HashMap<ClientId, ClientId>,
+    // Journal events not yet taken, when the config asks for them
+    pub(crate) events: Vec<Event>,
+    pub(crate) config: EngineConfig,
+}
+
+impl PaymentsEngine {
+    pub fn new() -> PaymentsEngine {
+        PaymentsEngine::default()
+    }
+
+    pub fn with_config(config: EngineConfig) -> PaymentsEngine {
+        PaymentsEngine {
+            config,
+            ..PaymentsEngine::default()
+        }
+    }
+
+    // Starts from existing account balances, such as the closing state of an earlier run.
+    // Only the balances carry over: transactions behind them cannot be disputed.
+    pub fn with_accounts(accounts: Accounts, config: EngineConfig) -> PaymentsEngine {
+        PaymentsEngine {
+            accounts,
+            config,
+            ..PaymentsEngine::default()
+        }
+    }
+}
+
+impl<S: Storage> PaymentsEngine<S> {
+    // Keeps transactions and disputes in the given storage instead of in memory, starting
+    // from the accounts it last committed
+    pub fn with_storage(
+        storage: S,
+        config: EngineConfig,
+    ) -> Result<PaymentsEngine<S>, StorageError> {
+        Ok(PaymentsEngine {
+            accounts: storage.load_accounts()?,
+            storage,
+            rejected: HashSet::default(),
+            queued: HashMap::new(),
+            aliases: HashMap::new(),
+            events: Vec::new(),
+            config,
+        })
+    }
+
+    // Makes the current state durable, for storages that persist it
+    pub fn commit(&mut self) -> Result<(), StorageError> {
+        self.storage.commit(&self.accounts)
+    }
+
+    // Processes a transaction record by updating accounts and tracking transactions.
+    pub fn process(&mut self, record: &Record) -> Result<(), EngineError> {
+        let record = &prepare(&self.config, &self.aliases, record);
+        let result = match self.accounts.entry(record.client) {
+            // If the account already exists, apply the transaction to it
+            Entry::Occupied(mut entry) => {
+                apply_to_account(record, entry.get_mut(), &self.config, &mut self.storage)
+            }
+            // If the account does not exist, only a deposit may open it, inserting the account AFTER the deposit
+            Entry::Vacant(entry) => {
+                open_account(record, &self.config, &mut self.storage).map(|account| {
+                    if let Some(account) = account {
+                        entry.insert(account);
+                    }
+                })
+            }
+        };
+        let result = match self.accounts.get(&record.client) {
+            Some(account) => {
+                let reserve = account.holds.reserve;
+                result.and_then(|()| self.journal_applied(record, reserve))
+            }
+            // A zero amount accepted without opening an account changed nothing
+            None => result,
+        };
+        let result = queue_if_locked(
+            record,
+            result,
+            self.config.locked_accounts,
+            &mut self.queued,
+        );
+        track_rejected(record, result, &mut self.rejected)
+    }
+
+    // Processes a batch of records, returning one result per record in input order.
+    // Consecutive records for the same client share a single account lookup, which
+    // cuts hashing overhead when one client dominates the input.
+    pub fn process_batch(&mut self, records: &[Record]) -> Vec<Result<(), EngineError>> {
+        let mut results = Vec::with_capacity(records.len());
+
+        for run in records.chunk_by(|a, b| a.client == b.client) {
+            match self
+                .accounts
+                .entry(resolve_alias(&self.aliases, run[0].client))
+            {
+                Entry::Occupied(mut entry) => {
+                    let account = entry.get_mut();
+                    for record in run {
+                        let record = &prepare(&self.config, &self.aliases, record);
+                        let result =
+                            apply_to_account(record, account, &self.config, &mut self.storage)
+                                .and_then(|()| {
+                                    record_applied(
+                                        &mut self.events,
+                                        &self.config,
+                                        record,
+                                        account.holds.reserve,
+                                        &self.storage,
+                                    )
+                                });
+                        let result = queue_if_locked(
+                            record,
+                            result,
+                            self.config.locked_accounts,
+                            &mut self.queued,
+                        );
+                        results.push(track_rejected(record, result, &mut self.rejected));
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    let mut opened: Option<Account> = None;
+                    for record in run {
+                        let record = &prepare(&self.config, &self.aliases, record);
+                        let result = match opened.as_mut() {
+                            Some(account) => {
+                                apply_to_account(record, account, &self.config, &mut self.storage)
+                            }
+                            None => open_account(record, &self.config, &mut self.storage)
+                                .map(|account| opened = account),
+                        };
+                        let result = match &opened {
+                            Some(account) => result.and_then(|()| {
+                                record_applied(
+                                    &mut self.events,
+                                    &self.config,
+                                    record,
+                                    account.holds.reserve,
+                                    &self.storage,
+                                )
+                            }),
+                            None => result,
+                        };
+                        let result = queue_if_locked(
+                            record,
+                            result,
+                            self.config.locked_accounts,
+                            &mut self.queued,
+                        );
+                        results.push(track_rejected(record, result, &mut self.rejected));
+                    }
+                    if let Some(account) = opened {
+                        entry.insert(account);
+                    }
+                }
+            }
+        }
+
+        results
+    }
+
+    // Evaluates a transaction against the current state without applying it, returning the
+    // account as it would be afterwards, or the error processing it would produce.
+    pub fn simulate(&self, record: &Record) -> Result<Account, EngineError> {
+        let record = &prepare(&self.config, &self.aliases, record);
+        // Every transaction type only reads and writes the state of its own tx id, so copies
+        // of those entries are all the scratch state a simulation needs
+        let mut scratch = MemoryStorage::default();
+        if let Some(stored) = self.storage.transaction(record.tx)? {
+            scratch.insert_transaction(&stored)?;
+        }
+        if self.storage.is_disputed(record.tx)? {
+            scratch.open_dispute(record.tx)?;
+        }
+
+        let result = match self.accounts.get(&record.client) {
+            Some(account) => {
+                let mut account = account.clone();
+                apply_to_account(record, &mut account, &self.config, &mut scratch).map(|()| account)
+            }
+            // A record accepted without opening the account leaves it as it was: empty
+            None => open_account(record, &self.config, &mut scratch)
+                .map(|account| account.unwrap_or_else(Account::new)),
+        };
+        explain_not_found(record, result, &self.rejected)
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn open_disputes(&self) -> Result<usize, StorageError> {
+        self.storage.open_disputes()
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    // Whether a deposit or withdrawal has taken the id, stored or refused, so that a later
+    // deposit or withdrawal with it would not be applied as a new transaction
+    pub fn uses_tx(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.rejected.contains(&tx) || self.storage.contains_transaction(tx)?)
+    }
+
+    // Transactions queued for locked accounts and not yet applied, by client and then in
+    // arrival order
+    pub fn queued(&self) -> Vec<&Record> {
+        let mut clients: Vec<_> = self.queued.keys().collect();
+        clients.sort_unstable();
+        clients
+            .into_iter()
+            .flat_map(|client| &self.queued[client])
+            .collect()
+    }
+
+    // Unlocks a client's account and applies the transactions queued while it was locked,
+    // in arrival order, returning their results. Does nothing for an unknown client.
+    pub fn unlock(&mut self, client: ClientId) -> Vec<Result<(), EngineError>> {
+        let Some(account) = self.accounts.get_mut(&client) else {
+            return Vec::new();
+        };
+        account.locked = false;
+        if self.config.journal {
+            self.events.push(Event::AccountUnlocked { client });
+        }
+        let queued = self.queued.remove(&client).unwrap_or_default();
+        queued.iter().map(|record| self.process(record)).collect()
+    }
+
+    // Folds the account of `from` into `into` for duplicate-customer cleanup: balances, holds
+    // and dispute history are summed, a lock on either carries over and queued transactions
+    // move across. `from` becomes an alias, so its transactions, past and future, apply to
+    // `into`. Returns the merged account.
+    pub fn merge_accounts(
+        &mut self,
+        from: ClientId,
+        into: ClientId,
+    ) -> Result<&Account, MergeError> {
+        if from == into {
+            return Err(MergeError::SameClient(from));
+        }
+        if !self.accounts.contains_key(&into) {
+            return Err(MergeError::UnknownClient(into));
+        }
+        let merged = self
+            .accounts
+            .remove(&from)
+            .ok_or(MergeError::UnknownClient(from))?;
+
+        let account = self.accounts.get_mut(&into).unwrap();
+        account.absorb(merged);
+        if let Some(queued) = self.queued.remove(&from) {
+            self.queued.entry(into).or_default().extend(queued);
+        }
+        for target in self.aliases.values_mut().filter(|target| **target == from) {
+            *target = into;
+        }
+        self.aliases.insert(from, into);
+        if self.config.journal {
+            self.events.push(Event::AccountsMerged { from, into });
+        }
+        Ok(account)
+    }
+
+    // Makes `alias` another id for the account of `client`, e.g. after a migration renumbered
+    // clients, so that transactions for `alias` apply to that account
+    pub fn add_alias(&mut self, alias: ClientId, client: ClientId) -> Result<(), MergeError> {
+        let client = resolve_alias(&self.aliases, client);
+        if alias == client {
+            return Err(MergeError::SameClient(alias));
+        }
+        if self.accounts.contains_key(&alias) {
+            return Err(MergeError::HasAccount(alias));
+        }
+        for target in self.aliases.values_mut().filter(|target| **target == alias) {
+            *target = client;
+        }
+        self.aliases.insert(alias, client);
+        Ok(())
+    }
+
+    pub fn aliases(&self) -> &HashMap<ClientId, ClientId> {
+        &self.aliases
+    }
+
+    // Takes the journal events recorded since the last call, in the order they were applied
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn journal_applied(&mut self, record: &Record, reserve: Decimal) -> Result<(), EngineError> {
+        record_applied(
+            &mut self.events,
+            &self.config,
+            record,
+            reserve,
+            &self.storage,
+        )
+    }
+
+    // Consumes the engine once all records are processed, returning the final account states.
+    pub fn finalize(self) -> Accounts {
+        self.accounts
+    }
+}
+
+// Adds the journal event for a record just applied, if the config asks for a journal, and
+// with a reserve configured the reserve it left the account holding
+fn record_applied(
+    events: &mut Vec<Event>,
+    config: &EngineConfig,
+    record: &Record,
+    reserve: Decimal,
+    storage: &impl Storage,
+) -> Result<(), EngineError> {
+    if config.journal {
+        events.push(journal::applied(record, storage)?);
+        if !config.reserve.is_zero() {
+            events.push(Event::ReserveHeld {
+                client: record.client,
+                amount: reserve,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Normalizes the amount according to the config and points the record at the surviving
+// account if its client was merged away
+fn prepare(
+    config: &EngineConfig,
+    aliases: &HashMap<ClientId, ClientId>,
+    record: &Record,
+) -> Record {
+    let mut record = config.excess_precision.apply(record);
+    record.client = resolve_alias(aliases, record.client);
+    record
+}
+
+fn resolve_alias(aliases: &HashMap<ClientId, ClientId>, client: ClientId) -> ClientId {
+    aliases.get(&client).copied().unwrap_or(client)
+}
+
+// Under LockedPolicy::Queue, keeps a deposit or resolve refused only because its account is
+// locked so that `unlock` can apply it later; it counts as accepted until then
+fn queue_if_locked(
+    record: &Record,
+    result: Result<(), EngineError>,
+    policy: LockedPolicy,
+    queued: &mut HashMap<ClientId, Vec<Record>>,
+) -> Result<(), EngineError> {
+    match result {
+        Err(EngineError::AccountLocked(TxType::Deposit | TxType::Resolve))
+            if policy == LockedPolicy::Queue =>
+        {
+            queued.entry(record.client).or_default().push(*record);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+// Remembers the ids of refused deposits and withdrawals, so that a later dispute, resolve
+// or chargeback referencing one is reported as such rather than as an unknown transaction.
+fn track_rejected(
+    record: &Record,
+    result: Result<(), EngineError>,
+    rejected: &mut HashSet<TransactionId, HashState>,
+) -> Result<(), EngineError> {
+    match result {
+        // A duplicate's id belongs to the transaction that was accepted first
+        Err(EngineError::DuplicateTx(tx)) => Err(EngineError::DuplicateTx(tx)),
+        Err(e) if creates_tx(record.tx_type) => {
+            rejected.insert(record.tx);
+            Err(e)
+        }
+        result => explain_not_found(record, result, rejected),
+    }
+}
+
+fn creates_tx(tx_type: TxType) -> bool {
+    matches!(tx_type, TxType::Deposit | TxType::Withdrawal)
+}
+
+// A reference to a refused transaction finds neither it nor, if it would have opened the
+// account, the account
+fn explain_not_found<T>(
+    record: &Record,
+    result: Result<T, EngineError>,
+    rejected: &HashSet<TransactionId, HashState>,
+) -> Result<T, EngineError> {
+    match result {
+        Err(EngineError::TxNotFound { .. } | EngineError::AccountNotFound { .. })
+            if !creates_tx(record.tx_type) && rejected.contains(&record.tx) =>
+        {
+            Err(EngineError::ReferencesRejectedTx {
+                tx_type: record.tx_type,
+                tx: record.tx,
+            })
+        }
+        result => result,
+    }
+}
+
+// Creates a new account for a client that doesn't have one yet; only a deposit may do so.
+// Returns None for a zero amount accepted under ZeroAmountPolicy::Noop, which also takes a
+// zero withdrawal of a new client and leaves the client without an account.
+fn open_account(
+    record: &Record,
+    config: &EngineConfig,
+    storage: &mut impl Storage,
+) -> Result<Option<Account>, EngineError> {
+    let noop = record.amount.is_some_and(|amount| amount.is_zero())
+        && config.zero_amounts == ZeroAmountPolicy::Noop;
+    if !matches!(
+        (record.tx_type, noop),
+        (TxType::Deposit, _) | (TxType::Withdrawal, true)
+    ) {
+        return Err(EngineError::AccountNotFound {
+            client: record.client,
+            tx_type: record.tx_type,
+        });
+    }
+
+    let mut account = Account::new();
+    apply_to_account(record, &mut account, config, storage)?;
+    if noop {
+        return Ok(None);
+    }
+    Ok(Some(account))
+}
+
+// Applies a transaction record to an existing account.
+fn apply_to_account(
+    record: &Record,
+    account: &mut Account,
+    config: &EngineConfig,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    // The change is made to a copy, kept only once storage has taken its part too, so a
+    // failed storage write leaves the account as it was
+    let mut updated = account.clone();
+    match record.tx_type {
+        TxType::Deposit => process_deposit(record, &mut updated, config, storage),
+        TxType::Withdrawal => process_withdrawal(record, &mut updated, config, storage),
+        TxType::Dispute => process_dispute(record, &mut updated, storage),
+        TxType::Resolve => process_resolve(record, &mut updated, storage),
+        TxType::Chargeback => process_chargeback(record, &mut updated, storage),
+    }?;
+    updated.hold_reserve(config.reserve);
+    *account = updated;
+    Ok(())
+}
+
+fn process_deposit(
+    record: &Record,
+    account: &mut Account,
+    config: &EngineConfig,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    if storage.contains_transaction(record.tx)? {
+        return Err(EngineError::DuplicateTx(record.tx));
+    }
+
+    let amount = validated_amount(record, config)?;
+    account.deposit(amount)?;
+    storage.insert_transaction(record)?;
+    Ok(())
+}
+
+fn process_withdrawal(
+    record: &Record,
+    account: &mut Account,
+    config: &EngineConfig,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    if storage.contains_transaction(record.tx)? {
+        return Err(EngineError::DuplicateTx(record.tx));
+    }
+
+    let amount = validated_amount(record, config)?;
+    account.withdraw(amount, config.overdraft_limit)?;
+    storage.insert_transaction(record)?;
+    Ok(())
+}
+
+// Moves funds from available to held and records the dispute.
+fn process_dispute(
+    record: &Record,
+    account: &mut Account,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    let disputed_tx = find_referenced_tx(record, storage)?;
+
+    if disputed_tx.tx_type != TxType::Deposit {
+        return Err(EngineError::NotADeposit(record.tx));
+    }
+
+    if storage.is_disputed(record.tx)? {
+        return Err(EngineError::AlreadyDisputed(record.tx));
+    }
+
+    account.apply_dispute(referenced_amount(record, &disputed_tx)?)?;
+    storage.open_dispute(record.tx)?;
+    Ok(())
+}
+
+// Moves funds from held back to available and removes the dispute.
+fn process_resolve(
+    record: &Record,
+    account: &mut Account,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    if !storage.is_disputed(record.tx)? {
+        return Err(EngineError::NotDisputed {
+            tx_type: record.tx_type,
+            tx: record.tx,
+        });
+    }
+
+    let disputed_tx = find_referenced_tx(record, storage)?;
+    account.resolve_dispute(referenced_amount(record, &disputed_tx)?)?;
+    storage.close_dispute(record.tx)?;
+    Ok(())
+}
+
+// Removes held funds (and thus total funds), removes dispute and locks the account.
+fn process_chargeback(
+    record: &Record,
+    account: &mut Account,
+    storage: &mut impl Storage,
+) -> Result<(), EngineError> {
+    if !storage.is_disputed(record.tx)? {
+        return Err(EngineError::NotDisputed {
+            tx_type: record.tx_type,
+            tx: record.tx,
+        });
+    }
+
+    let disputed_tx = find_referenced_tx(record, storage)?;
+    account.chargeback(referenced_amount(record, &disputed_tx)?)?;
+    storage.close_dispute(record.tx)?;
+    Ok(())
+}
+
+// Checks that a deposit/withdrawal carries an amount that passes the configured validators
+// and, if it is zero, the zero amount policy.
+fn validated_amount(record: &Record, config: &EngineConfig) -> Result<Decimal, EngineError> {
+    let amount = record.amount.ok_or(EngineError::MissingAmount {
+        tx_type: record.tx_type,
+        tx: record.tx,
+    })?;
+    config.validators.validate(record, amount)?;
+    if amount.is_zero() && config.zero_amounts == ZeroAmountPolicy::Reject {
+        return Err(EngineError::ZeroAmount {
+            tx_type: record.tx_type,
+            tx: record.tx,
+        });
+    }
+    Ok(amount)
+}
+
+// Looks up the transaction a dispute, resolve or chargeback refers to.
+fn find_referenced_tx(record: &Record, storage: &impl Storage) -> Result<Record, EngineError> {
+    storage
+        .transaction(record.tx)?
+        .ok_or(EngineError::TxNotFound {
+            tx_type: record.tx_type,
+            tx: record.tx,
+        })
+}
+
+fn referenced_amount(record: &Record, referenced: &Record) -> Result<Decimal, EngineError> {
+    referenced.amount.ok_or(EngineError::MissingAmount {
+        tx_type: record.tx_type,
+        tx: record.tx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::ReaderBuilder;
+    use std::env;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_large_csv_with_edge_cases() {
+        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let mut csv_path = PathBuf::from(manifest_dir);
+        csv_path.push("tests/data/test_data.csv");
+
+        let file = File::open(&csv_path).expect("Failed to open test data CSV file");
+        let mut rdr = ReaderBuilder::new().comment(Some(b'#')).from_reader(file);
+
+        let mut engine = PaymentsEngine::new();
+
+        // Keep track of error count
+        let mut error_count = 0;
+        for result in rdr.deserialize() {
+            let record: Record = match result {
+                Ok(rec) => rec,
+                Err(_) => {
+                    error_count += 1;
+                    continue;
+                }
+            };
+            if engine.process(&record).is_err() {
+                error_count += 1;
+            }
+        }
+        assert_eq!(error_count, 13);
+
+        let accounts = engine.finalize();
+
+        let account1 = accounts.get(&1).unwrap();
+        assert_eq!(account1.available, Decimal::new(130000, 2));
+        assert_eq!(account1.held(), Decimal::new(0, 2));
+        assert_eq!(account1.total, Decimal::new(130000, 2));
+        assert!(!account1.locked);
+
+        let account2 = accounts.get(&2).unwrap();
+        assert_eq!(account2.available, Decimal::new(0, 4));
+        assert_eq!(account2.held(), Decimal::new(0, 4));
+        assert_eq!(account2.total, Decimal::new(0, 4));
+        assert!(account2.locked);
+
+        assert!(!accounts.contains_key(&3));
+        assert!(!accounts.contains_key(&4));
+    }
+
+    #[test]
+    fn test_rejections_report_specific_errors() {
+        let record = |tx_type, tx, amount: Option<i64>| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount: amount.map(|a| Decimal::new(a, 0)),
+        };
+        let mut engine = PaymentsEngine::new();
+
+        assert_eq!(
+            engine.process(&record(TxType::Withdrawal, 1, Some(5))),
+            Err(EngineError::AccountNotFound {
+                client: 1,
+                tx_type: TxType::Withdrawal
+            })
+        );
+        engine
+            .process(&record(TxType::Deposit, 2, Some(5)))
+            .unwrap();
+        assert_eq!(
+            engine.process(&record(TxType::Deposit, 2, Some(5))),
+            Err(EngineError::DuplicateTx(2))
+        );
+        assert_eq!(
+            engine.process(&record(TxType::Withdrawal, 3, Some(6))),
+            Err(EngineError::InsufficientFunds(TxType::Withdrawal))
+        );
+        assert_eq!(
+            engine.process(&record(TxType::Resolve, 2, None)),
+            Err(EngineError::NotDisputed {
+                tx_type: TxType::Resolve,
+                tx: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_process_batch_matches_sequential_processing() {
+        let data = "type,client,tx,amount\n\
+                    withdrawal,1,1,5.0\n\
+                    deposit,1,2,-1.0\n\
+                    deposit,1,3,10.0\n\
+                    withdrawal,1,4,4.0\n\
+                    dispute,1,3,\n\
+                    deposit,2,5,3.0\n\
+                    deposit,1,6,2.0\n\
+                    deposit,1,6,2.0\n";
+        let records: Vec<Record> = ReaderBuilder::new()
+            .from_reader(data.as_bytes())
+            .deserialize()
+            .map(Result::unwrap)
+            .collect();
+
+        let mut sequential = PaymentsEngine::new();
+        let sequential_results: Vec<bool> = records
+            .iter()
+            .map(|r| sequential.process(r).is_ok())
+            .collect();
+
+        let mut batched = PaymentsEngine::new();
+        let batched_results: Vec<bool> = batched
+            .process_batch(&records)
+            .iter()
+            .map(Result::is_ok)
+            .collect();
+
+        assert_eq!(batched_results, sequential_results);
+        assert_eq!(batched.finalize(), sequential.finalize());
+    }
+
+    #[test]
+    fn test_simulate_does_not_change_state() {
+        let mut engine = PaymentsEngine::new();
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+        engine
+            .process(&record(TxType::Deposit, 1, Some(Decimal::new(10, 0))))
+            .unwrap();
+
+        let after = engine
+            .simulate(&record(TxType::Withdrawal, 2, Some(Decimal::new(4, 0))))
+            .unwrap();
+        assert_eq!(after.available, Decimal::new(6, 0));
+        assert_eq!(
+            engine.simulate(&record(TxType::Withdrawal, 2, Some(Decimal::new(11, 0)))),
+            Err(EngineError::InsufficientFunds(TxType::Withdrawal))
+        );
+        assert_eq!(
+            engine
+                .simulate(&record(TxType::Dispute, 1, None))
+                .unwrap()
+                .held(),
+            Decimal::new(10, 0)
+        );
+
+        assert_eq!(engine.accounts()[&1].available, Decimal::new(10, 0));
+        assert_eq!(engine.open_disputes().unwrap(), 0);
+        engine
+            .process(&record(TxType::Withdrawal, 2, Some(Decimal::new(4, 0))))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_references_to_rejected_transactions_are_reported() {
+        let mut engine = PaymentsEngine::new();
+        let record = |tx_type, amount| Record {
+            tx_type,
+            client: 1,
+            tx: 1,
+            amount,
+        };
+
+        let rejected = engine.process(&record(TxType::Deposit, Some(Decimal::new(1, 5))));
+        assert_eq!(
+            rejected,
+            Err(EngineError::PrecisionExceeded {
+                tx_type: TxType::Deposit,
+                tx: 1
+            })
+        );
+        assert_eq!(
+            engine.process(&record(TxType::Dispute, None)),
+            Err(EngineError::ReferencesRejectedTx {
+                tx_type: TxType::Dispute,
+                tx: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_zero_amount_policies() {
+        let zero = |tx_type, client, tx| Record {
+            tx_type,
+            client,
+            tx,
+            amount: Some(Decimal::ZERO),
+        };
+        let run = |policy| {
+            let mut engine = PaymentsEngine::with_config(EngineConfig {
+                zero_amounts: policy,
+                ..EngineConfig::default()
+            });
+            let results = [
+                engine.process(&zero(TxType::Deposit, 1, 1)),
+                engine.process(&zero(TxType::Withdrawal, 2, 2)),
+                // Only a tx id already in the history is a duplicate
+                engine.process(&zero(TxType::Deposit, 3, 1)),
+            ];
+            let mut clients: Vec<_> = engine.accounts().keys().copied().collect();
+            clients.sort();
+            (results, clients)
+        };
+
+        let rejected = |tx_type, tx| Err(EngineError::ZeroAmount { tx_type, tx });
+        // A withdrawal never opens an account, except that noop accepts a zero one
+        let not_found = Err(EngineError::AccountNotFound {
+            client: 2,
+            tx_type: TxType::Withdrawal,
+        });
+        assert_eq!(
+            run(ZeroAmountPolicy::Reject),
+            (
+                [
+                    rejected(TxType::Deposit, 1),
+                    not_found.clone(),
+                    rejected(TxType::Deposit, 1)
+                ],
+                vec![]
+            )
+        );
+        let duplicate = Err(EngineError::DuplicateTx(1));
+        assert_eq!(
+            run(ZeroAmountPolicy::Noop),
+            ([Ok(()), Ok(()), duplicate.clone()], vec![])
+        );
+        assert_eq!(
+            run(ZeroAmountPolicy::OpenAccount),
+            ([Ok(()), not_found, duplicate], vec![1])
+        );
+    }
+
+    #[test]
+    fn test_locked_account_queue_is_applied_on_unlock() {
+        let mut engine = PaymentsEngine::with_config(EngineConfig {
+            locked_accounts: LockedPolicy::Queue,
+            ..EngineConfig::default()
+        });
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+        for r in [
+            record(TxType::Deposit, 1, Some(Decimal::new(10, 0))),
+            record(TxType::Deposit, 2, Some(Decimal::new(3, 0))),
+            record(TxType::Dispute, 1, None),
+            record(TxType::Dispute, 2, None),
+            record(TxType::Chargeback, 1, None),
+            // The account is locked from here on
+            record(TxType::Resolve, 2, None),
+            record(TxType::Deposit, 3, Some(Decimal::new(5, 0))),
+        ] {
+            engine.process(&r).unwrap();
+        }
+        assert_eq!(
+            engine.process(&record(TxType::Withdrawal, 4, Some(Decimal::ONE))),
+            Err(EngineError::AccountLocked(TxType::Withdrawal))
+        );
+        assert_eq!(engine.queued().len(), 2);
+        assert_eq!(engine.accounts()[&1].total, Decimal::new(3, 0));
+
+        assert_eq!(engine.unlock(1), vec![Ok(()), Ok(())]);
+        let account = &engine.accounts()[&1];
+        assert_eq!(
+            (account.available, account.held(), account.total),
+            (Decimal::new(8, 0), Decimal::ZERO, Decimal::new(8, 0))
+        );
+        assert!(engine.queued().is_empty());
+    }
+
+    #[test]
+    fn test_reserve_is_held_back_from_available() {
+        let mut engine = PaymentsEngine::with_config(EngineConfig {
+            reserve: Decimal::new(3, 0),
+            journal: true,
+            ..EngineConfig::default()
+        });
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+        let balances = |engine: &PaymentsEngine| {
+            let account = &engine.accounts()[&1];
+            (
+                account.available,
+                account.holds.reserve,
+                account.holds.dispute,
+            )
+        };
+
+        engine
+            .process(&record(TxType::Deposit, 1, Some(Decimal::new(10, 0))))
+            .unwrap();
+        assert_eq!(
+            balances(&engine),
+            (Decimal::new(7, 0), Decimal::new(3, 0), Decimal::ZERO)
+        );
+        assert!(matches!(
+            engine.process(&record(TxType::Withdrawal, 2, Some(Decimal::new(8, 0)))),
+            Err(EngineError::InsufficientFunds(TxType::Withdrawal))
+        ));
+
+        // A dispute of the whole deposit takes the reserve with it until it is resolved
+        engine.process(&record(TxType::Dispute, 1, None)).unwrap();
+        assert_eq!(
+            balances(&engine),
+            (Decimal::ZERO, Decimal::ZERO, Decimal::new(10, 0))
+        );
+        engine.process(&record(TxType::Resolve, 1, None)).unwrap();
+        assert_eq!(
+            balances(&engine),
+            (Decimal::new(7, 0), Decimal::new(3, 0), Decimal::ZERO)
+        );
+
+        let mut accounts = Accounts::default();
+        for event in &engine.take_events() {
+            journal::replay(&mut accounts, event, Decimal::ZERO).unwrap();
+        }
+        assert_eq!(&accounts, engine.accounts());
+    }
+
+    #[test]
+    fn test_overdraft_limit_lets_withdrawals_go_below_zero() {
+        let config = || EngineConfig {
+            overdraft_limit: Decimal::new(5, 0),
+            journal: true,
+            ..EngineConfig::default()
+        };
+        let mut engine = PaymentsEngine::with_config(config());
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount: Some(Decimal::new(amount, 0)),
+        };
+
+        engine.process(&record(TxType::Deposit, 1, 10)).unwrap();
+        engine.process(&record(TxType::Withdrawal, 2, 14)).unwrap();
+        assert_eq!(engine.accounts()[&1].available, Decimal::new(-4, 0));
+        assert!(matches!(
+            engine.process(&record(TxType::Withdrawal, 3, 2)),
+            Err(EngineError::InsufficientFunds(TxType::Withdrawal))
+        ));
+        assert_eq!(
+            engine.accounts()[&1].withdrawable(config().overdraft_limit),
+            Decimal::ONE
+        );
+
+        // The journal only replays under the same limit
+        let events = engine.take_events();
+        let mut accounts = Accounts::default();
+        assert!(events
+            .iter()
+            .try_for_each(|event| journal::replay(&mut accounts, event, Decimal::ZERO))
+            .is_err());
+        let mut accounts = Accounts::default();
+        for event in &events {
+            journal::replay(&mut accounts, event, config().overdraft_limit).unwrap();
+        }
+        assert_eq!(&accounts, engine.accounts());
+    }
+
+    #[test]
+    fn test_merged_account_absorbs_balances_and_history() {
+        let mut engine = PaymentsEngine::new();
+        let record = |tx_type, client, tx, amount| Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+        for r in [
+            record(TxType::Deposit, 1, 1, Some(Decimal::new(10, 0))),
+            record(TxType::Deposit, 2, 2, Some(Decimal::new(5, 0))),
+            record(TxType::Dispute, 2, 2, None),
+        ] {
+            engine.process(&r).unwrap();
+        }
+
+        let merged = engine.merge_accounts(2, 1).unwrap();
+        assert_eq!(
+            (merged.available, merged.held(), merged.total),
+            (Decimal::new(10, 0), Decimal::new(5, 0), Decimal::new(15, 0))
+        );
+        assert!(!engine.accounts().contains_key(&2));
+        assert_eq!(
+            engine.merge_accounts(2, 1),
+            Err(MergeError::UnknownClient(2))
+        );
+
+        // The old id now refers to the surviving account, including for its past transactions
+        engine
+            .process(&record(TxType::Resolve, 2, 2, None))
+            .unwrap();
+        engine
+            .process(&record(TxType::Withdrawal, 2, 3, Some(Decimal::new(15, 0))))
+            .unwrap();
+        assert_eq!(engine.accounts()[&1].total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_aliases_resolve_to_the_surviving_account() {
+        let mut engine = PaymentsEngine::new();
+        let deposit = |client, tx| Record {
+            tx_type: TxType::Deposit,
+            client,
+            tx,
+            amount: Some(Decimal::ONE),
+        };
+        engine.process(&deposit(1, 1)).unwrap();
+        engine.process(&deposit(2, 2)).unwrap();
+
+        assert_eq!(engine.add_alias(2, 1), Err(MergeError::HasAccount(2)));
+        engine.add_alias(7, 1).unwrap();
+        engine.add_alias(8, 7).unwrap();
+        engine.merge_accounts(1, 2).unwrap();
+        assert_eq!(engine.aliases()[&8], 2);
+
+        engine.process(&deposit(8, 3)).unwrap();
+        engine.process(&deposit(7, 4)).unwrap();
+        assert_eq!(engine.accounts()[&2].total, Decimal::new(4, 0));
+    }
+}
@@ -0,0 +1,191 @@
+use exchange_test::{Record, TxType};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Debug;
+use std::path::Path;
+
+// Annotations an enricher attached to a record, by name
+pub type Annotations = BTreeMap<&'static str, String>;
+
+// A stage between parsing and the engine that annotates a record, from the record itself
+// and the other columns of its input row. Annotations never change how the record is
+// applied; they are carried into the journal and the rejects report.
+pub trait Enricher: Debug {
+    // `field` looks up a column of the input row by its header name
+    fn enrich(
+        &self,
+        record: &Record,
+        field: &dyn Fn(&str) -> Option<String>,
+        annotations: &mut Annotations,
+    );
+}
+
+// The enrichers of a run, applied in order
+#[derive(Debug, Default)]
+pub struct Enrichers(Vec<Box<dyn Enricher>>);
+
+impl Enrichers {
+    pub fn push(&mut self, enricher: impl Enricher + 'static) {
+        self.0.push(Box::new(enricher));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn annotate(&self, record: &Record, field: &dyn Fn(&str) -> Option<String>) -> Annotations {
+        let mut annotations = Annotations::new();
+        for enricher in &self.0 {
+            enricher.enrich(record, field, &mut annotations);
+        }
+        annotations
+    }
+}
+
+// Formats annotations for a CSV report column, as `name=value` pairs separated by `;`
+pub fn format_annotations(annotations: &Annotations) -> String {
+    let pairs: Vec<String> = annotations
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    pairs.join(";")
+}
+
+#[derive(Debug, Deserialize)]
+struct CategoryRule {
+    pattern: String,
+    category: String,
+}
+
+// Sets `category` from the first rule whose pattern occurs in the `memo` column, ignoring case
+#[derive(Debug)]
+pub struct MemoCategory {
+    rules: Vec<CategoryRule>,
+}
+
+impl MemoCategory {
+    // Reads the rules from a CSV file with `pattern,category` columns
+    pub fn from_path(path: &Path) -> Result<MemoCategory, Box<dyn Error>> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let rules = rdr
+            .deserialize()
+            .map(|rule| {
+                rule.map(|rule: CategoryRule| CategoryRule {
+                    pattern: rule.pattern.to_lowercase(),
+                    ..rule
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(MemoCategory { rules })
+    }
+}
+
+impl Enricher for MemoCategory {
+    fn enrich(
+        &self,
+        _: &Record,
+        field: &dyn Fn(&str) -> Option<String>,
+        annotations: &mut Annotations,
+    ) {
+        let Some(memo) = field("memo").map(|memo| memo.to_lowercase()) else {
+            return;
+        };
+        if let Some(rule) = self.rules.iter().find(|rule| memo.contains(&rule.pattern)) {
+            annotations.insert("category", rule.category.clone());
+        }
+    }
+}
+
+// Sets `risk_score`, from 0 to 100, for deposits and withdrawals: their amount as a share of
+// the threshold, so anything at or above the threshold scores 100
+#[derive(Debug)]
+pub struct RiskScore {
+    pub threshold: Decimal,
+}
+
+impl Enricher for RiskScore {
+    fn enrich(
+        &self,
+        record: &Record,
+        _: &dyn Fn(&str) -> Option<String>,
+        annotations: &mut Annotations,
+    ) {
+        let Some(amount) = record
+            .amount
+            .filter(|_| matches!(record.tx_type, TxType::Deposit | TxType::Withdrawal))
+        else {
+            return;
+        };
+        let score = (amount.max(Decimal::ZERO) / self.threshold * Decimal::ONE_HUNDRED)
+            .min(Decimal::ONE_HUNDRED)
+            .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero);
+        annotations.insert("risk_score", score.to_string());
+    }
+}
+
+// Sets `currency` to the `currency` column as a trimmed, upper-case ISO 4217 code
+#[derive(Debug)]
+pub struct CurrencyCode;
+
+impl Enricher for CurrencyCode {
+    fn enrich(
+        &self,
+        _: &Record,
+        field: &dyn Fn(&str) -> Option<String>,
+        annotations: &mut Annotations,
+    ) {
+        if let Some(code) = field("currency").map(|code| code.trim().to_uppercase()) {
+            if !code.is_empty() {
+                annotations.insert("currency", code);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_enrichers_annotate_from_record_and_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let rules = dir.path().join("categories.csv");
+        fs::write(&rules, "pattern,category\nGROCER,groceries\nrent,housing\n").unwrap();
+
+        let mut enrichers = Enrichers::default();
+        enrichers.push(MemoCategory::from_path(&rules).unwrap());
+        enrichers.push(RiskScore {
+            threshold: Decimal::new(1000, 0),
+        });
+        enrichers.push(CurrencyCode);
+
+        let record = Record {
+            tx_type: TxType::Withdrawal,
+            client: 1,
+            tx: 1,
+            amount: Some(Decimal::new(2505, 1)),
+        };
+        let field = |name: &str| match name {
+            "memo" => Some("Corner grocery store".to_string()),
+            "currency" => Some(" eur".to_string()),
+            _ => None,
+        };
+        let annotations = enrichers.annotate(&record, &field);
+        assert_eq!(
+            format_annotations(&annotations),
+            "category=groceries;currency=EUR;risk_score=25"
+        );
+
+        // A dispute has no amount of its own to score, and an input without the columns
+        // leaves nothing to categorize or normalize
+        let dispute = Record {
+            tx_type: TxType::Dispute,
+            amount: None,
+            ..record
+        };
+        assert!(enrichers.annotate(&dispute, &|_| None).is_empty());
+    }
+}
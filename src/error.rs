@@ -0,0 +1,116 @@
+use rust_decimal::Decimal;
+use std::io;
+use thiserror::Error;
+
+use crate::{ClientId, TransactionId, TxType, STATE_VERSION};
+
+// Every reason the engine can refuse a transaction. Library consumers can match on the
+// variant, and `code` gives a stable machine-readable identifier for reports and the CLI.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    #[error("Unknown transaction type: {0:?}")]
+    UnknownTxType(String),
+    #[error("{0:?} error: Account is locked")]
+    AccountLocked(TxType),
+    #[error("{0:?} error: Insufficient funds")]
+    InsufficientFunds(TxType),
+    #[error("Duplicate transaction ID: {0}; ignoring")]
+    DuplicateTx(TransactionId),
+    #[error("{tx_type:?} amount cannot be negative; transaction {tx}")]
+    NegativeAmount { tx_type: TxType, tx: TransactionId },
+    #[error("{tx_type:?} amount cannot be zero; transaction {tx}")]
+    ZeroAmount { tx_type: TxType, tx: TransactionId },
+    #[error("{tx_type:?} amount exceeds allowed precision; transaction {tx}")]
+    PrecisionExceeded { tx_type: TxType, tx: TransactionId },
+    #[error("{tx_type:?} amount exceeds the limit of {limit}; transaction {tx}")]
+    AmountLimitExceeded {
+        tx_type: TxType,
+        tx: TransactionId,
+        limit: Decimal,
+    },
+    #[error("{tx_type:?} transaction {tx} missing amount")]
+    MissingAmount { tx_type: TxType, tx: TransactionId },
+    #[error("Account {client} does not exist for transaction type {tx_type:?}")]
+    AccountNotFound { client: ClientId, tx_type: TxType },
+    #[error("{tx_type:?} error: Transaction {tx} not found")]
+    TxNotFound { tx_type: TxType, tx: TransactionId },
+    #[error("{tx_type:?} error: Transaction {tx} was rejected")]
+    ReferencesRejectedTx { tx_type: TxType, tx: TransactionId },
+    #[error("Dispute error: Transaction {0} is not a deposit")]
+    NotADeposit(TransactionId),
+    #[error("Dispute error: Transaction {0} is already disputed")]
+    AlreadyDisputed(TransactionId),
+    #[error("{tx_type:?} error: Transaction {tx} is not disputed")]
+    NotDisputed { tx_type: TxType, tx: TransactionId },
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+impl EngineError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineError::UnknownTxType(_) => "unknown_tx_type",
+            EngineError::AccountLocked(_) => "account_locked",
+            EngineError::InsufficientFunds(_) => "insufficient_funds",
+            EngineError::DuplicateTx(_) => "duplicate_tx",
+            EngineError::NegativeAmount { .. } => "negative_amount",
+            EngineError::ZeroAmount { .. } => "zero_amount",
+            EngineError::PrecisionExceeded { .. } => "precision_exceeded",
+            EngineError::AmountLimitExceeded { .. } => "amount_limit_exceeded",
+            EngineError::MissingAmount { .. } => "missing_amount",
+            EngineError::AccountNotFound { .. } => "account_not_found",
+            EngineError::TxNotFound { .. } => "tx_not_found",
+            EngineError::ReferencesRejectedTx { .. } => "references_rejected_tx",
+            EngineError::NotADeposit(_) => "not_a_deposit",
+            EngineError::AlreadyDisputed(_) => "already_disputed",
+            EngineError::NotDisputed { .. } => "not_disputed",
+            EngineError::Storage(_) => "storage_error",
+        }
+    }
+}
+
+// Why two accounts could not be merged, or a client id not aliased
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    #[error("Client {0} has no account")]
+    UnknownClient(ClientId),
+    #[error("Cannot merge client {0} into itself")]
+    SameClient(ClientId),
+    #[error("Client {0} has an account of its own; merge it instead of aliasing it")]
+    HasAccount(ClientId),
+}
+
+// A failure of the Storage backend holding transactions and disputes
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct StorageError(pub String);
+
+impl From<StorageError> for EngineError {
+    fn from(e: StorageError) -> EngineError {
+        EngineError::Storage(e.0)
+    }
+}
+
+// Why an engine state file could not be saved or loaded
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("State file I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not an engine state file")]
+    NotAStateFile,
+    #[error("Unsupported state file version {0}; this build reads versions 1 to {STATE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("State file is version {found}, not version {expected}")]
+    VersionMismatch { expected: u32, found: u32 },
+    #[error(
+        "Cannot migrate a state file from version {0} to version {1}; only upgrades up to \
+         version {STATE_VERSION} are supported"
+    )]
+    UnsupportedMigration(u32, u32),
+    #[error("Failed to encode engine state: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+    #[error("Corrupt state file: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+    #[error("Corrupt state file: {0}")]
+    Corrupt(&'static str),
+}
@@ -0,0 +1,86 @@
+use exchange_test::{replay, Accounts, Event};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::cli::JournalReplayArgs;
+use crate::enrich::Annotations;
+use crate::output::{write_accounts, write_atomically};
+
+// A journal line: the event, with the annotations of the record behind it if it had any
+#[derive(Serialize)]
+struct Entry<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    #[serde(skip_serializing_if = "Annotations::is_empty")]
+    annotations: &'a Annotations,
+}
+
+// Appends the engine's journal events to a file as JSON lines. Each run adds to what earlier
+// runs wrote, so a journal started along with the first run holds the whole history.
+pub struct JournalWriter {
+    wtr: BufWriter<File>,
+}
+
+impl JournalWriter {
+    pub fn open(path: &Path) -> io::Result<JournalWriter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JournalWriter {
+            wtr: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        event: &Event,
+        annotations: &Annotations,
+    ) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.wtr, &Entry { event, annotations })?;
+        writeln!(self.wtr)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+// Rebuilds the accounts from nothing but a journal, written by a run with the given overdraft
+// limit
+pub fn read_journal(path: &Path, overdraft_limit: Decimal) -> Result<Accounts, Box<dyn Error>> {
+    let mut accounts = Accounts::default();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let event: Event = serde_json::from_str(&line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        replay(&mut accounts, &event, overdraft_limit)
+            .map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+    }
+    Ok(accounts)
+}
+
+pub fn replay_journal(args: &JournalReplayArgs) -> Result<(), Box<dyn Error>> {
+    let overdraft_limit = args.overdraft_limit.unwrap_or_default();
+    let accounts = read_journal(&args.journal, overdraft_limit)?;
+    match &args.output {
+        Some(path) => write_atomically(path, |file| {
+            write_accounts(
+                &accounts,
+                args.output_format,
+                args.extended_output,
+                overdraft_limit,
+                file,
+            )
+        }),
+        None => write_accounts(
+            &accounts,
+            args.output_format,
+            args.extended_output,
+            overdraft_limit,
+            io::stdout(),
+        ),
+    }
+}
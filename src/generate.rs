@@ -0,0 +1,63 @@
+use csv::Writer;
+use exchange_test::{ClientId, TransactionId, TxType};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io;
+
+use crate::cli::GenerateArgs;
+
+// Writes a synthetic but plausible transaction stream: mostly deposits and withdrawals,
+// with disputes referencing earlier deposits and resolves/chargebacks closing them.
+pub fn generate_transactions<W: io::Write>(
+    writer: W,
+    args: &GenerateArgs,
+) -> Result<(), Box<dyn Error>> {
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let mut wtr = Writer::from_writer(writer);
+    wtr.write_record(["type", "client", "tx", "amount"])?;
+
+    let clients = args.clients.max(1);
+    let mut next_tx: TransactionId = 1;
+    let mut deposits: Vec<(ClientId, TransactionId)> = Vec::new();
+    let mut disputed: Vec<(ClientId, TransactionId)> = Vec::new();
+
+    for _ in 0..args.transactions {
+        let roll = rng.random_range(0..100);
+        let (tx_type, client, tx, amount) = if roll >= 95 && !disputed.is_empty() {
+            let (client, tx) = disputed.swap_remove(rng.random_range(0..disputed.len()));
+            let tx_type = if roll >= 98 {
+                TxType::Chargeback
+            } else {
+                TxType::Resolve
+            };
+            (tx_type, client, tx, None)
+        } else if roll >= 90 && !deposits.is_empty() {
+            let (client, tx) = deposits[rng.random_range(0..deposits.len())];
+            disputed.push((client, tx));
+            (TxType::Dispute, client, tx, None)
+        } else {
+            let client = rng.random_range(1..=clients);
+            let tx = next_tx;
+            next_tx += 1;
+            let amount = Decimal::new(rng.random_range(1..100_000_000), 4);
+            if roll >= 70 {
+                (TxType::Withdrawal, client, tx, Some(amount))
+            } else {
+                deposits.push((client, tx));
+                (TxType::Deposit, client, tx, Some(amount))
+            }
+        };
+
+        wtr.write_record([
+            tx_type.as_str().to_string(),
+            client.to_string(),
+            tx.to_string(),
+            amount.map(|a| a.to_string()).unwrap_or_default(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
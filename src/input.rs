@@ -0,0 +1,757 @@
+use csv::{ReaderBuilder, StringRecord};
+use flate2::read::MultiGzDecoder;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Path that selects stdin (for input) or stderr (for the summary) instead of a file
+pub const STDIO_PATH: &str = "-";
+
+// Default input guards: generous for any real transaction row, but small enough that a file
+// missing its newlines fails fast instead of being buffered whole
+pub const DEFAULT_MAX_LINE_LENGTH: u64 = 1 << 20;
+pub const DEFAULT_MAX_FIELD_SIZE: usize = 1 << 10;
+
+pub type TransactionReader = csv::Reader<LineFilter<Box<dyn BufRead>>>;
+
+// Compression formats that are decoded transparently on input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_extension(input: &Path) -> Option<Compression> {
+        match input.extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn from_magic(head: &[u8]) -> Option<Compression> {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+// Opens the input file, or stdin when the path is "-". Gzip and zstd input is decompressed
+// on the fly, detected by the file extension or else by the leading magic bytes.
+pub fn open_input(input: &Path) -> io::Result<Box<dyn Read>> {
+    let raw: Box<dyn Read> = if input == Path::new(STDIO_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(input)?)
+    };
+    let mut reader = BufReader::new(raw);
+
+    let compression = match Compression::from_extension(input) {
+        Some(compression) => Some(compression),
+        None => Compression::from_magic(reader.fill_buf()?),
+    };
+    Ok(match compression {
+        // Multi-member gzip files (e.g. concatenated archives) are read to the end
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(reader)),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::with_buffer(reader)?),
+        None => Box::new(reader),
+    })
+}
+
+// Opens the input like `open_input`, positioned `offset` bytes into its decoded content.
+// A plain file seeks there; stdin and compressed input cannot, so the bytes before the
+// offset are read and discarded.
+pub fn open_input_at(input: &Path, offset: u64) -> io::Result<Box<dyn Read>> {
+    if let Some(mut file) = open_plain(input)? {
+        file.seek(SeekFrom::Start(offset))?;
+        return Ok(Box::new(file));
+    }
+    let mut reader = open_input(input)?;
+    io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+    Ok(reader)
+}
+
+// Opens the input if it is a regular file that is not compressed, so it can be read from
+// any offset; None for stdin, pipes and compressed input
+fn open_plain(input: &Path) -> io::Result<Option<File>> {
+    if input == Path::new(STDIO_PATH) || Compression::from_extension(input).is_some() {
+        return Ok(None);
+    }
+    let mut file = File::open(input)?;
+    if !file.metadata()?.is_file() {
+        return Ok(None);
+    }
+    let mut head = Vec::new();
+    file.by_ref().take(4).read_to_end(&mut head)?;
+    if Compression::from_magic(&head).is_some() {
+        return Ok(None);
+    }
+    file.rewind()?;
+    Ok(Some(file))
+}
+
+// Opens the input like `open_input`, buffered for line-wise reading. With `mmap` a regular
+// file is memory-mapped and read straight from the map, saving the read syscalls and a copy
+// on large local files; stdin, pipes and other inputs that cannot be mapped are read through
+// the usual buffer instead.
+pub fn open_buffered(input: &Path, mmap: bool) -> io::Result<Box<dyn BufRead>> {
+    if mmap {
+        if let Some(mapped) = open_mapped(input)? {
+            return Ok(mapped);
+        }
+    }
+    Ok(Box::new(BufReader::new(open_input(input)?)))
+}
+
+#[cfg(feature = "mmap")]
+fn open_mapped(input: &Path) -> io::Result<Option<Box<dyn BufRead>>> {
+    if input == Path::new(STDIO_PATH) {
+        return Ok(None);
+    }
+    let file = File::open(input)?;
+    let metadata = file.metadata()?;
+    // An empty file has nothing to map
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(None);
+    }
+    // SAFETY: the map is only ever read. A file truncated by another process while mapped
+    // makes reads of the lost pages fault, so --mmap is for local files that stay put
+    // during the run.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    #[cfg(unix)]
+    map.advise(memmap2::Advice::Sequential)?;
+
+    let compression = Compression::from_extension(input).or_else(|| Compression::from_magic(&map));
+    let reader = io::Cursor::new(map);
+    Ok(Some(match compression {
+        Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Compression::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        None => Box::new(reader),
+    }))
+}
+
+#[cfg(not(feature = "mmap"))]
+fn open_mapped(_: &Path) -> io::Result<Option<Box<dyn BufRead>>> {
+    Err(io::Error::other(
+        "--mmap requires building with the `mmap` feature",
+    ))
+}
+
+// Expands glob patterns among the input arguments into the matching files, in sorted order.
+// Arguments without glob metacharacters (including "-") are passed through unchanged.
+pub fn expand_inputs(args: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        let pattern = arg.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            inputs.push(arg.clone());
+            continue;
+        }
+
+        let matched = glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?;
+        if matched.is_empty() {
+            return Err(format!("No input files match {}", pattern).into());
+        }
+        inputs.extend(matched);
+    }
+    Ok(inputs)
+}
+
+// Opens a transactions CSV for reading through a LineFilter
+pub fn transaction_reader(
+    input: &Path,
+    delimiter: u8,
+    max_line_length: u64,
+    mmap: bool,
+) -> io::Result<TransactionReader> {
+    let filter = LineFilter::new(open_buffered(input, mmap)?, delimiter, max_line_length);
+    Ok(ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(filter))
+}
+
+// Reopens a transactions CSV after its first `line` lines, `byte` bytes long, reading up to
+// byte `end` if given. The reader has no header row, so the headers read from the start of
+// the file come with it.
+pub fn resumed_transaction_reader(
+    input: &Path,
+    delimiter: u8,
+    max_line_length: u64,
+    line: u64,
+    byte: u64,
+    end: Option<u64>,
+) -> io::Result<(StringRecord, TransactionReader)> {
+    let headers = transaction_reader(input, delimiter, max_line_length, false)?
+        .headers()?
+        .clone();
+    let reader = open_input_at(input, byte)?;
+    let reader = match end {
+        Some(end) => Box::new(reader.take(end.saturating_sub(byte))),
+        None => reader,
+    };
+    let filter = LineFilter::resumed(
+        Box::new(BufReader::new(reader)) as Box<dyn BufRead>,
+        delimiter,
+        max_line_length,
+        line,
+        byte,
+    );
+    let rdr = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .from_reader(filter);
+    Ok((headers, rdr))
+}
+
+// A run of whole records in a CSV input: those after its first `line` lines, from byte
+// `start` up to byte `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputPart {
+    pub line: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+// Splits the records of a CSV file into at most `parts` runs of about equal size, for
+// parsing one input on several threads. A run only ends after a line once none of the
+// MAX_QUOTED_LINES lines up to it could open a quoted field, so no record, nor the lines a
+// LineFilter reads ahead for a stray quote, crosses into the next run and each run parses
+// as it would within the whole file. Stdin, pipes and compressed input cannot be read from
+// an offset and come back empty, as does a file whose header row opens a quoted field.
+pub fn split_input(
+    input: &Path,
+    delimiter: u8,
+    max_line_length: u64,
+    parts: usize,
+) -> io::Result<Vec<InputPart>> {
+    let Some(file) = open_plain(input)? else {
+        return Ok(Vec::new());
+    };
+    let size = file.metadata()?.len().div_ceil(parts.max(1) as u64);
+    let mut reader = BufReader::new(file);
+    let mut buf = Vec::new();
+    let (mut line, mut byte) = (0, 0);
+    // The last line that could open a quoted field, and the start of the run being read,
+    // which is only known once the header row has been
+    let mut quoted = None;
+    let mut run: Option<(u64, u64)> = None;
+    let mut split = Vec::new();
+    loop {
+        buf.clear();
+        let len = read_line_bounded(&mut reader, &mut buf, max_line_length, line + 1)?;
+        if len == 0 {
+            break;
+        }
+        line += 1;
+        byte += len as u64;
+        if buf.contains(&b'"') && quote_open(&buf, delimiter, false) {
+            quoted = Some(line);
+        }
+
+        let Some((run_line, run_start)) = run else {
+            let is_blank = buf.iter().all(|b| matches!(b, b'\r' | b'\n'));
+            if buf.starts_with(b"#") || is_blank {
+                continue;
+            }
+            if quoted == Some(line) {
+                return Ok(Vec::new());
+            }
+            run = Some((line, byte));
+            continue;
+        };
+        let settled = quoted.is_none_or(|quoted| line - quoted >= MAX_QUOTED_LINES);
+        if settled && byte - run_start >= size && split.len() + 1 < parts {
+            split.push(InputPart {
+                line: run_line,
+                start: run_start,
+                end: byte,
+            });
+            run = Some((line, byte));
+        }
+    }
+    if let Some((line, start)) = run.filter(|&(_, start)| start < byte || split.is_empty()) {
+        split.push(InputPart {
+            line,
+            start,
+            end: byte,
+        });
+    }
+    Ok(split)
+}
+
+// Appends the next line (including its newline) to `buf`, failing without reading further
+// once it grows past `max_len` bytes. `line` is the number of the line being read, used
+// only for the error message.
+pub fn read_line_bounded<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_len: u64,
+    line: u64,
+) -> io::Result<usize> {
+    let len = reader.take(max_len + 1).read_until(b'\n', buf)?;
+    if len as u64 > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "line {} exceeds the maximum line length of {} bytes",
+                line, max_len
+            ),
+        ));
+    }
+    Ok(len)
+}
+
+// A source line kept away from the CSV parser because it cannot be parsed safely
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quarantined {
+    pub line: u64,
+    pub bytes: Range<u64>,
+    pub reason: &'static str,
+}
+
+// Running totals of lines/bytes passed to the parser and dropped before it, recorded at
+// the start of each run of dropped lines
+#[derive(Debug)]
+struct DroppedBlock {
+    emitted_lines: u64,
+    emitted_bytes: u64,
+    dropped_lines: u64,
+    dropped_bytes: u64,
+}
+
+// Longest a quoted field may run across lines. A quote still open after this many lines, or
+// once the record outgrows the line length limit, is taken to be a stray one.
+const MAX_QUOTED_LINES: u64 = 32;
+
+// Feeds the CSV parser one record at a time (usually a single line, more when a quoted field
+// spans lines), dropping lines it should never see while remembering where they were, so
+// every record can be traced back to its line and byte offset in the source input. Dropped
+// are:
+// - `#` comment lines and blank lines (the csv crate's own comment support positions a
+//   record at the start of any comment block preceding it, so line numbers would drift)
+// - a line opening a quoted field that does not close within MAX_QUOTED_LINES lines or
+//   before the end of the input, which would otherwise swallow the lines after it into one
+//   field; it is quarantined so the caller can report it, and the lines after it are read
+//   as records again
+pub struct LineFilter<R> {
+    inner: R,
+    delimiter: u8,
+    max_line_length: u64,
+    line: Vec<u8>,
+    pos: usize,
+    // Lines read ahead looking for the end of a quoted field that never closed, to be read
+    // again as records of their own
+    read_ahead: VecDeque<Vec<u8>>,
+    source_lines: u64,
+    source_bytes: u64,
+    emitted_lines: u64,
+    emitted_bytes: u64,
+    dropped: Vec<DroppedBlock>,
+    quarantined: VecDeque<Quarantined>,
+}
+
+impl<R: BufRead> LineFilter<R> {
+    pub fn new(inner: R, delimiter: u8, max_line_length: u64) -> LineFilter<R> {
+        LineFilter {
+            inner,
+            delimiter,
+            max_line_length,
+            line: Vec::new(),
+            pos: 0,
+            read_ahead: VecDeque::new(),
+            source_lines: 0,
+            source_bytes: 0,
+            emitted_lines: 0,
+            emitted_bytes: 0,
+            dropped: Vec::new(),
+            quarantined: VecDeque::new(),
+        }
+    }
+
+    // A filter over input that continues a source after its first `lines` lines, `bytes`
+    // bytes long, so positions still map back to the whole source
+    pub fn resumed(
+        inner: R,
+        delimiter: u8,
+        max_line_length: u64,
+        lines: u64,
+        bytes: u64,
+    ) -> LineFilter<R> {
+        let mut filter = LineFilter::new(inner, delimiter, max_line_length);
+        filter.source_lines = lines;
+        filter.source_bytes = bytes;
+        filter.dropped.push(DroppedBlock {
+            emitted_lines: 0,
+            emitted_bytes: 0,
+            dropped_lines: lines,
+            dropped_bytes: bytes,
+        });
+        filter
+    }
+
+    // Maps a line number as seen by the CSV parser to the line number in the source input
+    pub fn source_line(&self, parsed_line: u64) -> u64 {
+        let blocks_before = self
+            .dropped
+            .partition_point(|block| block.emitted_lines < parsed_line);
+        match blocks_before.checked_sub(1) {
+            Some(i) => parsed_line + self.dropped[i].dropped_lines,
+            None => parsed_line,
+        }
+    }
+
+    // Maps a byte offset as seen by the CSV parser to the byte offset in the source input
+    pub fn source_byte(&self, parsed_byte: u64) -> u64 {
+        let blocks_before = self
+            .dropped
+            .partition_point(|block| block.emitted_bytes <= parsed_byte);
+        match blocks_before.checked_sub(1) {
+            Some(i) => parsed_byte + self.dropped[i].dropped_bytes,
+            None => parsed_byte,
+        }
+    }
+
+    // Takes the next quarantined line that precedes the given source line, in input order
+    pub fn pop_quarantined(&mut self, before_line: u64) -> Option<Quarantined> {
+        match self.quarantined.front() {
+            Some(q) if q.line < before_line => self.quarantined.pop_front(),
+            _ => None,
+        }
+    }
+
+    // Appends the next source line to the current record, returning its length
+    fn next_line(&mut self) -> io::Result<u64> {
+        let len = match self.read_ahead.pop_front() {
+            Some(line) => {
+                self.line.extend_from_slice(&line);
+                line.len()
+            }
+            None => read_line_bounded(
+                &mut self.inner,
+                &mut self.line,
+                self.max_line_length,
+                self.source_lines + 1,
+            )?,
+        } as u64;
+        if len > 0 {
+            self.source_lines += 1;
+            self.source_bytes += len;
+        }
+        Ok(len)
+    }
+
+    fn record_dropped(&mut self, lines: u64, len: u64) {
+        match self.dropped.last_mut() {
+            Some(last) if last.emitted_lines == self.emitted_lines => {
+                last.dropped_lines += lines;
+                last.dropped_bytes += len;
+            }
+            last => {
+                let (before, bytes) = last.map_or((0, 0), |b| (b.dropped_lines, b.dropped_bytes));
+                self.dropped.push(DroppedBlock {
+                    emitted_lines: self.emitted_lines,
+                    emitted_bytes: self.emitted_bytes,
+                    dropped_lines: before + lines,
+                    dropped_bytes: bytes + len,
+                });
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for LineFilter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            let first_line = self.source_lines + 1;
+            let start = self.source_bytes;
+            let first_len = self.next_line()?;
+            if first_len == 0 {
+                return Ok(0);
+            }
+
+            let is_blank = self.line.iter().all(|b| matches!(b, b'\r' | b'\n'));
+            if self.line.starts_with(b"#") || is_blank {
+                self.line.clear();
+                self.record_dropped(1, first_len);
+                continue;
+            }
+
+            // A quoted field may span lines, so a record with one still open takes in the
+            // following lines until it closes
+            let mut lines = 1;
+            let mut open = quote_open(&self.line, self.delimiter, false);
+            while open && lines < MAX_QUOTED_LINES && self.line.len() as u64 <= self.max_line_length
+            {
+                let read = self.line.len();
+                if self.next_line()? == 0 {
+                    break;
+                }
+                lines += 1;
+                open = quote_open(&self.line[read..], self.delimiter, true);
+            }
+
+            if open {
+                // Only the line that opened the field is dropped; the rest are read again
+                let rest = self.line.split_off(first_len as usize);
+                for line in rest.split_inclusive(|&b| b == b'\n').rev() {
+                    self.read_ahead.push_front(line.to_vec());
+                }
+                self.source_lines = first_line;
+                self.source_bytes = start + first_len;
+                self.quarantined.push_back(Quarantined {
+                    line: first_line,
+                    bytes: start..self.source_bytes,
+                    reason: "unterminated quoted field",
+                });
+                self.line.clear();
+                self.record_dropped(1, first_len);
+            } else {
+                self.emitted_lines += lines;
+                self.emitted_bytes += self.source_bytes - start;
+            }
+        }
+
+        let n = (&self.line[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Whether a quoted field is still open after `bytes`, given whether one was open before
+// them. As in the csv crate, a quote only opens a field as the field's first byte, and a
+// doubled quote inside one is an escaped quote; a quote anywhere else is data.
+fn quote_open(bytes: &[u8], delimiter: u8, mut open: bool) -> bool {
+    let mut field_start = !open;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if open {
+            if b == b'"' {
+                if bytes.get(i + 1) == Some(&b'"') {
+                    i += 1;
+                } else {
+                    open = false;
+                }
+            }
+        } else if b == b'"' && field_start {
+            open = true;
+        }
+        field_start = !open && b == delimiter;
+        i += 1;
+    }
+    open
+}
+
+// Whether a CSV error only affects the current row, so reading can resume at the next line
+pub fn is_corrupted_row(e: &csv::Error) -> bool {
+    matches!(
+        e.kind(),
+        csv::ErrorKind::UnequalLengths { .. } | csv::ErrorKind::Utf8 { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comment_lines_are_dropped_and_mapped() {
+        let data = "a\n# one\nb\nc\n# two\n\n# three\nd";
+        let mut filter = LineFilter::new(data.as_bytes(), b',', DEFAULT_MAX_LINE_LENGTH);
+        let mut out = String::new();
+        filter.read_to_string(&mut out).unwrap();
+
+        assert_eq!(out, "a\nb\nc\nd");
+        assert_eq!(filter.source_line(1), 1);
+        assert_eq!(filter.source_line(2), 3);
+        assert_eq!(filter.source_line(3), 4);
+        assert_eq!(filter.source_line(4), 8);
+        assert_eq!(filter.source_byte(0), 0);
+        assert_eq!(filter.source_byte(2), 8);
+        assert_eq!(filter.source_byte(6), 27);
+    }
+
+    #[test]
+    fn test_quoted_fields_span_lines() {
+        let data = "a,b\n\"two\n# lines\",1\n# comment\nc,d\n";
+        let mut filter = LineFilter::new(data.as_bytes(), b',', DEFAULT_MAX_LINE_LENGTH);
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(&mut filter);
+        let records: Vec<(u64, csv::StringRecord)> = rdr
+            .records()
+            .map(|r| {
+                let r = r.unwrap();
+                (r.position().unwrap().line(), r)
+            })
+            .collect();
+        drop(rdr);
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(&records[1].1[0], "two\n# lines");
+        assert_eq!(filter.source_line(records[1].0), 2);
+        assert_eq!(filter.source_line(records[2].0), 5);
+        assert_eq!(filter.pop_quarantined(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_unterminated_quoted_field_is_quarantined_alone() {
+        let data = "a,b\nc,d\n\"broken,1\ne,f\n";
+        let mut filter = LineFilter::new(data.as_bytes(), b',', DEFAULT_MAX_LINE_LENGTH);
+        let mut out = String::new();
+        filter.read_to_string(&mut out).unwrap();
+
+        assert_eq!(out, "a,b\nc,d\ne,f\n");
+        assert_eq!(filter.pop_quarantined(3), None);
+        assert_eq!(
+            filter.pop_quarantined(4),
+            Some(Quarantined {
+                line: 3,
+                bytes: 8..18,
+                reason: "unterminated quoted field",
+            })
+        );
+        assert_eq!(filter.pop_quarantined(u64::MAX), None);
+        assert_eq!(filter.source_line(3), 4);
+        assert_eq!(filter.source_byte(8), 18);
+    }
+
+    #[test]
+    fn test_quotes_inside_unquoted_fields_are_data() {
+        let data = "a,b\n1,2\"0\n3,\"4\"\"\"\n5,6\n";
+        let mut filter = LineFilter::new(data.as_bytes(), b',', DEFAULT_MAX_LINE_LENGTH);
+        let mut out = String::new();
+        filter.read_to_string(&mut out).unwrap();
+
+        assert_eq!(out, data);
+        assert_eq!(filter.pop_quarantined(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_quoted_field_open_too_long_is_a_stray_quote() {
+        let rows: String = (0..MAX_QUOTED_LINES).map(|i| format!("{i},x\n")).collect();
+        let data = format!("a,b\n\"stray,1\n{rows}\"late\",2\n");
+        let mut filter = LineFilter::new(data.as_bytes(), b',', DEFAULT_MAX_LINE_LENGTH);
+        let mut out = String::new();
+        filter.read_to_string(&mut out).unwrap();
+
+        assert_eq!(out, format!("a,b\n{rows}\"late\",2\n"));
+        let quarantined = filter.pop_quarantined(u64::MAX).unwrap();
+        assert_eq!((quarantined.line, quarantined.bytes), (2, 4..13));
+        assert_eq!(filter.pop_quarantined(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_split_input_ends_runs_clear_of_quotes() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows =
+            |from: u64, to: u64| -> String { (from..to).map(|i| format!("{i},x\n")).collect() };
+        let data = format!("# note\na,b\n{}\"stray,1\n{}", rows(0, 40), rows(40, 200));
+        let path = dir.path().join("input.csv");
+        std::fs::write(&path, &data).unwrap();
+
+        let parts = split_input(&path, b',', DEFAULT_MAX_LINE_LENGTH, 4).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!((parts[0].line, parts[0].start), (2, 11));
+        assert_eq!(parts[3].end, data.len() as u64);
+        for pair in parts.windows(2) {
+            let (run, next) = (pair[0], pair[1]);
+            assert_eq!(run.end, next.start);
+            let lines = data[run.start as usize..run.end as usize].lines().count() as u64;
+            assert_eq!(run.line + lines, next.line);
+            // The stray quote is on line 43, and no run may end among the lines it reads ahead
+            assert!(
+                !(43..43 + MAX_QUOTED_LINES).contains(&next.line),
+                "{:?}",
+                parts
+            );
+        }
+
+        // Compressed input cannot be read from an offset
+        let gzip = dir.path().join("input.csv.gz");
+        std::fs::write(&gzip, &data).unwrap();
+        assert!(split_input(&gzip, b',', DEFAULT_MAX_LINE_LENGTH, 4)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_overlong_lines_fail_fast() {
+        let data = "a,b\nc,d\n0123456789\n";
+        let mut filter = LineFilter::new(data.as_bytes(), b',', 8);
+        let mut out = String::new();
+        let err = filter.read_to_string(&mut out).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "line 3 exceeds the maximum line length of 8 bytes"
+        );
+    }
+
+    #[test]
+    fn test_compressed_input_is_detected_and_decoded() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let data = b"type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(data).unwrap();
+        let zstd = zstd::encode_all(&data[..], 0).unwrap();
+
+        // Named by extension, and misnamed so only the magic bytes give them away
+        for (name, bytes) in [
+            ("a.csv.gz", gzip.finish().unwrap()),
+            ("b.csv", zstd.clone()),
+            ("c.csv.zst", zstd),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let mut out = Vec::new();
+            open_input(&path).unwrap().read_to_end(&mut out).unwrap();
+            assert_eq!(out, data, "{}", name);
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_input_reads_like_buffered_input() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let data = b"type,client,tx,amount\ndeposit,1,1,1.0\n";
+        let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(data).unwrap();
+
+        for (name, bytes) in [
+            ("a.csv", data.to_vec()),
+            ("b.csv.gz", gzip.finish().unwrap()),
+            ("empty.csv", Vec::new()),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let read = |mmap| {
+                let mut out = Vec::new();
+                open_buffered(&path, mmap)
+                    .unwrap()
+                    .read_to_end(&mut out)
+                    .unwrap();
+                out
+            };
+            assert_eq!(read(true), read(false), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Account, Accounts, ClientId, EngineError, Record, Storage, StorageError, TransactionId, TxType,
+};
+
+// A state transition the engine applied to an account. With EngineConfig::journal set the
+// engine records one per change, in order, and `replay` rebuilds the accounts from them
+// alone. Records that were refused, or queued for a locked account, change nothing and
+// leave no event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    DepositApplied {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Decimal,
+    },
+    WithdrawalApplied {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Decimal,
+    },
+    DisputeOpened {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Decimal,
+    },
+    DisputeResolved {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Decimal,
+    },
+    ChargebackExecuted {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Decimal,
+    },
+    AccountUnlocked {
+        client: ClientId,
+    },
+    AccountsMerged {
+        from: ClientId,
+        into: ClientId,
+    },
+    // The reserve an account holds after the event before it, with EngineConfig::reserve set
+    ReserveHeld {
+        client: ClientId,
+        amount: Decimal,
+    },
+}
+
+// The event for a record the engine just applied. Disputes, resolves and chargebacks carry
+// the amount of the transaction they refer to.
+pub(crate) fn applied(record: &Record, storage: &impl Storage) -> Result<Event, StorageError> {
+    let (client, tx) = (record.client, record.tx);
+    let amount = match record.tx_type {
+        TxType::Deposit | TxType::Withdrawal => record.amount,
+        _ => storage
+            .transaction(tx)?
+            .and_then(|referenced| referenced.amount),
+    }
+    .unwrap_or_default();
+
+    Ok(match record.tx_type {
+        TxType::Deposit => Event::DepositApplied { client, tx, amount },
+        TxType::Withdrawal => Event::WithdrawalApplied { client, tx, amount },
+        TxType::Dispute => Event::DisputeOpened { client, tx, amount },
+        TxType::Resolve => Event::DisputeResolved { client, tx, amount },
+        TxType::Chargeback => Event::ChargebackExecuted { client, tx, amount },
+    })
+}
+
+// Applies one journal event to the accounts, under the overdraft limit the journaled run had.
+// Fails only if the journal does not describe a history the engine could have produced,
+// such as a withdrawal of funds never deposited.
+pub fn replay(
+    accounts: &mut Accounts,
+    event: &Event,
+    overdraft_limit: Decimal,
+) -> Result<(), EngineError> {
+    match *event {
+        Event::DepositApplied { client, amount, .. } => account(accounts, client).deposit(amount),
+        Event::WithdrawalApplied { client, amount, .. } => {
+            account(accounts, client).withdraw(amount, overdraft_limit)
+        }
+        Event::DisputeOpened { client, amount, .. } => {
+            account(accounts, client).apply_dispute(amount)
+        }
+        Event::DisputeResolved { client, amount, .. } => {
+            account(accounts, client).resolve_dispute(amount)
+        }
+        Event::ChargebackExecuted { client, amount, .. } => {
+            account(accounts, client).chargeback(amount)
+        }
+        Event::AccountUnlocked { client } => {
+            account(accounts, client).locked = false;
+            Ok(())
+        }
+        Event::AccountsMerged { from, into } => {
+            let merged = accounts.remove(&from).unwrap_or_else(Account::new);
+            account(accounts, into).absorb(merged);
+            Ok(())
+        }
+        Event::ReserveHeld { client, amount } => {
+            account(accounts, client).hold_reserve(amount);
+            Ok(())
+        }
+    }
+}
+
+// The first event for a client opened its account
+fn account(accounts: &mut Accounts, client: ClientId) -> &mut Account {
+    accounts.entry(client).or_insert_with(Account::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, PaymentsEngine};
+
+    #[test]
+    fn test_replaying_the_journal_rebuilds_the_accounts() {
+        let mut engine = PaymentsEngine::with_config(EngineConfig {
+            journal: true,
+            ..EngineConfig::default()
+        });
+        let record = |tx_type, client, tx, amount| Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+        let records = [
+            record(TxType::Deposit, 1, 1, Some(Decimal::new(10, 0))),
+            record(TxType::Deposit, 2, 2, Some(Decimal::new(5, 0))),
+            record(TxType::Withdrawal, 1, 3, Some(Decimal::new(3, 0))),
+            // Refused: insufficient funds
+            record(TxType::Withdrawal, 2, 4, Some(Decimal::new(9, 0))),
+            record(TxType::Dispute, 2, 2, None),
+            record(TxType::Chargeback, 2, 2, None),
+        ];
+        for r in &records {
+            let _ = engine.process(r);
+        }
+        engine.merge_accounts(2, 1).unwrap();
+        engine.process_batch(&records[..1]);
+
+        let events = engine.take_events();
+        assert_eq!(events.len(), 6);
+        assert_eq!(
+            events[4],
+            Event::ChargebackExecuted {
+                client: 2,
+                tx: 2,
+                amount: Decimal::new(5, 0)
+            }
+        );
+        assert!(engine.take_events().is_empty());
+
+        let mut accounts = Accounts::default();
+        for event in &events {
+            replay(&mut accounts, event, Decimal::ZERO).unwrap();
+        }
+        assert_eq!(&accounts, engine.accounts());
+    }
+}
@@ -0,0 +1,51 @@
+// Core payments engine, usable as a library independently of the CLI in `main.rs`.
+mod account;
+mod config;
+mod engine;
+mod error;
+mod journal;
+mod referenced;
+#[cfg(feature = "sled")]
+mod sled_storage;
+mod spill;
+#[cfg(feature = "sqlite")]
+mod sqlite_storage;
+mod state;
+mod storage;
+#[cfg(feature = "async")]
+mod stream;
+mod transaction;
+mod validation;
+mod window;
+
+pub use account::{Account, Holds};
+pub use config::{EngineConfig, ExcessPrecision, LockedPolicy, ZeroAmountPolicy, MAX_PRECISION};
+pub use engine::PaymentsEngine;
+pub use error::{EngineError, MergeError, StateError, StorageError};
+pub use journal::{replay, Event};
+pub use referenced::ReferencedStorage;
+#[cfg(feature = "sled")]
+pub use sled_storage::SledStorage;
+pub use spill::SpillStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_storage::SqliteStorage;
+pub use state::{inspect_state, migrate_state, StateSummary, STATE_VERSION};
+pub use storage::{MemoryStorage, Storage};
+#[cfg(feature = "async")]
+pub use stream::read_records;
+pub use transaction::{Record, TxType};
+pub use validation::{MaxAmount, MaxPrecision, NonNegative, Validator, Validators};
+pub use window::{DedupWindow, WindowedStorage};
+
+pub type ClientId = u16;
+pub type TransactionId = u32;
+
+// The hasher of the maps keyed by client and transaction id. SipHash by default; the `ahash`
+// feature swaps in aHash, which hashes integer keys several times faster and is still seeded
+// randomly per map.
+#[cfg(feature = "ahash")]
+pub type HashState = ahash::RandomState;
+#[cfg(not(feature = "ahash"))]
+pub type HashState = std::collections::hash_map::RandomState;
+
+pub type Accounts = std::collections::HashMap<ClientId, Account, HashState>;
@@ -1,485 +1,1300 @@
+mod accounts;
+mod audit;
+#[cfg(feature = "avro")]
+mod avro;
+mod bench;
+mod checkpoint;
+mod cli;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod diff;
+mod enrich;
+mod events;
+mod generate;
+mod input;
+mod output;
+#[cfg(feature = "proto")]
+mod proto;
+mod rejects;
+mod replay;
+mod routes;
+mod sample;
+mod search;
+mod series;
+mod shard;
+#[cfg(feature = "postgres")]
+mod sink;
+mod source;
+#[cfg(feature = "xlsx")]
+mod spreadsheet;
+mod summary;
+mod wal;
+
+use accounts::{read_accounts, read_aliases, AccountRow};
+use audit::{AuditEvent, AuditLog};
+use checkpoint::{Checkpointing, Checkpoints, Start, WalTail};
+use clap::{CommandFactory, Parser};
+use cli::{
+    BenchCommand, Cli, Command, InputArgs, InspectArgs, JournalCommand, MergeArgs, MigrateArgs,
+    ProcessOptions, RecoverArgs, ReportArgs, SnapshotCommand, TxCommand, ValidateArgs,
+};
 use csv::ReaderBuilder;
+use enrich::{Annotations, Enrichers};
+use events::JournalWriter;
+#[cfg(feature = "sled")]
+use exchange_test::SledStorage;
+#[cfg(feature = "sqlite")]
+use exchange_test::SqliteStorage;
+use exchange_test::{
+    inspect_state, migrate_state, Accounts, DedupWindow, EngineConfig, EngineError, HashState,
+    MemoryStorage, PaymentsEngine, ReferencedStorage, SpillStorage, Storage, TransactionId, TxType,
+    WindowedStorage,
+};
+use input::{expand_inputs, STDIO_PATH};
+use log::warn;
+use output::{write_accounts, write_atomically, AccountState};
+use rejects::RejectWriter;
 use rust_decimal::Decimal;
-use serde::Deserialize;
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
-use std::env;
+use sample::Sampler;
+use series::BalanceSeries;
+use source::{open_source, resume_source, InputFormat, Row};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fs::File;
-use std::io;
-use std::str::FromStr;
-
-type ClientId = u16;
-type TransactionId = u32;
-
-// Represents the different types of transactions
-#[derive(Debug)]
-enum TxType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
-}
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use summary::{RunStats, Summary};
+use wal::WalWriter;
 
-impl FromStr for TxType {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "deposit" => Ok(TxType::Deposit),
-            "withdrawal" => Ok(TxType::Withdrawal),
-            "dispute" => Ok(TxType::Dispute),
-            "resolve" => Ok(TxType::Resolve),
-            "chargeback" => Ok(TxType::Chargeback),
-            _ => Err("Unknown transaction type"),
-        }
-    }
-}
+// Reads transactions from a CSV file provided as a command line argument (or stdin)
+// Outputs the final state of all accounts in CSV format to stdout
+fn main() {
+    let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.log_level)
+        .init();
 
-// Represents a transaction record parsed from the CSV input
-#[derive(Debug, Deserialize, Clone)]
-struct Record {
-    #[serde(rename = "type")]
-    tx_type: String,
-    client: ClientId,
-    tx: TransactionId,
-    #[serde(deserialize_with = "csv::invalid_option")]
-    amount: Option<Decimal>,
+    if let Err(e) = run(cli) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-// Represents a client's account, storing/managing balances and status
-#[derive(Debug, Clone)]
-struct Account {
-    available: Decimal,
-    held: Decimal,
-    total: Decimal,
-    locked: bool,
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Some(Command::Process(args)) => process(&args.inputs, &args.options),
+        Some(Command::Validate(args)) => validate(&args),
+        Some(Command::Replay(args)) => replay::replay(&args),
+        Some(Command::Generate(args)) => match &args.output {
+            Some(path) => {
+                write_atomically(path, |file| generate::generate_transactions(file, &args))
+            }
+            None => generate::generate_transactions(io::stdout(), &args),
+        },
+        Some(Command::Report(args)) => report(&args),
+        Some(Command::Diff(args)) => diff::diff(&args),
+        Some(Command::MergeAccounts(args)) => merge_accounts(&args),
+        Some(Command::Recover(args)) => recover(&args),
+        Some(Command::Snapshot(SnapshotCommand::Inspect(args))) => inspect_snapshot(&args),
+        Some(Command::Snapshot(SnapshotCommand::Migrate(args))) => migrate_snapshot(&args),
+        Some(Command::Tx(TxCommand::Search(args))) => search::search_transactions(&args),
+        Some(Command::Journal(JournalCommand::Replay(args))) => events::replay_journal(&args),
+        Some(Command::Bench(BenchCommand::Compare(args))) => bench::compare(&args),
+        // Without an input path we read stdin, unless nothing is being piped in
+        None if cli.inputs.is_empty() && io::stdin().is_terminal() => {
+            Cli::command().print_help()?;
+            std::process::exit(2);
+        }
+        None if cli.inputs.is_empty() => process(&[PathBuf::from(STDIO_PATH)], &cli.options),
+        None => process(&cli.inputs, &cli.options),
+    }
 }
 
-impl Account {
-    fn new() -> Account {
-        Account {
-            available: Decimal::new(0, 0),
-            held: Decimal::new(0, 0),
-            total: Decimal::new(0, 0),
-            locked: false,
+fn process(inputs: &[PathBuf], options: &ProcessOptions) -> Result<(), Box<dyn Error>> {
+    let inputs = expand_inputs(inputs)?;
+    if options.dedup_window().is_some() && options.sled_dir.is_none() && options.db.is_none() {
+        return Err(
+            "--dedup-window and --dedup-ttl need --sled-dir or --db to hold the \
+                    transactions moved out of memory"
+                .into(),
+        );
+    }
+    if let Some(shards) = options.shards {
+        return write_output(
+            shard::process_sharded(
+                &inputs,
+                options,
+                shards.into(),
+                options.parse_threads.unwrap_or(1).into(),
+            )?,
+            options,
+        );
+    }
+    if let Some(dir) = &options.sled_dir {
+        return process_on_disk(&inputs, options, dir);
+    }
+    if let Some(path) = &options.db {
+        return process_in_db(&inputs, options, path);
+    }
+    if let Some(budget) = options.memory_budget {
+        let storage = match &options.spill_dir {
+            Some(dir) => SpillStorage::new_in(dir)?,
+            None => SpillStorage::new()?,
+        };
+        let window = DedupWindow::for_memory(budget << 20);
+        return process_on_storage(&inputs, options, WindowedStorage::new(storage, window));
+    }
+    if options.two_pass {
+        if inputs.iter().any(|input| input == Path::new(STDIO_PATH)) {
+            return Err("--two-pass needs input files; stdin cannot be read twice".into());
         }
+        let disputed = disputed_transactions(&inputs, &options.input_args)?;
+        let storage = ReferencedStorage::new(MemoryStorage::default(), disputed);
+        return process_on_storage(&inputs, options, storage);
     }
 
-    fn deposit(&mut self, amount: Decimal) -> Result<(), &'static str> {
-        if self.locked {
-            return Err("Deposit error: Account is locked");
+    let target = options.checkpoint.clone().zip(options.checkpoint_every);
+    if target.is_some() || options.resume.is_some() {
+        if options.input_args.input_format != InputFormat::Csv {
+            return Err("checkpoints are only supported for CSV input".into());
+        }
+        if inputs.iter().any(|input| input == Path::new(STDIO_PATH)) {
+            return Err("checkpoints need input files; stdin cannot be read again".into());
         }
-        self.available += amount;
-        self.total += amount;
-        Ok(())
     }
+    let mut checkpoints = Checkpoints::new(&inputs, target);
 
-    fn withdraw(&mut self, amount: Decimal) -> Result<(), &'static str> {
-        if self.locked {
-            return Err("Withdrawal error: Account is locked");
+    // A checkpoint or loaded state continues a previous run and initial accounts carry over
+    // its closing balances; otherwise processing starts from scratch
+    let engine = match (
+        &options.resume,
+        &options.load_state,
+        &options.initial_accounts,
+    ) {
+        (Some(path), _, _) => checkpoints.resume(path, options.engine_config())?,
+        (None, Some(path), _) => {
+            let file = BufReader::new(File::open(path)?);
+            PaymentsEngine::load_state(file, options.engine_config())
+                .map_err(|e| format!("Failed to load state from {}: {}", path.display(), e))?
         }
-        if self.available >= amount {
-            self.available -= amount;
-            self.total -= amount;
-            Ok(())
-        } else {
-            Err("Withdrawal error: Insufficient funds for withdrawal")
+        (None, None, Some(path)) => {
+            PaymentsEngine::with_accounts(read_accounts(path)?, options.engine_config())
         }
+        (None, None, None) => PaymentsEngine::with_config(options.engine_config()),
+    };
+    let engine = process_records(&inputs, options, engine, Some(&mut checkpoints))?;
+
+    if let Some(path) = &options.save_state {
+        write_atomically(path, |file| Ok(engine.save_state(BufWriter::new(file))?))?;
     }
+    write_output(engine.finalize(), options)
+}
 
-    fn apply_dispute(&mut self, amount: Decimal) -> Result<(), &'static str> {
-        if self.locked {
-            return Err("Dispute error: Account is locked");
-        }
-        if self.available >= amount {
-            self.available -= amount;
-            self.held += amount;
-            Ok(())
-        } else {
-            Err("Dispute error: Insufficient available funds for dispute")
+// The first pass of --two-pass: the ids of all transactions a dispute row refers to. Rows
+// that do not parse are left to the second pass to report.
+fn disputed_transactions(
+    inputs: &[PathBuf],
+    input_args: &InputArgs,
+) -> Result<HashSet<TransactionId, HashState>, Box<dyn Error>> {
+    let mut disputed = HashSet::default();
+    for input in inputs {
+        let mut source = open_source(input, input_args)?;
+        while let Some((_, row)) = source.next_row()? {
+            if let Row::Parsed(record) = row {
+                if record.tx_type == TxType::Dispute {
+                    disputed.insert(record.tx);
+                }
+            }
         }
     }
+    Ok(disputed)
+}
 
-    fn resolve_dispute(&mut self, amount: Decimal) -> Result<(), &'static str> {
-        if self.locked {
-            return Err("Resolve error: Account is locked");
+// Processes with the transaction history in a sled database in a fresh directory under
+// `dir`, which is removed again at the end of the run
+#[cfg(feature = "sled")]
+fn process_on_disk(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir_in(dir)?;
+    process_on_storage(inputs, options, SledStorage::open(dir.path())?)
+}
+
+#[cfg(not(feature = "sled"))]
+fn process_on_disk(_: &[PathBuf], _: &ProcessOptions, _: &Path) -> Result<(), Box<dyn Error>> {
+    Err("--sled-dir requires building with the `sled` feature".into())
+}
+
+// Processes on top of the state persisted in a SQLite database, committing it after each input
+#[cfg(feature = "sqlite")]
+fn process_in_db(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    process_on_storage(inputs, options, SqliteStorage::open(path)?)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn process_in_db(_: &[PathBuf], _: &ProcessOptions, _: &Path) -> Result<(), Box<dyn Error>> {
+    Err("--db requires building with the `sqlite` feature".into())
+}
+
+// Processes on storage other than memory, behind a window of recent transactions in memory
+// when --dedup-window or --dedup-ttl bound it
+fn process_on_storage<S: Storage>(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    storage: S,
+) -> Result<(), Box<dyn Error>> {
+    let accounts = match options.dedup_window() {
+        Some(window) => {
+            let storage = WindowedStorage::new(storage, window);
+            let engine = PaymentsEngine::with_storage(storage, options.engine_config())?;
+            process_records(inputs, options, engine, None)?.finalize()
         }
-        if self.held >= amount {
-            self.held -= amount;
-            self.available += amount;
-            Ok(())
-        } else {
-            Err("Resolve error: Insufficient held funds for resolve")
+        None => {
+            let engine = PaymentsEngine::with_storage(storage, options.engine_config())?;
+            process_records(inputs, options, engine, None)?.finalize()
         }
-    }
+    };
+    write_output(accounts, options)
+}
 
-    fn chargeback(&mut self, amount: Decimal) -> Result<(), &'static str> {
-        if self.locked {
-            return Err("Chargeback error: Account is locked");
+// Runs the (glob-expanded) inputs through the engine and writes the rejects, sample and
+// summary side outputs
+fn process_records<S: Storage>(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    mut engine: PaymentsEngine<S>,
+    checkpoints: Option<&mut dyn Checkpointing<S>>,
+) -> Result<PaymentsEngine<S>, Box<dyn Error>> {
+    if let Some(path) = &options.aliases {
+        for row in read_aliases(path)? {
+            engine.add_alias(row.alias, row.client)?;
         }
-        if self.held >= amount {
-            self.total -= amount;
-            self.held -= amount;
-            self.locked = true;
-            Ok(())
+    }
+    let mut audit = match &options.audit_log {
+        Some(path) => Some(AuditLog::create(path)?),
+        None => None,
+    };
+    let enrichers = options.enrichers()?;
+    let mut rejects = match &options.rejects {
+        Some(path) => Some(RejectWriter::create(path, !enrichers.is_empty())?),
+        None => None,
+    };
+    let mut sampler = match (options.sample, &options.sample_out) {
+        (Some(rate), Some(path)) => Some(Sampler::create(path, rate, options.sample_seed)?),
+        _ => None,
+    };
+    let mut wal = match (&options.wal, checkpoints.as_deref()) {
+        (Some(path), Some(checkpoints)) => Some(checkpoints.open_wal(path)?),
+        (Some(path), None) => Some(WalWriter::create(path)?),
+        (None, _) => None,
+    };
+    let mut journal = match &options.journal {
+        Some(path) => Some(JournalWriter::open(path)?),
+        None => None,
+    };
+    let mut series = match &options.balance_series {
+        Some(path) => Some(BalanceSeries::create(path)?),
+        None => None,
+    };
+    let outputs = SideOutputs {
+        rejects: rejects.as_mut(),
+        sampler: sampler.as_mut(),
+        audit: audit.as_mut(),
+        wal: wal.as_mut(),
+        journal: journal.as_mut(),
+        enrichers: Some(&enrichers).filter(|e| !e.is_empty()),
+        // Reborrowed for the lifetime of the other outputs
+        checkpoints: checkpoints.map(|c| c as _),
+        slow_tx: options.slow_tx_ms.map(Duration::from_millis),
+        series: series.as_mut(),
+    };
+    let (engine, stats) = run_engine(inputs, &options.input_args, engine, options.strict, outputs)?;
+    if let Some(audit) = audit {
+        audit.finish()?;
+    }
+    if let Some(rejects) = rejects {
+        rejects.finish()?;
+    }
+    if let Some(sampler) = sampler {
+        sampler.finish()?;
+    }
+    if let Some(journal) = journal {
+        journal.finish()?;
+    }
+    if let Some(series) = series {
+        series.finish(engine.accounts())?;
+    }
+
+    for record in engine.queued() {
+        warn!(
+            "{:?} transaction {} for locked client {} was queued but never applied",
+            record.tx_type, record.tx, record.client
+        );
+    }
+
+    if let Some(path) = &options.summary {
+        let summary = Summary::new(&stats, &engine)?;
+        if path == Path::new(STDIO_PATH) {
+            serde_json::to_writer_pretty(io::stderr(), &summary)?;
+            eprintln!();
         } else {
-            Err("Chargeback error: Insufficient held funds for chargeback")
+            write_atomically(path, |file| {
+                Ok(serde_json::to_writer_pretty(file, &summary)?)
+            })?;
         }
     }
+    Ok(engine)
 }
 
-// Reads transactions from a CSV file provided as a command line argument
-// Outputs the final state of all accounts in CSV format to stdout
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: cargo run -- <input_csv>");
-        std::process::exit(1);
+fn write_output(accounts: Accounts, options: &ProcessOptions) -> Result<(), Box<dyn Error>> {
+    let overdraft_limit = options.engine.overdraft_limit.unwrap_or_default();
+    if let Some(url) = &options.sink {
+        write_sink(url, &accounts)?;
     }
-    let input_file = &args[1];
+    let accounts = match &options.routes {
+        Some(routes) => {
+            let (rest, routed) = routes.split(accounts);
+            for (path, accounts) in routed {
+                write_atomically(path, |file| {
+                    write_accounts(
+                        &accounts,
+                        options.output_format,
+                        options.extended_output,
+                        overdraft_limit,
+                        file,
+                    )
+                })?;
+            }
+            rest
+        }
+        None => accounts,
+    };
+    match &options.output {
+        Some(path) => write_atomically(path, |file| {
+            write_accounts(
+                &accounts,
+                options.output_format,
+                options.extended_output,
+                overdraft_limit,
+                file,
+            )
+        }),
+        None => write_accounts(
+            &accounts,
+            options.output_format,
+            options.extended_output,
+            overdraft_limit,
+            io::stdout(),
+        ),
+    }
+}
 
-    let file = File::open(input_file)?;
-    let mut rdr = ReaderBuilder::new().comment(Some(b'#')).from_reader(file);
+// Sends the final account states to the database at `url`, besides the regular output
+#[cfg(feature = "postgres")]
+fn write_sink(url: &str, accounts: &Accounts) -> Result<(), Box<dyn Error>> {
+    sink::write_accounts_postgres(url, accounts)
+}
 
-    // For the purpose of this project we'll use a HashMap to store accounts and transactions
-    let mut accounts: HashMap<ClientId, Account> = HashMap::new();
-    let mut transactions: HashMap<TransactionId, Record> = HashMap::new();
-    let mut disputes: HashSet<TransactionId> = HashSet::new();
+#[cfg(not(feature = "postgres"))]
+fn write_sink(_: &str, _: &Accounts) -> Result<(), Box<dyn Error>> {
+    Err("--sink requires building with the `postgres` feature".into())
+}
 
-    // Stream each record one at a time to avoid loading the entire file into memory
-    for result in rdr.deserialize() {
-        let record: Record = result?;
-        if let Err(e) =
-            process_transaction(&record, &mut accounts, &mut transactions, &mut disputes)
-        {
-            // In the specification we are told to ignore invalid disputes, resolves, and chargebacks
-            // so I've decided to print an error message and continue processing
-            eprintln!("Failed to process transaction: {}", e);
-        }
-    }
+// Runs the input through the engine only to find rejected rows; exits non-zero if any
+fn validate(args: &ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let (_, stats) = run_engine(
+        &expand_inputs(&args.inputs)?,
+        &args.input_args,
+        PaymentsEngine::with_config(args.engine.config()),
+        false,
+        SideOutputs::default(),
+    )?;
+    println!(
+        "{} records processed, {} rejected",
+        stats.processed, stats.rejected
+    );
 
-    write_accounts_to_csv(&accounts)?;
+    if stats.rejected > 0 {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-// Processes a transaction record by updating accounts and tracking transactions.
-fn process_transaction(
-    record: &Record,
-    accounts: &mut HashMap<ClientId, Account>,
-    transactions: &mut HashMap<TransactionId, Record>,
-    disputes: &mut HashSet<TransactionId>,
-) -> Result<(), Box<dyn Error>> {
-    let tx_type = TxType::from_str(&record.tx_type)?;
-
-    match tx_type {
-        TxType::Deposit => match accounts.entry(record.client) {
-            // If the account already exists, process the deposit
-            Entry::Occupied(mut entry) => process_deposit(record, entry.get_mut(), transactions),
-            // If the account does not exist, create a new account and process the deposit, inserting the account AFTER the deposit
-            Entry::Vacant(entry) => {
-                let mut account = Account::new();
-                process_deposit(&record, &mut account, transactions)?;
-                entry.insert(account);
-                Ok(())
-            }
-        },
+// Streams every record of the inputs, one file after another, through the engine
+fn run_engine<S: Storage>(
+    inputs: &[PathBuf],
+    input_args: &InputArgs,
+    engine: PaymentsEngine<S>,
+    strict: bool,
+    outputs: SideOutputs<'_, S>,
+) -> Result<(PaymentsEngine<S>, RunStats), Box<dyn Error>> {
+    let mut run = Run {
+        engine,
+        stats: RunStats::default(),
+        strict,
+        outputs,
+    };
+
+    for (i, input) in inputs.iter().enumerate() {
+        let start = (run.outputs.checkpoints.as_deref()).map_or(Start::Beginning, |c| c.start(i));
+        let mut source = match start {
+            Start::Beginning => open_source(input, input_args)?,
+            Start::At(point) => resume_source(input, input_args, point, None)?,
+            Start::Skip => continue,
+        };
+        // Stream each record one at a time to avoid loading the entire file into memory
+        while let Some((line, row)) = source.next_row()? {
+            let annotations = match (&row, run.outputs.enrichers) {
+                (Row::Parsed(record), Some(enrichers)) => {
+                    enrichers.annotate(record, &|name| source.field(name))
+                }
+                _ => Annotations::new(),
+            };
+            let (record, outcome) = match row {
+                Row::Parsed(record) => {
+                    if let Some(series) = run.outputs.series.as_deref_mut() {
+                        let timestamp = source.field("timestamp");
+                        series
+                            .advance(timestamp.as_deref(), run.engine.accounts())
+                            .map_err(|e| format!("{} line {}: {}", input.display(), line, e))?;
+                    }
+                    if let Some(wal) = run.outputs.wal.as_deref_mut() {
+                        wal.append(&record)?;
+                    }
+                    let started = run.outputs.slow_tx.map(|_| Instant::now());
+                    let result = run.engine.process(&record);
+                    if let (Some(started), Some(threshold)) = (started, run.outputs.slow_tx) {
+                        let elapsed = started.elapsed();
+                        if elapsed >= threshold {
+                            warn!(
+                                "Slow transaction at {} line {}: {:?} client {} tx {} amount {} \
+                                 took {:?} ({})",
+                                input.display(),
+                                line,
+                                record.tx_type,
+                                record.client,
+                                record.tx,
+                                record.amount.map_or_else(String::new, |a| a.to_string()),
+                                elapsed,
+                                result.as_ref().map_or_else(|e| e.code(), |()| "applied"),
+                            );
+                            run.stats.record_slow(record.tx_type);
+                        }
+                    }
+                    // The engine cannot go on without its storage, so that is never a row reject
+                    match result {
+                        Err(e @ EngineError::Storage(_)) => return Err(e.into()),
+                        result => (Some(record), result.map_err(|e| (e.code(), e.to_string()))),
+                    }
+                }
+                Row::Invalid { code, reason } => (None, Err((code, reason))),
+            };
 
-        // All other transaction types require an existing account
-        TxType::Withdrawal | TxType::Dispute | TxType::Resolve | TxType::Chargeback => {
-            if let Some(account) = accounts.get_mut(&record.client) {
-                match tx_type {
-                    TxType::Withdrawal => process_withdrawal(&record, account, transactions),
-                    TxType::Dispute => process_dispute(&record, account, transactions, disputes),
-                    TxType::Resolve => process_resolve(&record, account, transactions, disputes),
-                    TxType::Chargeback => {
-                        process_chargeback(&record, account, transactions, disputes)
+            run.stats.record(input, record.as_ref(), outcome.is_ok());
+            // The client whose account the record went to, if it was given under an alias
+            let resolved = record.and_then(|r| run.engine.aliases().get(&r.client).copied());
+            if let (Ok(()), Some(record), Some(series)) =
+                (&outcome, record, run.outputs.series.as_deref_mut())
+            {
+                series.changed(resolved.unwrap_or(record.client));
+            }
+            if let (Some(record), Some(resolved), Some(audit)) =
+                (record, resolved, run.outputs.audit.as_deref_mut())
+            {
+                audit.write(&AuditEvent::AliasResolved {
+                    file: input.display().to_string(),
+                    line,
+                    tx: record.tx,
+                    client: record.client,
+                    resolved,
+                })?;
+            }
+            match (outcome, record, run.outputs.sampler.as_deref_mut()) {
+                (Err((code, reason)), _, _) => {
+                    let fields = source.raw_fields();
+                    run.reject(input, line, &fields, code, reason, &annotations)?
+                }
+                (Ok(()), Some(record), Some(sampler)) => {
+                    // A zero amount accepted under `--zero-amounts noop` opens no account and
+                    // has no balances to sample
+                    let client = resolved.unwrap_or(record.client);
+                    if let Some(account) = run.engine.accounts().get(&client) {
+                        sampler.offer(input, line, &record, account)?
                     }
-                    _ => unreachable!(),
                 }
-            } else {
-                Err(format!(
-                    "Account {} does not exist for transaction type {:?}",
-                    record.client, tx_type
-                )
-                .into())
+                _ => {}
+            }
+            if let Some(journal) = run.outputs.journal.as_deref_mut() {
+                for event in run.engine.take_events() {
+                    journal.write(&event, &annotations)?;
+                }
+            }
+            if let Some(checkpoints) = run.outputs.checkpoints.as_deref_mut() {
+                let wal = run.outputs.wal.as_deref();
+                checkpoints.row_done(i, source.as_ref(), &run.engine, wal)?;
             }
         }
+        // A persistent storage commits each input as a whole
+        run.engine.commit()?;
     }
+
+    Ok((run.engine, run.stats))
 }
 
-fn process_deposit(
-    record: &Record,
-    account: &mut Account,
-    transactions: &mut HashMap<TransactionId, Record>,
-) -> Result<(), Box<dyn Error>> {
-    if transactions.contains_key(&record.tx) {
-        return Err(format!("Duplicate transaction ID: {}; ignoring", record.tx).into());
-    }
+// Optional outputs written alongside the account states, and the run's checkpoints
+struct SideOutputs<'a, S> {
+    rejects: Option<&'a mut RejectWriter>,
+    sampler: Option<&'a mut Sampler>,
+    audit: Option<&'a mut AuditLog>,
+    wal: Option<&'a mut WalWriter>,
+    journal: Option<&'a mut JournalWriter>,
+    enrichers: Option<&'a Enrichers>,
+    checkpoints: Option<&'a mut dyn Checkpointing<S>>,
+    // Transactions taking at least this long to apply are logged and counted
+    slow_tx: Option<Duration>,
+    series: Option<&'a mut BalanceSeries>,
+}
 
-    if let Some(amount) = record.amount {
-        if amount.is_sign_negative() {
-            return Err(format!(
-                "Deposit amount cannot be negative; transaction {}",
-                record.tx
-            )
-            .into());
+// Derived, this would require S: Default
+impl<S> Default for SideOutputs<'_, S> {
+    fn default() -> Self {
+        SideOutputs {
+            rejects: None,
+            sampler: None,
+            audit: None,
+            wal: None,
+            journal: None,
+            enrichers: None,
+            checkpoints: None,
+            slow_tx: None,
+            series: None,
         }
+    }
+}
+
+// State threaded through a single processing run
+struct Run<'a, S> {
+    engine: PaymentsEngine<S>,
+    stats: RunStats,
+    strict: bool,
+    outputs: SideOutputs<'a, S>,
+}
 
-        // Reject deposits that exceed the precision
-        if !has_valid_precision(&amount) {
+impl<S> Run<'_, S> {
+    // Handles a row that could not be applied: aborts in strict mode, otherwise reports it
+    // and lets processing continue
+    fn reject(
+        &mut self,
+        file: &Path,
+        line: u64,
+        fields: &[String],
+        code: &str,
+        reason: String,
+        annotations: &Annotations,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.strict {
             return Err(format!(
-                "Deposit amount exceeds allowed precision; transaction {}",
-                record.tx
+                "Aborting in strict mode at {} line {}: {}",
+                file.display(),
+                line,
+                reason
             )
             .into());
         }
-
-        account.deposit(amount)?;
-        transactions.insert(record.tx, record.clone());
+        if let Some(rejects) = self.outputs.rejects.as_deref_mut() {
+            rejects.write(file, line, fields, code, &reason, annotations)?;
+        }
+        // In the specification we are told to ignore invalid disputes, resolves, and chargebacks
+        // so I've decided to log an error message and continue processing
+        warn!(
+            "Failed to process transaction at {} line {} [{}]: {}",
+            file.display(),
+            line,
+            code,
+            reason
+        );
         Ok(())
-    } else {
-        Err(format!("Deposit transaction {} missing amount", record.tx).into())
     }
 }
 
-fn process_withdrawal(
-    record: &Record,
-    account: &mut Account,
-    transactions: &mut HashMap<TransactionId, Record>,
-) -> Result<(), Box<dyn Error>> {
-    if transactions.contains_key(&record.tx) {
-        return Err(format!("Duplicate transation ID: {}; ignoring", record.tx).into());
+// Prints aggregate balances and lock counts for an accounts CSV
+fn report(args: &ReportArgs) -> Result<(), Box<dyn Error>> {
+    let mut rdr = ReaderBuilder::new().from_path(&args.accounts)?;
+
+    let (mut accounts, mut locked) = (0usize, 0usize);
+    let (mut available, mut held, mut total) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    for result in rdr.deserialize() {
+        let row: AccountRow = result?;
+        accounts += 1;
+        locked += usize::from(row.locked);
+        available += row.available;
+        held += row.held;
+        total += row.total;
     }
 
-    if let Some(amount) = record.amount {
-        if amount.is_sign_negative() {
-            return Err(format!(
-                "Withdrawal amount cannot be negative; transaction {}",
-                record.tx
-            )
-            .into());
+    println!("accounts: {}", accounts);
+    println!("locked: {}", locked);
+    println!("available: {:.4}", available);
+    println!("held: {:.4}", held);
+    println!("total: {:.4}", total);
+    Ok(())
+}
+
+// Merges two accounts in a saved engine state and prints the audit record as a JSON line
+fn merge_accounts(args: &MergeArgs) -> Result<(), Box<dyn Error>> {
+    let file = BufReader::new(File::open(&args.state)?);
+    let config = EngineConfig {
+        journal: args.journal.is_some(),
+        overdraft_limit: args.overdraft_limit.unwrap_or_default(),
+        ..EngineConfig::default()
+    };
+    let overdraft_limit = config.overdraft_limit;
+    let mut engine = PaymentsEngine::load_state(file, config)?;
+    let account = |client| {
+        engine
+            .accounts()
+            .get(&client)
+            .map(|a| AccountState::new(client, a, true, overdraft_limit))
+    };
+    let (from, into) = (account(args.from), account(args.into));
+
+    let merged = AccountState::new(
+        args.into,
+        engine.merge_accounts(args.from, args.into)?,
+        true,
+        overdraft_limit,
+    );
+    let event = AuditEvent::MergeAccounts {
+        // Both accounts exist, or the merge would have failed
+        from: Box::new(from.unwrap()),
+        into: Box::new(into.unwrap()),
+        merged: Box::new(merged),
+    };
+    write_atomically(&args.state, |file| {
+        Ok(engine.save_state(BufWriter::new(file))?)
+    })?;
+    if let Some(path) = &args.journal {
+        let mut journal = JournalWriter::open(path)?;
+        for event in engine.take_events() {
+            journal.write(&event, &Annotations::new())?;
         }
+        journal.finish()?;
+    }
 
-        // Reject withdrawals that exceed the precision
-        if !has_valid_precision(&amount) {
-            return Err(format!(
-                "Withdrawal amount exceeds allowed precision; transaction {}",
-                record.tx
-            )
-            .into());
+    event.write_to(io::stdout())
+}
+
+fn inspect_snapshot(args: &InspectArgs) -> Result<(), Box<dyn Error>> {
+    let summary = inspect_state(BufReader::new(File::open(&args.state)?))?;
+    let mut stdout = io::stdout();
+    serde_json::to_writer(&mut stdout, &summary)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn migrate_snapshot(args: &MigrateArgs) -> Result<(), Box<dyn Error>> {
+    let input = BufReader::new(File::open(&args.input)?);
+    write_atomically(&args.output, |file| {
+        Ok(migrate_state(
+            input,
+            BufWriter::new(file),
+            args.from,
+            args.to,
+        )?)
+    })
+}
+
+// Replays a run's write-ahead log on top of the state it started from, or only the tail of
+// the log on top of its last checkpoint, leaving the accounts as they were when the run
+// stopped
+fn recover(args: &RecoverArgs) -> Result<(), Box<dyn Error>> {
+    let mut tail = None;
+    let mut engine = match (&args.checkpoint, &args.load_state, &args.initial_accounts) {
+        (Some(path), _, _) => {
+            let (engine, after) =
+                WalTail::from_checkpoint(path, &args.wal, args.engine.config())
+                    .map_err(|e| format!("Failed to recover from {}: {}", path.display(), e))?;
+            tail = Some(after);
+            engine
+        }
+        (None, Some(path), _) => {
+            let file = BufReader::new(File::open(path)?);
+            PaymentsEngine::load_state(file, args.engine.config())
+                .map_err(|e| format!("Failed to load state from {}: {}", path.display(), e))?
+        }
+        (None, None, Some(path)) => {
+            PaymentsEngine::with_accounts(read_accounts(path)?, args.engine.config())
+        }
+        (None, None, None) => PaymentsEngine::with_config(args.engine.config()),
+    };
+    if let Some(path) = &args.aliases {
+        for row in read_aliases(path)? {
+            engine.add_alias(row.alias, row.client)?;
         }
+    }
+    // The last entry may have been cut short by the crash; it is skipped as a corrupted row
+    let (engine, stats) = run_engine(
+        std::slice::from_ref(&args.wal),
+        &InputArgs::default(),
+        engine,
+        false,
+        SideOutputs {
+            checkpoints: tail.as_mut().map(|tail| tail as _),
+            ..SideOutputs::default()
+        },
+    )?;
+    eprintln!("Recovered {} logged transactions", stats.processed);
 
-        account.withdraw(amount)?;
-        transactions.insert(record.tx, record.clone());
-        Ok(())
-    } else {
-        Err(format!("Withdrawal transaction {} missing amount", record.tx).into())
+    if let Some(path) = &args.save_state {
+        write_atomically(path, |file| Ok(engine.save_state(BufWriter::new(file))?))?;
+    }
+    let accounts = engine.finalize();
+    let overdraft_limit = args.engine.overdraft_limit.unwrap_or_default();
+    match &args.output {
+        Some(path) => write_atomically(path, |file| {
+            write_accounts(
+                &accounts,
+                args.output_format,
+                args.extended_output,
+                overdraft_limit,
+                file,
+            )
+        }),
+        None => write_accounts(
+            &accounts,
+            args.output_format,
+            args.extended_output,
+            overdraft_limit,
+            io::stdout(),
+        ),
     }
 }
 
-// Moves funds from available to held and records the dispute.
-fn process_dispute(
-    record: &Record,
-    account: &mut Account,
-    transactions: &HashMap<TransactionId, Record>,
-    disputes: &mut HashSet<TransactionId>,
-) -> Result<(), Box<dyn Error>> {
-    let disputed_tx = match transactions.get(&record.tx) {
-        Some(tx) => tx,
-        None => return Err(format!("Dispute error: Transaction {} not found", record.tx).into()),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::EngineArgs;
+    use crate::output::OutputFormat;
+    use exchange_test::ZeroAmountPolicy;
+    use std::fs;
+
+    // Tests run from the package root; relative paths keep the file column of rejects short
+    const TEST_DATA: &str = "tests/data/test_data.csv";
+    const CORRUPTED_ROWS: &str = "tests/data/corrupted_rows.csv";
 
-    if disputed_tx.tx_type.to_lowercase() != "deposit" {
-        return Err(format!("Dispute error: Transaction {} is not a deposit", record.tx).into());
+    #[test]
+    fn test_strict_mode_reports_offending_line() {
+        let result = run_engine(
+            &[PathBuf::from(TEST_DATA)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            true,
+            SideOutputs::default(),
+        );
+        let err = result.map(|_| ()).expect_err("strict run should abort");
+        assert_eq!(
+            err.to_string(),
+            "Aborting in strict mode at tests/data/test_data.csv line 6: \
+             Deposit amount exceeds allowed precision; transaction 4"
+        );
+
+        let (_, stats) = run_engine(
+            &[PathBuf::from(TEST_DATA)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs::default(),
+        )
+        .unwrap();
+        assert_eq!(stats.processed, 22);
+        assert_eq!(stats.accepted, 9);
+        assert_eq!(stats.rejected, 13);
+        assert_eq!(stats.by_type["deposit"].accepted, 4);
+        assert_eq!(stats.by_type["deposit"].rejected, 5);
+        assert_eq!(stats.by_type["invalid"].rejected, 1);
     }
 
-    if disputes.contains(&record.tx) {
-        return Err(format!(
-            "Dispute error: Transaction {} is already disputed",
-            record.tx
+    #[test]
+    fn test_slow_transactions_are_counted_per_type() {
+        // With no latency allowed every record that reaches the engine counts as slow
+        let (_, stats) = run_engine(
+            &[PathBuf::from(TEST_DATA)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                slow_tx: Some(Duration::ZERO),
+                ..SideOutputs::default()
+            },
         )
-        .into());
+        .unwrap();
+        assert_eq!(stats.by_type["deposit"].slow, 9);
+        assert_eq!(stats.by_type["invalid"].slow, 0);
+        let slow: usize = stats.by_type.values().map(|t| t.slow).sum();
+        assert_eq!(slow, stats.processed - 1);
     }
 
-    if let Some(amount) = disputed_tx.amount {
-        account.apply_dispute(amount)?;
-        disputes.insert(record.tx);
-        Ok(())
-    } else {
-        Err(format!("Dispute error: Transaction {} has no amount", record.tx).into())
+    #[test]
+    fn test_balance_series_has_end_of_day_balances() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("dated.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,10.0,2024-03-01T09:00:00Z\n\
+             deposit,2,2,5.0,2024-03-01T17:30:00Z\n\
+             withdrawal,1,3,2.5,2024-03-01T18:00:00Z\n\
+             withdrawal,2,4,50.0,2024-03-02T10:00:00Z\n\
+             deposit,1,5,1.0,2024-03-04\n\
+             dispute,2,2,,2024-03-04T08:00:00\n",
+        )
+        .unwrap();
+        let path = dir.path().join("series.csv");
+
+        let mut series = BalanceSeries::create(&path).unwrap();
+        let (engine, _) = run_engine(
+            &[input],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                series: Some(&mut series),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        series.finish(engine.accounts()).unwrap();
+
+        // The refused withdrawal changes nothing, so 2024-03-02 has no rows
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "client,date,available,held,total\n\
+             1,2024-03-01,7.5000,0.0000,7.5000\n\
+             2,2024-03-01,5.0000,0.0000,5.0000\n\
+             1,2024-03-04,8.5000,0.0000,8.5000\n\
+             2,2024-03-04,0.0000,5.0000,5.0000\n"
+        );
     }
-}
 
-// Moves funds from held back to available and removes the dispute.
-fn process_resolve(
-    record: &Record,
-    account: &mut Account,
-    transactions: &HashMap<TransactionId, Record>,
-    disputes: &mut HashSet<TransactionId>,
-) -> Result<(), Box<dyn Error>> {
-    if !disputes.contains(&record.tx) {
-        return Err(format!("Resolve error: Transaction {} is not disputed", record.tx).into());
+    #[test]
+    fn test_sample_skips_zero_amounts_that_open_no_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("zero.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,5,1,0\n\
+             deposit,6,2,1.5\n",
+        )
+        .unwrap();
+        let path = dir.path().join("sample.csv");
+        let engine = PaymentsEngine::with_config(EngineConfig {
+            zero_amounts: ZeroAmountPolicy::Noop,
+            ..EngineConfig::default()
+        });
+
+        let mut sampler = Sampler::create(&path, 1.0, 0).unwrap();
+        run_engine(
+            &[input],
+            &InputArgs::default(),
+            engine,
+            false,
+            SideOutputs {
+                sampler: Some(&mut sampler),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        sampler.finish().unwrap();
+
+        let sample = fs::read_to_string(&path).unwrap();
+        assert_eq!(sample.lines().count(), 2);
+        assert!(sample.ends_with(",3,deposit,6,2,1.5,1.5000,0.0000,1.5000,false\n"));
     }
 
-    let disputed_tx = match transactions.get(&record.tx) {
-        Some(tx) => tx,
-        None => return Err(format!("Resolve error: Transaction {} not found", record.tx).into()),
-    };
+    #[test]
+    fn test_two_pass_matches_a_single_pass() {
+        fn run<S: Storage>(inputs: &[PathBuf], engine: PaymentsEngine<S>) -> (Accounts, usize) {
+            let (engine, stats) = run_engine(
+                inputs,
+                &InputArgs::default(),
+                engine,
+                false,
+                SideOutputs::default(),
+            )
+            .unwrap();
+            (engine.finalize(), stats.rejected)
+        }
 
-    if let Some(amount) = disputed_tx.amount {
-        account.resolve_dispute(amount)?;
-        disputes.remove(&record.tx);
-        Ok(())
-    } else {
-        Err(format!("Resolve error: Transaction {} has no amount", record.tx).into())
+        let inputs = [PathBuf::from(TEST_DATA)];
+        let disputed = disputed_transactions(&inputs, &InputArgs::default()).unwrap();
+        let storage = ReferencedStorage::new(MemoryStorage::default(), disputed);
+        let two_pass = PaymentsEngine::with_storage(storage, EngineConfig::default()).unwrap();
+        // The test data reuses the id of a deposit that is never disputed
+        assert_eq!(run(&inputs, two_pass), run(&inputs, PaymentsEngine::new()));
     }
-}
 
-// Removes held funds (and thus total funds), removes dispute and locks the account.
-fn process_chargeback(
-    record: &Record,
-    account: &mut Account,
-    transactions: &HashMap<TransactionId, Record>,
-    disputes: &mut HashSet<TransactionId>,
-) -> Result<(), Box<dyn Error>> {
-    if !disputes.contains(&record.tx) {
-        return Err(format!(
-            "Chargeback error: Transaction {} is not disputed",
-            record.tx
+    #[test]
+    fn test_rejected_rows_are_written_with_line_and_reason() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rejects.csv");
+
+        let mut rejects = RejectWriter::create(&path, false).unwrap();
+        run_engine(
+            &[PathBuf::from(TEST_DATA)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                rejects: Some(&mut rejects),
+                ..SideOutputs::default()
+            },
         )
-        .into());
+        .unwrap();
+        rejects.finish().unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(lines[0], "file,line,type,client,tx,amount,reason,detail");
+        assert_eq!(
+            lines[1],
+            "tests/data/test_data.csv,6,deposit,3,4,100.12345,precision_exceeded,\
+             Deposit amount exceeds allowed precision; transaction 4"
+        );
     }
 
-    let disputed_tx = match transactions.get(&record.tx) {
-        Some(tx) => tx,
-        None => {
-            return Err(format!("Chargeback error: Transaction {} not found", record.tx).into())
-        }
-    };
+    #[test]
+    fn test_corrupted_rows_are_skipped_and_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rejects.csv");
 
-    if let Some(amount) = disputed_tx.amount {
-        account.chargeback(amount)?;
-        disputes.remove(&record.tx);
-        Ok(())
-    } else {
-        Err(format!("Chargeback error: Transaction {} has no amount", record.tx).into())
+        let mut rejects = RejectWriter::create(&path, false).unwrap();
+        let (engine, stats) = run_engine(
+            &[PathBuf::from(CORRUPTED_ROWS)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                rejects: Some(&mut rejects),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        rejects.finish().unwrap();
+
+        assert_eq!(stats.accepted, 2);
+        assert_eq!(stats.rejected, 2);
+        assert_eq!(engine.accounts()[&1].total, Decimal::new(120, 1));
+
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(
+            lines[1..],
+            [
+                "tests/data/corrupted_rows.csv,4,,,,,corrupted_row,\
+                 bytes 112..129: unterminated quoted field",
+                "tests/data/corrupted_rows.csv,5,,,,,corrupted_row,\
+                 \"bytes 129..151: expected 4 fields, found 5\"",
+            ]
+            .map(|l| l.to_string())
+        );
     }
-}
 
-// Outputs client ID, available funds, held funds, total funds, and locked status.
-fn write_accounts_to_csv(accounts: &HashMap<ClientId, Account>) -> Result<(), Box<dyn Error>> {
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    wtr.write_record(&["client", "available", "held", "total", "locked"])?;
+    #[test]
+    fn test_stray_quote_mid_file_loses_only_its_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("stray.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,1,2,2\"0\n\
+             deposit,2,3,5.0\n\
+             deposit,3,4,\"6.0\n\
+             deposit,3,5,7.0\n",
+        )
+        .unwrap();
+
+        let (engine, stats) = run_engine(
+            &[input],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs::default(),
+        )
+        .unwrap();
 
-    for (client_id, account) in accounts {
-        wtr.write_record(&[
-            client_id.to_string(),
-            format!("{:.4}", account.available),
-            format!("{:.4}", account.held),
-            format!("{:.4}", account.total),
-            account.locked.to_string(),
-        ])?;
+        // The quote inside an unquoted field is data, so only that amount is invalid; the
+        // quote opening a field that never closes only costs its own line
+        assert_eq!((stats.accepted, stats.rejected), (3, 2));
+        assert_eq!(engine.accounts()[&2].total, Decimal::new(50, 1));
+        assert_eq!(engine.accounts()[&3].total, Decimal::new(70, 1));
     }
 
-    wtr.flush()?;
-    Ok(())
-}
+    #[test]
+    fn test_recovery_from_wal_matches_the_interrupted_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.wal");
 
-fn has_valid_precision(amount: &Decimal) -> bool {
-    amount.scale() <= 4 // Scale gives the number of decimal places
-}
+        let mut wal = WalWriter::create(&path).unwrap();
+        let (engine, _) = run_engine(
+            &[PathBuf::from(TEST_DATA)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                wal: Some(&mut wal),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        drop(wal);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rust_decimal::Decimal;
-    use std::collections::{HashMap, HashSet};
-    use std::env;
-    use std::fs::File;
-    use std::path::PathBuf;
+        let (recovered, stats) = run_engine(
+            &[path],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs::default(),
+        )
+        .unwrap();
+        // Every parsed row was logged; the one corrupted row never reached the engine
+        assert_eq!(stats.processed, 21);
+        assert_eq!(recovered.accounts(), engine.accounts());
+    }
 
     #[test]
-    fn test_large_csv_with_edge_cases() {
-        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-        let mut csv_path = PathBuf::from(manifest_dir);
-        csv_path.push("tests/data/test_data.csv");
-
-        let file = File::open(&csv_path).expect("Failed to open test data CSV file");
-        let mut rdr = ReaderBuilder::new().comment(Some(b'#')).from_reader(file);
-
-        let mut accounts: HashMap<ClientId, Account> = HashMap::new();
-        let mut transactions: HashMap<TransactionId, Record> = HashMap::new();
-        let mut disputes: HashSet<TransactionId> = HashSet::new();
-
-        // Keep track of error count
-        let mut error_count = 0;
-        for result in rdr.deserialize() {
-            let record: Record = match result {
-                Ok(rec) => rec,
-                Err(_) => {
-                    error_count += 1;
-                    continue;
-                }
-            };
-            if process_transaction(&record, &mut accounts, &mut transactions, &mut disputes)
-                .is_err()
-            {
-                error_count += 1;
-            }
-        }
-        assert_eq!(error_count, 13);
+    fn test_resumed_run_continues_from_the_last_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = dir.path().join("run.checkpoint");
+        let inputs = [PathBuf::from(TEST_DATA)];
 
-        let account1 = accounts.get(&1).unwrap();
-        assert_eq!(account1.available, Decimal::new(130000, 2));
-        assert_eq!(account1.held, Decimal::new(0, 2));
-        assert_eq!(account1.total, Decimal::new(130000, 2));
-        assert!(!account1.locked);
+        let mut checkpoints = Checkpoints::new(&inputs, Some((checkpoint.clone(), 8)));
+        let (engine, _) = run_engine(
+            &inputs,
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                checkpoints: Some(&mut checkpoints),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
 
-        let account2 = accounts.get(&2).unwrap();
-        assert_eq!(account2.available, Decimal::new(0, 4));
-        assert_eq!(account2.held, Decimal::new(0, 4));
-        assert_eq!(account2.total, Decimal::new(0, 4));
-        assert!(account2.locked);
+        // The last checkpoint was taken after the 16th row, on line 25
+        let path = dir.path().join("rejects.csv");
+        let mut rejects = RejectWriter::create(&path, false).unwrap();
+        let mut checkpoints = Checkpoints::new(&inputs, None);
+        let resumed = checkpoints
+            .resume(&checkpoint, EngineConfig::default())
+            .unwrap();
+        let (resumed, stats) = run_engine(
+            &inputs,
+            &InputArgs::default(),
+            resumed,
+            false,
+            SideOutputs {
+                rejects: Some(&mut rejects),
+                checkpoints: Some(&mut checkpoints),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        rejects.finish().unwrap();
 
-        assert!(!accounts.contains_key(&3));
-        assert!(!accounts.contains_key(&4));
+        assert_eq!(stats.processed, 6);
+        assert_eq!(resumed.accounts(), engine.accounts());
+        let written = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = (written.lines().skip(1))
+            .map(|l| l.split(',').nth(1).unwrap())
+            .collect();
+        assert_eq!(lines, ["27", "29", "31", "33", "35"]);
     }
 
     #[test]
-    fn test_account_deposit_and_withdrawal() {
-        let mut account = Account::new();
-        account.deposit(Decimal::new(1000, 2)).unwrap();
-        account.withdraw(Decimal::new(500, 2)).unwrap();
+    fn test_wal_continued_by_a_resumed_run_recovers_the_whole_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let initial = dir.path().join("initial.csv");
+        fs::write(
+            &initial,
+            "client,available,held,total,locked\n4,5.0,0,5.0,false\n",
+        )
+        .unwrap();
+        let (checkpoint, wal) = (
+            dir.path().join("run.checkpoint"),
+            dir.path().join("run.wal"),
+        );
+        let inputs = [PathBuf::from(TEST_DATA)];
+        let start = || {
+            PaymentsEngine::with_accounts(read_accounts(&initial).unwrap(), EngineConfig::default())
+        };
+
+        // The whole input is logged, as if the run died after logging past its last checkpoint
+        let mut checkpoints = Checkpoints::new(&inputs, Some((checkpoint.clone(), 8)));
+        let mut writer = checkpoints.open_wal(&wal).unwrap();
+        let outputs = SideOutputs {
+            wal: Some(&mut writer),
+            checkpoints: Some(&mut checkpoints),
+            ..SideOutputs::default()
+        };
+        let (engine, _) =
+            run_engine(&inputs, &InputArgs::default(), start(), false, outputs).unwrap();
+        drop(writer);
 
-        assert_eq!(account.available, Decimal::new(500, 2));
-        assert_eq!(account.total, Decimal::new(500, 2));
-        assert_eq!(account.held, Decimal::new(0, 2));
+        let mut checkpoints = Checkpoints::new(&inputs, None);
+        let resumed = checkpoints
+            .resume(&checkpoint, EngineConfig::default())
+            .unwrap();
+        let mut writer = checkpoints.open_wal(&wal).unwrap();
+        let outputs = SideOutputs {
+            wal: Some(&mut writer),
+            checkpoints: Some(&mut checkpoints),
+            ..SideOutputs::default()
+        };
+        run_engine(&inputs, &InputArgs::default(), resumed, false, outputs).unwrap();
+        drop(writer);
+
+        // Rows after the checkpoint are in the log once, after those before it
+        let output = dir.path().join("recovered.csv");
+        recover(&RecoverArgs {
+            wal,
+            load_state: None,
+            initial_accounts: Some(initial.clone()),
+            aliases: None,
+            checkpoint: None,
+            output: Some(output.clone()),
+            output_format: OutputFormat::Csv,
+            extended_output: true,
+            save_state: None,
+            engine: EngineArgs::default(),
+        })
+        .unwrap();
+        let mut expected = Vec::new();
+        write_accounts(
+            &engine.finalize(),
+            OutputFormat::Csv,
+            true,
+            Decimal::ZERO,
+            &mut expected,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&output).unwrap(), expected);
     }
 
     #[test]
-    fn test_account_dispute_and_resolve() {
-        let mut account = Account::new();
-        account.deposit(Decimal::new(1000, 2)).unwrap();
-        account.apply_dispute(Decimal::new(1000, 2)).unwrap();
-        account.resolve_dispute(Decimal::new(1000, 2)).unwrap();
+    fn test_recovery_from_a_checkpoint_replays_only_the_log_after_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let (checkpoint, wal) = (
+            dir.path().join("run.checkpoint"),
+            dir.path().join("run.wal"),
+        );
+        let inputs = [PathBuf::from(TEST_DATA)];
 
-        assert_eq!(account.available, Decimal::new(1000, 2));
-        assert_eq!(account.held, Decimal::new(0, 2));
-        assert_eq!(account.total, Decimal::new(1000, 2));
+        // The run crashed after logging the rows past its last checkpoint
+        let mut checkpoints = Checkpoints::new(&inputs, Some((checkpoint.clone(), 8)));
+        let mut writer = checkpoints.open_wal(&wal).unwrap();
+        let outputs = SideOutputs {
+            wal: Some(&mut writer),
+            checkpoints: Some(&mut checkpoints),
+            ..SideOutputs::default()
+        };
+        let (engine, _) = run_engine(
+            &inputs,
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            outputs,
+        )
+        .unwrap();
+        drop(writer);
+
+        // Only the rows after the checkpoint on line 25 are replayed, rejected as in the run
+        let (restored, mut tail) =
+            WalTail::from_checkpoint(&checkpoint, &wal, EngineConfig::default()).unwrap();
+        let outputs = SideOutputs {
+            checkpoints: Some(&mut tail),
+            ..SideOutputs::default()
+        };
+        let (_, stats) = run_engine(
+            std::slice::from_ref(&wal),
+            &InputArgs::default(),
+            restored,
+            false,
+            outputs,
+        )
+        .unwrap();
+        assert_eq!((stats.processed, stats.rejected), (6, 5));
+
+        let output = dir.path().join("recovered.csv");
+        recover(&RecoverArgs {
+            wal,
+            load_state: None,
+            initial_accounts: None,
+            aliases: None,
+            checkpoint: Some(checkpoint),
+            output: Some(output.clone()),
+            output_format: OutputFormat::Csv,
+            extended_output: false,
+            save_state: None,
+            engine: EngineArgs::default(),
+        })
+        .unwrap();
+        let mut expected = Vec::new();
+        let accounts = engine.finalize();
+        write_accounts(
+            &accounts,
+            OutputFormat::Csv,
+            false,
+            Decimal::ZERO,
+            &mut expected,
+        )
+        .unwrap();
+        assert_eq!(fs::read(&output).unwrap(), expected);
     }
 
     #[test]
-    fn test_account_chargeback() {
-        let mut account = Account::new();
-        account.deposit(Decimal::new(1000, 2)).unwrap();
-        account.apply_dispute(Decimal::new(1000, 2)).unwrap();
-        account.chargeback(Decimal::new(1000, 2)).unwrap();
-
-        assert_eq!(account.available, Decimal::new(0, 2));
-        assert_eq!(account.held, Decimal::new(0, 2));
-        assert_eq!(account.total, Decimal::new(0, 2));
-        assert!(account.locked);
+    fn test_multiple_inputs_share_state_and_attribute_rejects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rejects.csv");
+
+        let mut rejects = RejectWriter::create(&path, false).unwrap();
+        let (engine, stats) = run_engine(
+            &[PathBuf::from(TEST_DATA), PathBuf::from(CORRUPTED_ROWS)],
+            &InputArgs::default(),
+            PaymentsEngine::new(),
+            false,
+            SideOutputs {
+                rejects: Some(&mut rejects),
+                ..SideOutputs::default()
+            },
+        )
+        .unwrap();
+        rejects.finish().unwrap();
+
+        assert_eq!(stats.processed, 22 + 4);
+        let second = &stats.by_source[CORRUPTED_ROWS];
+        assert_eq!((second.processed, second.rejected), (4, 3));
+        // Tx 1 was already deposited by the first file; tx 4 was rejected there, so is new
+        assert_eq!(engine.accounts()[&1].total, Decimal::new(13020, 1));
+
+        let written = fs::read_to_string(&path).unwrap();
+        let second_file: Vec<&str> = written
+            .lines()
+            .filter(|l| l.starts_with(CORRUPTED_ROWS))
+            .map(|l| l.split(',').nth(6).unwrap())
+            .collect();
+        assert_eq!(
+            second_file,
+            ["duplicate_tx", "corrupted_row", "corrupted_row"]
+        );
     }
 }
@@ -0,0 +1,279 @@
+use clap::ValueEnum;
+use exchange_test::{Account, Accounts, ClientId};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+// An output file that is written into a temp file next to its destination and only renamed
+// into place by `commit`, so readers never observe a partially written file. Dropping it
+// without committing discards the temp file.
+pub struct AtomicFile {
+    tmp: NamedTempFile,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<AtomicFile> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        Ok(AtomicFile {
+            tmp: NamedTempFile::new_in(dir)?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn commit(self) -> Result<(), Box<dyn Error>> {
+        self.tmp.as_file().sync_all()?;
+        self.tmp.persist(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tmp.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tmp.flush()
+    }
+}
+
+// Runs `write` against an AtomicFile for `path`, committing it only if writing succeeded
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut File) -> Result<(), Box<dyn Error>>,
+{
+    let mut file = AtomicFile::create(path)?;
+    write(file.tmp.as_file_mut())?;
+    file.commit()
+}
+
+// Supported encodings of the account state output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // A single JSON array of account objects
+    Json,
+    // One JSON account object per line
+    Jsonl,
+    // An Apache Parquet file with DECIMAL(38, 4) amount columns; requires the `parquet` feature
+    Parquet,
+    // An Excel workbook with numeric amount cells; requires the `xlsx` feature
+    Xlsx,
+}
+
+pub type AmountColumn = (&'static str, Box<dyn Fn(&Account) -> Decimal>);
+
+// Amount columns written after the original five only with --extended-output, so consumers of
+// the specified `client,available,held,total,locked` output are not broken by new columns.
+// Withdrawable funds include the overdraft the run allowed.
+pub fn extended_columns(overdraft_limit: Decimal) -> [AmountColumn; 3] {
+    [
+        ("disputed_held", Box::new(|a| a.holds.dispute)),
+        ("disputed_lifetime", Box::new(|a| a.disputed_lifetime)),
+        (
+            "withdrawable",
+            Box::new(move |a| a.withdrawable(overdraft_limit)),
+        ),
+    ]
+}
+
+// The output representation of one account. Amounts are fixed to four decimal places and
+// kept as strings so JSON consumers do not round them through floats.
+#[derive(Debug, Serialize)]
+pub struct AccountState {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disputed_held: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disputed_lifetime: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    withdrawable: Option<String>,
+}
+
+impl AccountState {
+    // The state with the extended columns left out unless `extended` is set
+    pub fn new(
+        client: ClientId,
+        account: &Account,
+        extended: bool,
+        overdraft_limit: Decimal,
+    ) -> AccountState {
+        let extra = |amount: Decimal| extended.then(|| format!("{:.4}", amount));
+        AccountState {
+            client,
+            available: format!("{:.4}", account.available),
+            held: format!("{:.4}", account.held()),
+            total: format!("{:.4}", account.total),
+            locked: account.locked,
+            disputed_held: extra(account.holds.dispute),
+            disputed_lifetime: extra(account.disputed_lifetime),
+            withdrawable: extra(account.withdrawable(overdraft_limit)),
+        }
+    }
+}
+
+// Outputs client ID, available funds, held funds, total funds, and locked status, followed by
+// the extended columns when `extended` is set. Rows are sorted by client ID so the output is
+// byte-stable across runs.
+pub fn write_accounts<W: io::Write + Send>(
+    accounts: &Accounts,
+    format: OutputFormat,
+    extended: bool,
+    overdraft_limit: Decimal,
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut rows: Vec<_> = accounts.iter().collect();
+    rows.sort_unstable_by_key(|(client_id, _)| **client_id);
+    let states = rows.iter().map(|(client_id, account)| {
+        AccountState::new(**client_id, account, extended, overdraft_limit)
+    });
+    let all_columns = extended_columns(overdraft_limit);
+    let extended_columns: &[AmountColumn] = if extended { &all_columns } else { &[] };
+
+    match format {
+        OutputFormat::Csv => {
+            // The header is written explicitly so that it is present even with no accounts
+            let mut wtr = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(&mut writer);
+            let header = ["client", "available", "held", "total", "locked"]
+                .into_iter()
+                .chain(extended_columns.iter().map(|(name, _)| *name));
+            wtr.write_record(header)?;
+            for state in states {
+                wtr.serialize(state)?;
+            }
+            wtr.flush()?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &states.collect::<Vec<_>>())?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for state in states {
+                serde_json::to_writer(&mut writer, &state)?;
+                writeln!(writer)?;
+            }
+        }
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => {
+            crate::columnar::write_accounts_parquet(&rows, extended_columns, &mut writer)?
+        }
+        #[cfg(not(feature = "parquet"))]
+        OutputFormat::Parquet => {
+            return Err("parquet output requires building with the `parquet` feature".into())
+        }
+        #[cfg(feature = "xlsx")]
+        OutputFormat::Xlsx => {
+            crate::spreadsheet::write_accounts_xlsx(&rows, extended_columns, &mut writer)?
+        }
+        #[cfg(not(feature = "xlsx"))]
+        OutputFormat::Xlsx => {
+            return Err("xlsx output requires building with the `xlsx` feature".into())
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::{PaymentsEngine, Record, TxType};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_accounts_are_written_in_client_order() {
+        let mut engine = PaymentsEngine::new();
+        for client in [7, 3, 42, 1] {
+            let record = Record {
+                tx_type: TxType::Deposit,
+                client,
+                tx: client.into(),
+                amount: Some(Decimal::new(15, 1)),
+            };
+            engine.process(&record).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let accounts = engine.finalize();
+        write_accounts(
+            &accounts,
+            OutputFormat::Csv,
+            false,
+            Decimal::ZERO,
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             3,1.5000,0.0000,1.5000,false\n\
+             7,1.5000,0.0000,1.5000,false\n\
+             42,1.5000,0.0000,1.5000,false\n"
+        );
+
+        // Withdrawable funds include the overdraft
+        let mut output = Vec::new();
+        let overdraft_limit = Decimal::new(2, 0);
+        write_accounts(
+            &accounts,
+            OutputFormat::Csv,
+            true,
+            overdraft_limit,
+            &mut output,
+        )
+        .unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with(
+            "client,available,held,total,locked,disputed_held,disputed_lifetime,withdrawable\n\
+             1,1.5000,0.0000,1.5000,false,0.0000,0.0000,3.5000\n"
+        ));
+    }
+
+    #[test]
+    fn test_accounts_are_written_as_json_lines() {
+        let mut engine = PaymentsEngine::new();
+        engine
+            .process(&Record {
+                tx_type: TxType::Deposit,
+                client: 2,
+                tx: 1,
+                amount: Some(Decimal::new(15, 1)),
+            })
+            .unwrap();
+
+        let mut output = Vec::new();
+        let accounts = engine.finalize();
+        write_accounts(
+            &accounts,
+            OutputFormat::Jsonl,
+            true,
+            Decimal::ZERO,
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":2,\"available\":\"1.5000\",\"held\":\"0.0000\",\
+             \"total\":\"1.5000\",\"locked\":false,\"disputed_held\":\"0.0000\",\
+             \"disputed_lifetime\":\"0.0000\",\"withdrawable\":\"1.5000\"}\n"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use exchange_test::{ClientId, Record, TxType};
+use prost::Message;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::source::{record_fields, RecordSource, Row};
+
+// Mirrors `Transaction` in proto/transaction.proto
+#[derive(Clone, PartialEq, Message)]
+pub struct Transaction {
+    #[prost(enumeration = "ProtoTxType", tag = "1")]
+    pub r#type: i32,
+    #[prost(uint32, tag = "2")]
+    pub client: u32,
+    #[prost(uint32, tag = "3")]
+    pub tx: u32,
+    #[prost(string, optional, tag = "4")]
+    pub amount: Option<String>,
+}
+
+// Mirrors `TxType` in proto/transaction.proto
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ProtoTxType {
+    Unspecified = 0,
+    Deposit = 1,
+    Withdrawal = 2,
+    Dispute = 3,
+    Resolve = 4,
+    Chargeback = 5,
+}
+
+impl TryFrom<Transaction> for Record {
+    type Error = String;
+
+    fn try_from(message: Transaction) -> Result<Record, String> {
+        let tx_type = match ProtoTxType::try_from(message.r#type) {
+            Ok(ProtoTxType::Deposit) => TxType::Deposit,
+            Ok(ProtoTxType::Withdrawal) => TxType::Withdrawal,
+            Ok(ProtoTxType::Dispute) => TxType::Dispute,
+            Ok(ProtoTxType::Resolve) => TxType::Resolve,
+            Ok(ProtoTxType::Chargeback) => TxType::Chargeback,
+            Ok(ProtoTxType::Unspecified) | Err(_) => {
+                return Err(format!("Unknown transaction type: {}", message.r#type))
+            }
+        };
+        let client = ClientId::try_from(message.client)
+            .map_err(|_| format!("Client id {} is out of range", message.client))?;
+        let amount = message
+            .amount
+            .as_deref()
+            .map(Decimal::from_str)
+            .transpose()
+            .map_err(|e| format!("Invalid amount: {}", e))?;
+
+        Ok(Record {
+            tx_type,
+            client,
+            tx: message.tx,
+            amount,
+        })
+    }
+}
+
+// Reads a stream of varint length-prefixed `Transaction` messages. Messages are numbered
+// from 1 in place of line numbers.
+pub struct ProtoSource {
+    reader: Box<dyn BufRead>,
+    max_message_size: u64,
+    buf: Vec<u8>,
+    message: u64,
+    last: Option<Record>,
+}
+
+impl ProtoSource {
+    pub fn new(reader: Box<dyn BufRead>, max_message_size: u64) -> ProtoSource {
+        ProtoSource {
+            reader,
+            max_message_size,
+            buf: Vec::new(),
+            message: 0,
+            last: None,
+        }
+    }
+
+    // Reads the varint length prefix of the next message, or None at a clean end of input
+    fn read_length(&mut self) -> io::Result<Option<u64>> {
+        let mut length = 0;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            match self.reader.read_exact(&mut byte) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && shift == 0 => {
+                    return Ok(None)
+                }
+                result => result?,
+            }
+            length |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(Some(length));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message {} has an invalid length prefix", self.message + 1),
+        ))
+    }
+}
+
+impl RecordSource for ProtoSource {
+    fn next_row(&mut self) -> Result<Option<(u64, Row)>, Box<dyn Error>> {
+        self.last = None;
+        let Some(length) = self.read_length()? else {
+            return Ok(None);
+        };
+        self.message += 1;
+
+        // A corrupt length would otherwise allocate whatever it claims
+        if length > self.max_message_size {
+            return Err(format!(
+                "message {} is {} bytes, over the maximum of {}",
+                self.message, length, self.max_message_size
+            )
+            .into());
+        }
+        self.buf.resize(length as usize, 0);
+        self.reader.read_exact(&mut self.buf)?;
+
+        // The length prefix keeps the stream in sync, so a bad message only loses itself
+        let decoded = Transaction::decode(self.buf.as_slice())
+            .map_err(|e| e.to_string())
+            .and_then(Record::try_from);
+        let row = match decoded {
+            Ok(record) => {
+                self.last = Some(record);
+                Row::Parsed(record)
+            }
+            Err(reason) => Row::Invalid {
+                code: "invalid_record",
+                reason,
+            },
+        };
+        Ok(Some((self.message, row)))
+    }
+
+    fn raw_fields(&self) -> Vec<String> {
+        self.last.as_ref().map_or_else(Vec::new, record_fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::DEFAULT_MAX_LINE_LENGTH;
+
+    #[test]
+    fn test_length_delimited_messages_are_decoded() {
+        let mut stream = Vec::new();
+        for message in [
+            Transaction {
+                r#type: ProtoTxType::Deposit as i32,
+                client: 1,
+                tx: 1,
+                amount: Some("2.5".to_string()),
+            },
+            Transaction {
+                r#type: ProtoTxType::Dispute as i32,
+                client: 70_000,
+                tx: 1,
+                amount: None,
+            },
+        ] {
+            message.encode_length_delimited(&mut stream).unwrap();
+        }
+
+        let mut source =
+            ProtoSource::new(Box::new(io::Cursor::new(stream)), DEFAULT_MAX_LINE_LENGTH);
+        let Some((1, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+            panic!("expected a deposit as message 1");
+        };
+        assert_eq!(deposit.amount, Some(Decimal::new(25, 1)));
+        assert_eq!(source.raw_fields(), ["deposit", "1", "1", "2.5"]);
+
+        let Some((2, Row::Invalid { reason, .. })) = source.next_row().unwrap() else {
+            panic!("expected message 2 to be rejected");
+        };
+        assert_eq!(reason, "Client id 70000 is out of range");
+        assert!(source.next_row().unwrap().is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use crate::{Accounts, HashState, Record, Storage, StorageError, TransactionId};
+
+// Keeps only the transactions whose ids are known up front to be referenced by a dispute,
+// such as those found by a first pass over the input. Of every other transaction only the
+// id is kept, so reused ids are still refused as duplicates while memory grows by a few
+// bytes per transaction instead of a full stored record.
+pub struct ReferencedStorage<S> {
+    referenced: HashSet<TransactionId, HashState>,
+    seen: HashSet<TransactionId, HashState>,
+    inner: S,
+}
+
+impl<S: Storage> ReferencedStorage<S> {
+    pub fn new(inner: S, referenced: HashSet<TransactionId, HashState>) -> ReferencedStorage<S> {
+        ReferencedStorage {
+            referenced,
+            seen: HashSet::default(),
+            inner,
+        }
+    }
+}
+
+impl<S: Storage> Storage for ReferencedStorage<S> {
+    fn load_accounts(&self) -> Result<Accounts, StorageError> {
+        self.inner.load_accounts()
+    }
+
+    fn commit(&mut self, accounts: &Accounts) -> Result<(), StorageError> {
+        self.inner.commit(accounts)
+    }
+
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        self.inner.transaction(tx)
+    }
+
+    fn contains_transaction(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.seen.contains(&tx) || self.inner.contains_transaction(tx)?)
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        if !self.referenced.contains(&record.tx) {
+            self.seen.insert(record.tx);
+            return Ok(());
+        }
+        self.inner.insert_transaction(record)
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        self.inner.is_disputed(tx)
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.inner.open_dispute(tx)
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.inner.close_dispute(tx)
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        self.inner.open_disputes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, EngineError, MemoryStorage, PaymentsEngine, TxType};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_only_referenced_transactions_are_kept() {
+        let storage = ReferencedStorage::new(MemoryStorage::default(), [2].into_iter().collect());
+        let mut engine = PaymentsEngine::with_storage(storage, EngineConfig::default()).unwrap();
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+
+        for tx in 1..=3 {
+            engine
+                .process(&record(TxType::Deposit, tx, Some(Decimal::ONE)))
+                .unwrap();
+        }
+        assert_eq!(engine.storage.inner.transactions.len(), 1);
+        assert_eq!(engine.storage.seen.len(), 2);
+
+        engine.process(&record(TxType::Dispute, 2, None)).unwrap();
+        engine.process(&record(TxType::Resolve, 2, None)).unwrap();
+        assert_eq!(
+            engine.process(&record(TxType::Dispute, 1, None)),
+            Err(EngineError::TxNotFound {
+                tx_type: TxType::Dispute,
+                tx: 1
+            })
+        );
+        for tx in [1, 2] {
+            assert_eq!(
+                engine.process(&record(TxType::Deposit, tx, Some(Decimal::ONE))),
+                Err(EngineError::DuplicateTx(tx))
+            );
+        }
+        assert_eq!(engine.accounts()[&1].available, Decimal::new(3, 0));
+    }
+}
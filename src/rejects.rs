@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::path::Path;
+
+use crate::enrich::{format_annotations, Annotations};
+use crate::output::AtomicFile;
+
+// Writes every rejected input row, with its original fields, source file and line and a
+// machine-readable reason code, so operations can reconcile and resubmit failed rows. When
+// the run enriches records, a last column holds their annotations.
+pub struct RejectWriter {
+    wtr: csv::Writer<AtomicFile>,
+    annotated: bool,
+}
+
+impl RejectWriter {
+    pub fn create(path: &Path, annotated: bool) -> Result<RejectWriter, Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_writer(AtomicFile::create(path)?);
+        let mut header = vec![
+            "file", "line", "type", "client", "tx", "amount", "reason", "detail",
+        ];
+        if annotated {
+            header.push("annotations");
+        }
+        wtr.write_record(header)?;
+        Ok(RejectWriter { wtr, annotated })
+    }
+
+    pub fn write(
+        &mut self,
+        file: &Path,
+        line: u64,
+        fields: &[String],
+        reason: &str,
+        detail: &str,
+        annotations: &Annotations,
+    ) -> csv::Result<()> {
+        let file = file.to_string_lossy();
+        let line = line.to_string();
+        let field = |i| fields.get(i).map_or("", String::as_str);
+        let annotations = format_annotations(annotations);
+        let mut row = vec![
+            file.as_ref(),
+            line.as_str(),
+            field(0),
+            field(1),
+            field(2),
+            field(3),
+            reason,
+            detail,
+        ];
+        if self.annotated {
+            row.push(&annotations);
+        }
+        self.wtr.write_record(row)
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.wtr.into_inner()?.commit()
+    }
+}
@@ -0,0 +1,229 @@
+use exchange_test::{Account, Accounts, ClientId, PaymentsEngine, Record, TransactionId, TxType};
+use log::{debug, warn};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+use crate::cli::ReplayArgs;
+use crate::input::{expand_inputs, STDIO_PATH};
+use crate::output::{write_accounts, write_atomically};
+use crate::source::{open_source, Row};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    // Substitute the given row for the original transaction
+    Replace,
+    // Drop the original transaction, as if it had never been received
+    Void,
+}
+
+// A row of the corrections file. Only `replace` rows need a type, client and amount.
+#[derive(Debug, Deserialize)]
+struct CorrectionRow {
+    action: Action,
+    #[serde(rename = "type")]
+    tx_type: Option<TxType>,
+    client: Option<ClientId>,
+    tx: TransactionId,
+    #[serde(deserialize_with = "csv::invalid_option")]
+    amount: Option<Decimal>,
+}
+
+// Corrections keyed by the id of the deposit or withdrawal they apply to; `None` voids it
+type Corrections = HashMap<TransactionId, Option<Record>>;
+
+fn read_corrections(path: &Path) -> Result<Corrections, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut corrections = Corrections::new();
+    for result in rdr.deserialize() {
+        let row: CorrectionRow = result?;
+        let correction = match (row.action, row.tx_type, row.client) {
+            (Action::Void, _, _) => None,
+            (Action::Replace, Some(tx_type), Some(client)) => Some(Record {
+                tx_type,
+                client,
+                tx: row.tx,
+                amount: row.amount,
+            }),
+            (Action::Replace, _, _) => {
+                return Err(
+                    format!("Correction for tx {} is missing a type or client", row.tx).into(),
+                )
+            }
+        };
+        if corrections.insert(row.tx, correction).is_some() {
+            return Err(format!("Transaction {} is corrected more than once", row.tx).into());
+        }
+    }
+    Ok(corrections)
+}
+
+// Replays the inputs twice over, as received and with the corrections applied, writing the
+// corrected account states and the accounts that differ between the two
+pub fn replay(args: &ReplayArgs) -> Result<(), Box<dyn Error>> {
+    let mut corrections = read_corrections(&args.corrections)?;
+    let mut original = PaymentsEngine::with_config(args.engine.config());
+    let mut corrected = PaymentsEngine::with_config(args.engine.config());
+
+    for input in expand_inputs(&args.inputs)? {
+        let mut source = open_source(&input, &args.input_args)?;
+        while let Some((line, row)) = source.next_row()? {
+            let Row::Parsed(record) = row else {
+                continue;
+            };
+            if let Err(e) = original.process(&record) {
+                debug!("Original {} line {}: {}", input.display(), line, e);
+            }
+
+            // Corrections target the row that created a transaction, not disputes of it,
+            // and each is applied once
+            let creates_tx = matches!(record.tx_type, TxType::Deposit | TxType::Withdrawal);
+            let replacement = match corrections.remove(&record.tx) {
+                Some(correction) if creates_tx => correction,
+                Some(correction) => {
+                    corrections.insert(record.tx, correction);
+                    Some(record)
+                }
+                None => Some(record),
+            };
+            if let Some(record) = replacement {
+                if let Err(e) = corrected.process(&record) {
+                    debug!("Corrected {} line {}: {}", input.display(), line, e);
+                }
+            }
+        }
+    }
+
+    let mut unused: Vec<_> = corrections.into_keys().collect();
+    unused.sort_unstable();
+    for tx in unused {
+        warn!("Correction for tx {} matched no deposit or withdrawal", tx);
+    }
+
+    let (original, corrected) = (original.finalize(), corrected.finalize());
+    if args.diff == Path::new(STDIO_PATH) {
+        write_diff(&original, &corrected, io::stderr())?;
+    } else {
+        write_atomically(&args.diff, |file| write_diff(&original, &corrected, file))?;
+    }
+    let overdraft_limit = args.engine.overdraft_limit.unwrap_or_default();
+    match &args.output {
+        Some(path) => write_atomically(path, |file| {
+            write_accounts(
+                &corrected,
+                args.output_format,
+                args.extended_output,
+                overdraft_limit,
+                file,
+            )
+        }),
+        None => write_accounts(
+            &corrected,
+            args.output_format,
+            args.extended_output,
+            overdraft_limit,
+            io::stdout(),
+        ),
+    }
+}
+
+// Writes one row per client whose balances or lock differ, in client order; the columns of a side
+// on which the client has no account are left empty
+fn write_diff<W: io::Write>(
+    original: &Accounts,
+    corrected: &Accounts,
+    writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "original_available",
+        "corrected_available",
+        "original_held",
+        "corrected_held",
+        "original_total",
+        "corrected_total",
+        "original_locked",
+        "corrected_locked",
+    ])?;
+
+    let clients: BTreeSet<_> = original.keys().chain(corrected.keys()).collect();
+    for client in clients {
+        let (before, after) = (original.get(client), corrected.get(client));
+        let balances = |a: &Account| (a.available, a.held(), a.total, a.locked);
+        if before.map(balances) == after.map(balances) {
+            continue;
+        }
+        let amount = |account: Option<&Account>, field: fn(&Account) -> Decimal| {
+            account.map_or_else(String::new, |a| format!("{:.4}", field(a)))
+        };
+        let locked =
+            |account: Option<&Account>| account.map_or_else(String::new, |a| a.locked.to_string());
+        wtr.write_record([
+            client.to_string(),
+            amount(before, |a| a.available),
+            amount(after, |a| a.available),
+            amount(before, Account::held),
+            amount(after, Account::held),
+            amount(before, |a| a.total),
+            amount(after, |a| a.total),
+            locked(before),
+            locked(after),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{EngineArgs, InputArgs};
+    use crate::output::OutputFormat;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_replay_applies_corrections_and_diffs_accounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let corrections = dir.path().join("corrections.csv");
+        fs::write(
+            &corrections,
+            "action,type,client,tx,amount\n\
+             void,,,5,\n\
+             replace,deposit,2,2,100.0\n\
+             replace,deposit,9,999,1.0\n",
+        )
+        .unwrap();
+        let args = ReplayArgs {
+            inputs: vec![PathBuf::from("tests/data/test_data.csv")],
+            corrections,
+            output: Some(dir.path().join("accounts.csv")),
+            output_format: OutputFormat::Csv,
+            extended_output: false,
+            diff: dir.path().join("diff.csv"),
+            input_args: InputArgs::default(),
+            engine: EngineArgs::default(),
+        };
+
+        replay(&args).unwrap();
+
+        // Voiding the withdrawal restores client 1's funds. Client 2's replaced deposit is
+        // still charged back, so its final state is unchanged and it is not in the diff.
+        assert_eq!(
+            fs::read_to_string(dir.path().join("diff.csv")).unwrap(),
+            "client,original_available,corrected_available,original_held,corrected_held,\
+             original_total,corrected_total,original_locked,corrected_locked\n\
+             1,1300.0000,1600.0000,0.0000,0.0000,1300.0000,1600.0000,false,false\n"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use exchange_test::{Account, Accounts, ClientId};
+use serde::Deserialize;
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Which accounts a route takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rule {
+    Locked,
+    Unlocked,
+    // Clients whose id is in the range, such as a block handed out to one tier of clients
+    Clients(RangeInclusive<ClientId>),
+}
+
+impl Rule {
+    fn matches(&self, client: ClientId, account: &Account) -> bool {
+        match self {
+            Rule::Locked => account.locked,
+            Rule::Unlocked => !account.locked,
+            Rule::Clients(range) => range.contains(&client),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || {
+            format!(
+                "unknown rule {:?}, expected locked|unlocked|clients:LOW-HIGH",
+                s
+            )
+        };
+        match s.trim().to_lowercase().as_str() {
+            "locked" => Ok(Rule::Locked),
+            "unlocked" => Ok(Rule::Unlocked),
+            rule => {
+                let (low, high) = rule
+                    .strip_prefix("clients:")
+                    .and_then(|range| range.split_once('-'))
+                    .ok_or_else(unknown)?;
+                let bound = |id: &str| id.trim().parse::<ClientId>().map_err(|_| unknown());
+                Ok(Rule::Clients(bound(low)?..=bound(high)?))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteRow {
+    rule: String,
+    output: PathBuf,
+}
+
+// Sends accounts to output files of their own by the first rule they match, so each
+// downstream team receives only its slice. Rules naming the same file share it.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    rules: Vec<(Rule, usize)>,
+    outputs: Vec<PathBuf>,
+}
+
+impl Routes {
+    // Reads the routes from a CSV file with `rule,output` columns
+    pub fn from_path(path: &Path) -> Result<Routes, Box<dyn Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)?;
+        let mut routes = Routes {
+            rules: Vec::new(),
+            outputs: Vec::new(),
+        };
+        for (i, row) in rdr.deserialize().enumerate() {
+            let row: RouteRow = row?;
+            // Line 1 is the header
+            let rule = row
+                .rule
+                .parse()
+                .map_err(|e| format!("{} line {}: {}", path.display(), i + 2, e))?;
+            let output = match routes.outputs.iter().position(|o| *o == row.output) {
+                Some(output) => output,
+                None => {
+                    routes.outputs.push(row.output);
+                    routes.outputs.len() - 1
+                }
+            };
+            routes.rules.push((rule, output));
+        }
+        Ok(routes)
+    }
+
+    // Splits the accounts into those matching no rule and those of each output file. Every
+    // output file is listed, if only with no accounts, so it is written on every run.
+    pub fn split(&self, accounts: Accounts) -> (Accounts, Vec<(&Path, Accounts)>) {
+        let mut routed: Vec<Accounts> = vec![Accounts::default(); self.outputs.len()];
+        let mut rest = Accounts::default();
+        for (client, account) in accounts {
+            match self
+                .rules
+                .iter()
+                .find(|(rule, _)| rule.matches(client, &account))
+            {
+                Some((_, output)) => routed[*output].insert(client, account),
+                None => rest.insert(client, account),
+            };
+        }
+        let outputs = self.outputs.iter().map(PathBuf::as_path).zip(routed);
+        (rest, outputs.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::Holds;
+    use rust_decimal::Decimal;
+    use std::fs;
+
+    #[test]
+    fn test_accounts_go_to_the_first_matching_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routes.csv");
+        fs::write(
+            &path,
+            "rule,output\n\
+             locked,locked.csv\n\
+             clients:100-199,institutional.csv\n\
+             clients:500-599,locked.csv\n",
+        )
+        .unwrap();
+        let routes = Routes::from_path(&path).unwrap();
+
+        let account = |locked| Account {
+            available: Decimal::ZERO,
+            holds: Holds::default(),
+            total: Decimal::ZERO,
+            locked,
+            disputed_lifetime: Decimal::ZERO,
+        };
+        let accounts: Accounts = [
+            (1, account(false)),
+            (2, account(true)),
+            (150, account(false)),
+            (151, account(true)),
+            (550, account(false)),
+        ]
+        .into_iter()
+        .collect();
+
+        let (rest, routed) = routes.split(accounts);
+        let clients = |accounts: &Accounts| {
+            let mut clients: Vec<_> = accounts.keys().copied().collect();
+            clients.sort_unstable();
+            clients
+        };
+        assert_eq!(clients(&rest), [1]);
+        assert_eq!(routed.len(), 2);
+        assert_eq!(routed[0].0, Path::new("locked.csv"));
+        assert_eq!(clients(&routed[0].1), [2, 151, 550]);
+        assert_eq!(routed[1].0, Path::new("institutional.csv"));
+        assert_eq!(clients(&routed[1].1), [150]);
+
+        fs::write(&path, "rule,output\ngold,gold.csv\n").unwrap();
+        assert!(Routes::from_path(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("line 2: unknown rule \"gold\""));
+    }
+}
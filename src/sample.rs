@@ -0,0 +1,96 @@
+use exchange_test::{Account, Record};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::error::Error;
+use std::path::Path;
+
+use crate::output::AtomicFile;
+
+// Writes a random sample of the applied transactions, each with the balances it left its
+// account with, for manual spot checks. The same seed and input always give the same sample.
+pub struct Sampler {
+    rate: f64,
+    rng: StdRng,
+    wtr: csv::Writer<AtomicFile>,
+}
+
+impl Sampler {
+    pub fn create(path: &Path, rate: f64, seed: u64) -> Result<Sampler, Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_writer(AtomicFile::create(path)?);
+        wtr.write_record([
+            "file",
+            "line",
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "available",
+            "held",
+            "total",
+            "locked",
+        ])?;
+        Ok(Sampler {
+            rate,
+            rng: StdRng::seed_from_u64(seed),
+            wtr,
+        })
+    }
+
+    // Called for every applied transaction; writes it out if it is drawn into the sample
+    pub fn offer(
+        &mut self,
+        file: &Path,
+        line: u64,
+        record: &Record,
+        account: &Account,
+    ) -> csv::Result<()> {
+        if !self.rng.random_bool(self.rate) {
+            return Ok(());
+        }
+        self.wtr.write_record([
+            file.to_string_lossy().as_ref(),
+            &line.to_string(),
+            record.tx_type.as_str(),
+            &record.client.to_string(),
+            &record.tx.to_string(),
+            &record.amount.map(|a| a.to_string()).unwrap_or_default(),
+            &format!("{:.4}", account.available),
+            &format!("{:.4}", account.held()),
+            &format!("{:.4}", account.total),
+            &account.locked.to_string(),
+        ])
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.wtr.into_inner()?.commit()
+    }
+}
+
+// Parses a sampling rate given as a fraction ("0.001") or a percentage ("0.1%")
+pub fn parse_sample_rate(value: &str) -> Result<f64, String> {
+    let rate = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => value.parse::<f64>(),
+    }
+    .map_err(|e| e.to_string())?;
+
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{} is not between 0 and 100%", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_rates_parse_as_fraction_or_percentage() {
+        assert_eq!(parse_sample_rate("0.1%"), Ok(0.001));
+        assert_eq!(parse_sample_rate("0.25"), Ok(0.25));
+        assert_eq!(parse_sample_rate("100%"), Ok(1.0));
+        assert!(parse_sample_rate("150%").is_err());
+        assert!(parse_sample_rate("often").is_err());
+    }
+}
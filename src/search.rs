@@ -0,0 +1,83 @@
+use exchange_test::Record;
+use log::debug;
+use std::error::Error;
+use std::io;
+
+use crate::cli::SearchArgs;
+use crate::source::{open_source, Row};
+
+impl SearchArgs {
+    fn matches(&self, record: &Record) -> bool {
+        // Rows without an amount (disputes, resolves, chargebacks) never match an amount range
+        let amount_in_range = match (self.min_amount, self.max_amount) {
+            (None, None) => true,
+            (min, max) => record.amount.is_some_and(|amount| {
+                min.is_none_or(|min| amount >= min) && max.is_none_or(|max| amount <= max)
+            }),
+        };
+
+        amount_in_range
+            && self.client.is_none_or(|client| record.client == client)
+            && self.tx.is_none_or(|tx| record.tx == tx)
+            && self.tx_type.is_none_or(|tx_type| record.tx_type == tx_type)
+    }
+}
+
+// Streams the input and writes every matching row, prefixed with its source line, to stdout
+pub fn search_transactions(args: &SearchArgs) -> Result<(), Box<dyn Error>> {
+    let mut source = open_source(&args.input, &args.input_args)?;
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    wtr.write_record(["line", "type", "client", "tx", "amount"])?;
+
+    while let Some((line, row)) = source.next_row()? {
+        let record = match row {
+            Row::Parsed(record) => record,
+            Row::Invalid { reason, .. } => {
+                debug!("Skipping unparseable row at line {}: {}", line, reason);
+                continue;
+            }
+        };
+
+        if args.matches(&record) {
+            let mut fields = source.raw_fields();
+            fields.insert(0, line.to_string());
+            wtr.write_record(&fields)?;
+        }
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::InputArgs;
+    use exchange_test::TxType;
+    use rust_decimal::Decimal;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_filters_combine() {
+        let args = SearchArgs {
+            input: PathBuf::from("-"),
+            input_args: InputArgs::default(),
+            client: Some(1),
+            tx: None,
+            tx_type: None,
+            min_amount: Some(Decimal::new(10, 0)),
+            max_amount: None,
+        };
+        let record = |client, amount| Record {
+            tx_type: TxType::Deposit,
+            client,
+            tx: 1,
+            amount,
+        };
+
+        assert!(args.matches(&record(1, Some(Decimal::new(10, 0)))));
+        assert!(!args.matches(&record(1, Some(Decimal::new(9, 0)))));
+        assert!(!args.matches(&record(2, Some(Decimal::new(10, 0)))));
+        assert!(!args.matches(&record(1, None)));
+    }
+}
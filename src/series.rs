@@ -0,0 +1,92 @@
+use exchange_test::{Accounts, ClientId};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+
+use crate::output::AtomicFile;
+
+// Writes end-of-day balances from a single pass over inputs in date order: when a row of a
+// later day comes in, every client whose account changed during the day before is written
+// with its balances at that point. Clients are left out on days their account did not
+// change, so a client's series holds its balance from each of its rows until the next one.
+pub struct BalanceSeries {
+    wtr: csv::Writer<AtomicFile>,
+    day: Option<String>,
+    changed: BTreeSet<ClientId>,
+}
+
+impl BalanceSeries {
+    pub fn create(path: &Path) -> Result<BalanceSeries, Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_writer(AtomicFile::create(path)?);
+        wtr.write_record(["client", "date", "available", "held", "total"])?;
+        Ok(BalanceSeries {
+            wtr,
+            day: None,
+            changed: BTreeSet::new(),
+        })
+    }
+
+    // Called with the `timestamp` of each record before it is applied, while the accounts
+    // still hold the balances of the day before when the record starts a new day
+    pub fn advance(&mut self, timestamp: Option<&str>, accounts: &Accounts) -> Result<(), String> {
+        let date = timestamp.and_then(parse_date).ok_or_else(|| {
+            format!(
+                "expected a `timestamp` column starting with a YYYY-MM-DD date, found {:?}",
+                timestamp.unwrap_or_default()
+            )
+        })?;
+        match self.day.as_deref() {
+            Some(day) if date == day => return Ok(()),
+            Some(day) if date < day => {
+                return Err(format!(
+                    "{} comes after {}; the balance series needs inputs in date order",
+                    date, day
+                ))
+            }
+            _ => {}
+        }
+        self.write_day(accounts).map_err(|e| e.to_string())?;
+        self.day = Some(date.to_string());
+        Ok(())
+    }
+
+    // Notes a client whose account the current record changed
+    pub fn changed(&mut self, client: ClientId) {
+        self.changed.insert(client);
+    }
+
+    fn write_day(&mut self, accounts: &Accounts) -> csv::Result<()> {
+        let Some(day) = &self.day else {
+            return Ok(());
+        };
+        for client in std::mem::take(&mut self.changed) {
+            let Some(account) = accounts.get(&client) else {
+                continue;
+            };
+            self.wtr.write_record([
+                &client.to_string(),
+                day,
+                &format!("{:.4}", account.available),
+                &format!("{:.4}", account.held()),
+                &format!("{:.4}", account.total),
+            ])?;
+        }
+        Ok(())
+    }
+
+    // Writes the last day with the final balances
+    pub fn finish(mut self, accounts: &Accounts) -> Result<(), Box<dyn Error>> {
+        self.write_day(accounts)?;
+        self.wtr.into_inner()?.commit()
+    }
+}
+
+// The date of an ISO 8601 timestamp such as `2024-03-01T09:30:00Z`, or of a bare date
+fn parse_date(timestamp: &str) -> Option<&str> {
+    let timestamp = timestamp.trim();
+    let date = timestamp.get(..10)?;
+    let digits = |range: std::ops::Range<usize>| date[range].bytes().all(|b| b.is_ascii_digit());
+    let separated = date.as_bytes()[4] == b'-' && date.as_bytes()[7] == b'-';
+    let rest_ok = timestamp[10..].is_empty() || timestamp[10..].starts_with(['T', ' ']);
+    (separated && digits(0..4) && digits(5..7) && digits(8..10) && rest_ok).then_some(date)
+}
@@ -0,0 +1,680 @@
+use exchange_test::{
+    Accounts, ClientId, EngineError, HashState, PaymentsEngine, Record, TransactionId, TxType,
+};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use crate::accounts::read_accounts;
+use crate::cli::{InputArgs, ProcessOptions};
+use crate::input::{split_input, InputPart};
+use crate::source::{open_source, resume_source, InputFormat, RecordSource, ResumePoint, Row};
+
+// Records are handed to the shards in batches, so the channels are not contended per record
+const BATCH: usize = 1024;
+// Batches a shard may have waiting from one parser before that parser blocks on it
+const QUEUED_BATCHES: usize = 4;
+
+// What one parser reads at a time: a whole input, or a run of the records of a CSV file
+// split between several parsers
+struct Chunk {
+    input: usize,
+    part: Option<InputPart>,
+}
+
+// The records of one chunk bound for one shard, numbered in the order the parser sent them
+// so the shard can check it applies them in input order
+struct Batch {
+    chunk: usize,
+    seq: u64,
+    // Each parsed record with its line, for error messages
+    records: Vec<(u64, Record)>,
+    // Whether this is the chunk's final batch for the shard
+    last: bool,
+}
+
+// Why a thread stopped, at the input and line where it did
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Failure {
+    input: usize,
+    line: u64,
+    message: String,
+}
+
+// Where a transaction id is used, and the shard of the client using it
+#[derive(Debug, Clone, Copy)]
+struct Use {
+    input: usize,
+    line: u64,
+    shard: usize,
+}
+
+// What a shard leaves when it is done or has stopped early: its engine, the ids its failed
+// disputes, resolves and chargebacks referred to, and the failure it stopped on
+struct Shard {
+    engine: PaymentsEngine,
+    references: Vec<TransactionId>,
+    failure: Option<Failure>,
+}
+
+// Processes the inputs on `shards` worker threads, each with an engine of its own for the
+// clients whose id modulo `shards` is its index. The inputs are parsed on `parsers` threads
+// in chunks: whole inputs, or with more parsers than inputs, runs of whole records of each
+// CSV file. Parser p reads chunks p, p + parsers, and so on, and each record is routed to
+// its client's shard. A shard takes the chunks in order, from the parser reading each, and
+// checks every batch's sequence number, so each client's records are applied in input order
+// and the result is that of a serial run. Rows that fail to parse are reported by their
+// parser, failed records by the shard that applied them. The shards' accounts are disjoint
+// and merge into the result.
+//
+// Each shard only knows its own clients' transactions, so a transaction id used by clients
+// of two shards, by a reused id or by a dispute of another client's transaction, would not
+// be checked as in a serial run. Once the shards are done, the ids each stored or refused
+// are looked up in the others, as are those its failed references were after, and a run
+// with any such id shared fails instead.
+pub fn process_sharded(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    shards: usize,
+    parsers: usize,
+) -> Result<Accounts, Box<dyn Error>> {
+    let mut initial: Vec<Accounts> = vec![Accounts::default(); shards];
+    if let Some(path) = &options.initial_accounts {
+        for (client, account) in read_accounts(path)? {
+            initial[shard_of(client, shards)].insert(client, account);
+        }
+    }
+
+    // One channel from every parser to every shard
+    let chunks = &chunks(inputs, &options.input_args, parsers);
+    let parsers = parsers.min(chunks.len());
+    let mut senders: Vec<Vec<SyncSender<Batch>>> = (0..parsers).map(|_| Vec::new()).collect();
+    let mut receivers: Vec<Vec<Receiver<Batch>>> = (0..shards).map(|_| Vec::new()).collect();
+    for to_shards in &mut senders {
+        for from_parsers in &mut receivers {
+            let (sender, receiver) = sync_channel(QUEUED_BATCHES);
+            to_shards.push(sender);
+            from_parsers.push(receiver);
+        }
+    }
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = initial
+            .into_iter()
+            .zip(receivers)
+            .map(|(accounts, receivers)| {
+                let engine = PaymentsEngine::with_accounts(accounts, options.engine_config());
+                scope.spawn(move || run_shard(engine, receivers, inputs, chunks, options.strict))
+            })
+            .collect();
+        let readers: Vec<_> = senders
+            .into_iter()
+            .enumerate()
+            .map(|(first, senders)| {
+                scope.spawn(move || route(inputs, chunks, options, first, parsers, senders))
+            })
+            .collect();
+
+        // A thread stops early on a failure, and the threads depending on it stop on their
+        // next batch to or from it without failing themselves. A parser that stops still
+        // sends every shard the records it has read, so every failure before the earliest
+        // one is still found and the error reported is the same as in a serial run.
+        let mut failures = Vec::new();
+        for reader in readers {
+            if let Err(failure) = reader.join().expect("parser thread panicked") {
+                failures.push(failure);
+            }
+        }
+        let shard_results: Vec<Shard> = workers
+            .into_iter()
+            .map(|worker| worker.join().expect("shard thread panicked"))
+            .collect();
+        let shared = shared_ids(&shard_results)?;
+        if !shared.is_empty() {
+            if let Some(conflict) = first_shared_use(inputs, options, shards, &shared) {
+                // Whatever its own shard made of the record is down to the conflict
+                failures.retain(|f| (f.input, f.line) != (conflict.input, conflict.line));
+                failures.push(conflict);
+            }
+        }
+
+        let mut accounts = Accounts::default();
+        for shard in shard_results {
+            if let Some(failure) = shard.failure {
+                failures.push(failure);
+                continue;
+            }
+            for record in shard.engine.queued() {
+                warn!(
+                    "{:?} transaction {} for locked client {} was queued but never applied",
+                    record.tx_type, record.tx, record.client
+                );
+            }
+            accounts.extend(shard.engine.finalize());
+        }
+        match failures.into_iter().min() {
+            Some(failure) => Err(failure.message.into()),
+            None => Ok(accounts),
+        }
+    })
+}
+
+fn shard_of(client: ClientId, shards: usize) -> usize {
+    client as usize % shards
+}
+
+// Splits the inputs into the chunks the parsers read. With more parsers than inputs, each
+// CSV file is split into as many runs as there are parsers for every input, so that even a
+// single input is parsed in parallel.
+fn chunks(inputs: &[PathBuf], args: &InputArgs, parsers: usize) -> Vec<Chunk> {
+    let per_input = parsers.div_ceil(inputs.len().max(1));
+    let mut chunks = Vec::new();
+    for (input, path) in inputs.iter().enumerate() {
+        let parts = if per_input > 1 && args.input_format == InputFormat::Csv {
+            // An input that cannot be split, or fails to read, is read whole; its parser then
+            // reports any error as in a serial run
+            split_input(path, args.delimiter, args.max_line_length, per_input).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if parts.len() < 2 {
+            chunks.push(Chunk { input, part: None });
+        } else {
+            chunks.extend(parts.into_iter().map(|part| Chunk {
+                input,
+                part: Some(part),
+            }));
+        }
+    }
+    chunks
+}
+
+// Reads every `step`th chunk from `first` on, sending each parsed record to its client's
+// shard
+fn route(
+    inputs: &[PathBuf],
+    chunks: &[Chunk],
+    options: &ProcessOptions,
+    first: usize,
+    step: usize,
+    senders: Vec<SyncSender<Batch>>,
+) -> Result<(), Failure> {
+    for c in (first..chunks.len()).step_by(step) {
+        let mut batches = Batches::new(c, &senders);
+        let read = route_chunk(inputs, &chunks[c], options, &mut batches);
+        // The records read before a failure still go to their shards, so that a failing row
+        // among them is found and the earliest failure is reported
+        let sent = batches.finish();
+        match read {
+            Ok(true) if sent => {}
+            // A shard only stops taking records after a failure, which is reported instead
+            Ok(_) => return Ok(()),
+            Err(failure) => return Err(failure),
+        }
+    }
+    Ok(())
+}
+
+// Routes the records of a chunk; returns false if a shard stopped taking them
+fn route_chunk(
+    inputs: &[PathBuf],
+    chunk: &Chunk,
+    options: &ProcessOptions,
+    batches: &mut Batches,
+) -> Result<bool, Failure> {
+    let input = &inputs[chunk.input];
+    let mut line = chunk.part.map_or(0, |part| part.line);
+    let fail = |line, message| Failure {
+        input: chunk.input,
+        line,
+        message,
+    };
+    let mut source =
+        open_chunk(input, chunk, &options.input_args).map_err(|e| fail(line, e.to_string()))?;
+    while let Some((at, row)) = source.next_row().map_err(|e| fail(line, e.to_string()))? {
+        line = at;
+        match row {
+            Row::Parsed(record) => {
+                if !batches.push(line, record) {
+                    return Ok(false);
+                }
+            }
+            Row::Invalid { code, reason } => {
+                report(input, line, code, &reason, options.strict).map_err(|m| fail(line, m))?
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn open_chunk(
+    input: &Path,
+    chunk: &Chunk,
+    args: &InputArgs,
+) -> Result<Box<dyn RecordSource>, Box<dyn Error>> {
+    match chunk.part {
+        Some(part) => {
+            let start = ResumePoint {
+                line: part.line,
+                byte: part.start,
+            };
+            resume_source(input, args, start, Some(part.end))
+        }
+        None => open_source(input, args),
+    }
+}
+
+// The records of one chunk on their way to the shards, collected per shard into batches
+struct Batches<'a> {
+    chunk: usize,
+    senders: &'a [SyncSender<Batch>],
+    seqs: Vec<u64>,
+    pending: Vec<Vec<(u64, Record)>>,
+}
+
+impl Batches<'_> {
+    fn new(chunk: usize, senders: &[SyncSender<Batch>]) -> Batches<'_> {
+        Batches {
+            chunk,
+            senders,
+            seqs: vec![0; senders.len()],
+            pending: vec![Vec::with_capacity(BATCH); senders.len()],
+        }
+    }
+
+    // Adds a record to its shard's batch, sending the batch once it is full; returns false
+    // if the shard has stopped
+    fn push(&mut self, line: u64, record: Record) -> bool {
+        let shard = shard_of(record.client, self.senders.len());
+        self.pending[shard].push((line, record));
+        if self.pending[shard].len() < BATCH {
+            return true;
+        }
+        let records = std::mem::replace(&mut self.pending[shard], Vec::with_capacity(BATCH));
+        self.send(shard, records, false)
+    }
+
+    // Sends every shard the last batch of the chunk with the records it has not been sent;
+    // returns false if a shard has stopped
+    fn finish(mut self) -> bool {
+        let mut sent = true;
+        for shard in 0..self.senders.len() {
+            let records = std::mem::take(&mut self.pending[shard]);
+            sent &= self.send(shard, records, true);
+        }
+        sent
+    }
+
+    fn send(&mut self, shard: usize, records: Vec<(u64, Record)>, last: bool) -> bool {
+        let batch = Batch {
+            chunk: self.chunk,
+            seq: self.seqs[shard],
+            records,
+            last,
+        };
+        self.seqs[shard] += 1;
+        self.senders[shard].send(batch).is_ok()
+    }
+}
+
+// Applies the batches routed to one shard, chunk by chunk, until the last chunk is done
+fn run_shard(
+    engine: PaymentsEngine,
+    receivers: Vec<Receiver<Batch>>,
+    inputs: &[PathBuf],
+    chunks: &[Chunk],
+    strict: bool,
+) -> Shard {
+    let mut shard = Shard {
+        engine,
+        references: Vec::new(),
+        failure: None,
+    };
+    shard.failure = apply_chunks(&mut shard, receivers, inputs, chunks, strict).err();
+    shard
+}
+
+fn apply_chunks(
+    shard: &mut Shard,
+    receivers: Vec<Receiver<Batch>>,
+    inputs: &[PathBuf],
+    chunks: &[Chunk],
+    strict: bool,
+) -> Result<(), Failure> {
+    for (c, chunk) in chunks.iter().enumerate() {
+        let receiver = &receivers[c % receivers.len()];
+        let input = &inputs[chunk.input];
+        for seq in 0.. {
+            // The parser only hangs up early after a failure, which it reports
+            let Ok(batch) = receiver.recv() else {
+                return Ok(());
+            };
+            if (batch.chunk, batch.seq) != (c, seq) {
+                return Err(Failure {
+                    input: chunk.input,
+                    line: 0,
+                    message: format!(
+                        "Shard received batch {} of chunk {} while expecting batch {} of chunk {}",
+                        batch.seq, batch.chunk, seq, c
+                    ),
+                });
+            }
+            for (line, record) in batch.records {
+                let fail = |message| Failure {
+                    input: chunk.input,
+                    line,
+                    message,
+                };
+                match shard.engine.process(&record) {
+                    Ok(()) => {}
+                    Err(e @ EngineError::Storage(_)) => return Err(fail(e.to_string())),
+                    Err(e) => {
+                        if !matches!(record.tx_type, TxType::Deposit | TxType::Withdrawal) {
+                            shard.references.push(record.tx);
+                        }
+                        report(input, line, e.code(), &e.to_string(), strict).map_err(fail)?
+                    }
+                }
+            }
+            if batch.last {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The transaction ids used by clients of more than one shard: taken by deposits or
+// withdrawals on two shards, or taken on one and referred to in vain on another. Each id is
+// only looked up in the shards' own storage, so the check needs no memory of its own.
+fn shared_ids(shards: &[Shard]) -> Result<HashSet<TransactionId, HashState>, Box<dyn Error>> {
+    let mut shared = HashSet::default();
+    for (s, shard) in shards.iter().enumerate() {
+        let others =
+            || (shards.iter().enumerate()).filter_map(|(o, other)| (o != s).then_some(other));
+        let taken = shard.engine.storage().transaction_ids();
+        for tx in taken.chain(shard.references.iter().copied()) {
+            for other in others() {
+                if other.engine.uses_tx(tx)? {
+                    shared.insert(tx);
+                }
+            }
+        }
+    }
+    Ok(shared)
+}
+
+// The earliest use of one of the `shared` ids by a client of another shard than the one that
+// used it first, found by reading the inputs again. Only the shared ids are tracked, and
+// rows that cannot be read were reported by the parsers.
+fn first_shared_use(
+    inputs: &[PathBuf],
+    options: &ProcessOptions,
+    shards: usize,
+    shared: &HashSet<TransactionId, HashState>,
+) -> Option<Failure> {
+    let mut uses: HashMap<TransactionId, Use, HashState> = HashMap::default();
+    for (i, input) in inputs.iter().enumerate() {
+        let Ok(mut source) = open_source(input, &options.input_args) else {
+            continue;
+        };
+        while let Ok(Some((line, row))) = source.next_row() {
+            let Row::Parsed(record) = row else {
+                continue;
+            };
+            if !shared.contains(&record.tx) {
+                continue;
+            }
+            let at = Use {
+                input: i,
+                line,
+                shard: shard_of(record.client, shards),
+            };
+            let first = *uses.entry(record.tx).or_insert(at);
+            if first.shard != at.shard {
+                return Some(shared_tx(inputs, record.tx, first, at));
+            }
+        }
+    }
+    None
+}
+
+// The failure for a transaction id used on two shards, at the later of the two uses
+fn shared_tx(inputs: &[PathBuf], tx: TransactionId, first: Use, later: Use) -> Failure {
+    Failure {
+        input: later.input,
+        line: later.line,
+        message: format!(
+            "Cannot shard {} line {}: transaction {} is already used by a client of another \
+             shard at {} line {}; run without --shards",
+            inputs[later.input].display(),
+            later.line,
+            tx,
+            inputs[first.input].display(),
+            first.line
+        ),
+    }
+}
+
+// Logs a row that could not be applied, or fails on it in strict mode
+fn report(file: &Path, line: u64, code: &str, reason: &str, strict: bool) -> Result<(), String> {
+    if strict {
+        return Err(format!(
+            "Aborting in strict mode at {} line {}: {}",
+            file.display(),
+            line,
+            reason
+        ));
+    }
+    warn!(
+        "Failed to process transaction at {} line {} [{}]: {}",
+        file.display(),
+        line,
+        code,
+        reason
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::Parser;
+    use std::fmt::Write;
+    use std::fs;
+
+    fn serial_run(inputs: &[PathBuf]) -> Accounts {
+        let mut serial = PaymentsEngine::new();
+        for input in inputs {
+            let mut source = open_source(input, &Default::default()).unwrap();
+            while let Some((_, row)) = source.next_row().unwrap() {
+                if let Row::Parsed(record) = row {
+                    let _ = serial.process(&record);
+                }
+            }
+        }
+        serial.finalize()
+    }
+
+    #[test]
+    fn test_sharded_run_matches_serial_run() {
+        let input = PathBuf::from("tests/data/test_data.csv");
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "3"]).unwrap();
+        let sharded = process_sharded(std::slice::from_ref(&input), &cli.options, 3, 1).unwrap();
+        assert_eq!(sharded, serial_run(&[input]));
+    }
+
+    #[test]
+    fn test_strict_failure_in_an_unfilled_batch_is_reported() {
+        // Client 1's failing row waits in a batch that never fills while client 2's shard
+        // fails on a later row and stops the parser
+        let dir = tempfile::tempdir().unwrap();
+        let mut csv =
+            String::from("type,client,tx,amount\nwithdrawal,1,1,5.0\nwithdrawal,2,2,5.0\n");
+        for tx in 3..20 * BATCH as u32 {
+            writeln!(csv, "deposit,2,{tx},1.0").unwrap();
+        }
+        let input = dir.path().join("input.csv");
+        fs::write(&input, csv).unwrap();
+
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "2", "--strict"]).unwrap();
+        let error = process_sharded(&[input], &cli.options, 2, 1).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("line 2: Account 1 does not exist for transaction type Withdrawal"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_transactions_used_on_two_shards_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, csv: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("type,client,tx,amount\n{csv}")).unwrap();
+            path
+        };
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "2"]).unwrap();
+        let error = |inputs: &[PathBuf], parsers| {
+            process_sharded(inputs, &cli.options, 2, parsers)
+                .unwrap_err()
+                .to_string()
+        };
+
+        // Client 2 reuses client 1's id, which a serial run refuses as a duplicate
+        let duplicate = write("duplicate.csv", "deposit,1,1,5.0\ndeposit,2,1,3.0\n");
+        assert!(error(std::slice::from_ref(&duplicate), 1).contains(
+            "duplicate.csv line 3: transaction 1 is already used by a client of another shard at"
+        ));
+
+        // Client 2 disputes client 1's deposit, which a serial run finds
+        let dispute = write(
+            "dispute.csv",
+            "deposit,1,1,5.0\ndeposit,2,2,1.0\ndispute,2,1,\n",
+        );
+        assert!(error(&[dispute], 1).contains("dispute.csv line 4: transaction 1"));
+
+        // Inputs read by different parsers are compared once both are read
+        let first = write("first.csv", "deposit,1,7,5.0\n");
+        let second = write("second.csv", "deposit,3,8,1.0\nwithdrawal,2,7,1.0\n");
+        assert!(error(&[first, second], 2).contains("second.csv line 3: transaction 7"));
+
+        // Clients of the same shard may share an id, which their shard checks
+        let same_shard = write("same_shard.csv", "deposit,1,1,5.0\ndeposit,3,1,3.0\n");
+        let sharded =
+            process_sharded(std::slice::from_ref(&same_shard), &cli.options, 2, 1).unwrap();
+        assert_eq!(sharded, serial_run(&[same_shard]));
+    }
+
+    #[test]
+    fn test_single_input_is_parsed_in_parallel() {
+        // One input, split between the parsers, with comments, quoted fields spanning lines
+        // and a stray quote among its rows
+        let dir = tempfile::tempdir().unwrap();
+        let mut csv = String::from("# exported\ntype,client,tx,amount\n");
+        for tx in 1..20_000 {
+            match tx % 997 {
+                0 => writeln!(csv, "# checkpoint {tx}\n").unwrap(),
+                1 => writeln!(csv, "deposit,{},{tx},\"2.\n5\"", tx % 30).unwrap(),
+                2 => writeln!(csv, "deposit,{},{tx},\"3.0", tx % 30).unwrap(),
+                3 => writeln!(csv, "dispute,{},{},", (tx - 5) % 30, tx - 5).unwrap(),
+                _ => writeln!(csv, "deposit,{},{tx},\"1.{}\"", tx % 30, tx % 10).unwrap(),
+            }
+        }
+        let input = dir.path().join("input.csv");
+        fs::write(&input, &csv).unwrap();
+        let inputs = std::slice::from_ref(&input);
+        assert_eq!(chunks(inputs, &Default::default(), 4).len(), 4);
+
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "3"]).unwrap();
+        let serial = serial_run(inputs);
+        for parsers in [2, 4, 7] {
+            let sharded = process_sharded(inputs, &cli.options, 3, parsers).unwrap();
+            assert_eq!(sharded, serial, "{parsers} parsers");
+        }
+
+        // Lines are counted from the start of the input in every run of it
+        let mut csv = String::from("type,client,tx,amount\n");
+        for tx in 1..20_000 {
+            writeln!(csv, "deposit,{},{tx},1.0", tx % 30).unwrap();
+        }
+        writeln!(csv, "withdrawal,1,20000,1000000.0").unwrap();
+        fs::write(&input, &csv).unwrap();
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "3", "--strict"]).unwrap();
+        let error = |parsers| {
+            process_sharded(inputs, &cli.options, 3, parsers)
+                .unwrap_err()
+                .to_string()
+        };
+        let first = error(1);
+        assert!(first.contains("line 20001"), "{first}");
+        assert_eq!(error(4), first);
+    }
+
+    #[test]
+    fn test_parallel_parsing_matches_serial_run() {
+        // Several inputs of interleaved clients, longer than a batch, whose disputes,
+        // resolves and chargebacks refer back to deposits in earlier inputs, so any
+        // reordering across inputs or batches changes the result
+        let dir = tempfile::tempdir().unwrap();
+        let mut inputs = Vec::new();
+        let mut state: u64 = 7;
+        let mut next = |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % n
+        };
+        // Ids start above those of corrupted_rows.csv, which is processed along with them
+        let first_tx = 1000;
+        let mut tx = first_tx;
+        for i in 0..5 {
+            let mut csv = String::from("type,client,tx,amount\n");
+            for _ in 0..3000 {
+                let roll = next(10);
+                if roll < 5 || tx == first_tx {
+                    tx += 1;
+                    let kind = if roll < 3 { "deposit" } else { "withdrawal" };
+                    writeln!(csv, "{kind},{},{tx},{}.{}", tx % 40, next(50), next(10)).unwrap();
+                } else {
+                    // Refer to a recent transaction of its client
+                    let target = tx - next(tx.min(200));
+                    let kind = ["dispute", "resolve", "chargeback", "dispute", "resolve"]
+                        [roll as usize - 5];
+                    writeln!(csv, "{kind},{},{target},", target % 40).unwrap();
+                }
+            }
+            let path = dir.path().join(format!("input{i}.csv"));
+            fs::write(&path, csv).unwrap();
+            inputs.push(path);
+        }
+        inputs.push(PathBuf::from("tests/data/corrupted_rows.csv"));
+
+        let serial = serial_run(&inputs);
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "4"]).unwrap();
+        for shards in [1, 3, 4] {
+            for parsers in [1, 2, 6, 8] {
+                let sharded = process_sharded(&inputs, &cli.options, shards, parsers).unwrap();
+                assert_eq!(sharded, serial, "{shards} shards, {parsers} parsers");
+            }
+        }
+
+        // In strict mode the first failure in input order is the one reported
+        let cli = Cli::try_parse_from(["exchange_test", "--shards", "4", "--strict"]).unwrap();
+        let error = |parsers| {
+            process_sharded(&inputs, &cli.options, 4, parsers)
+                .unwrap_err()
+                .to_string()
+        };
+        let first = error(1);
+        assert!(first.contains("input0.csv"), "{first}");
+        for parsers in [2, 6] {
+            assert_eq!(error(parsers), first);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use exchange_test::Accounts;
+use postgres::{Client, NoTls};
+use std::error::Error;
+
+// Upserts the final account rows into the `accounts` table of a Postgres database, creating
+// the table if needed. All rows are written in one transaction.
+pub fn write_accounts_postgres(url: &str, accounts: &Accounts) -> Result<(), Box<dyn Error>> {
+    let mut client = Client::connect(url, NoTls)?;
+    let mut tx = client.transaction()?;
+    tx.batch_execute(
+        "CREATE TABLE IF NOT EXISTS accounts (
+            client INTEGER PRIMARY KEY,
+            available NUMERIC(38, 4) NOT NULL,
+            held NUMERIC(38, 4) NOT NULL,
+            total NUMERIC(38, 4) NOT NULL,
+            locked BOOLEAN NOT NULL
+        )",
+    )?;
+
+    let upsert = tx.prepare(
+        "INSERT INTO accounts (client, available, held, total, locked)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (client) DO UPDATE SET
+            available = EXCLUDED.available,
+            held = EXCLUDED.held,
+            total = EXCLUDED.total,
+            locked = EXCLUDED.locked",
+    )?;
+    for (client, account) in accounts {
+        tx.execute(
+            &upsert,
+            &[
+                &i32::from(*client),
+                &account.available,
+                &account.held(),
+                &account.total,
+                &account.locked,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
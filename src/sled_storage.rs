@@ -0,0 +1,122 @@
+use bincode::config;
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::state::StoredRecord;
+use crate::{Record, Storage, StorageError, TransactionId};
+
+// Keeps transactions and open disputes in a sled database, so the transaction history can
+// outgrow RAM; sled caches the hot part of it in memory. Keys are big-endian tx ids.
+pub struct SledStorage {
+    transactions: sled::Tree,
+    disputes: sled::Tree,
+}
+
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<SledStorage, StorageError> {
+        SledStorage::from_db(sled::open(path).map_err(storage_error)?)
+    }
+
+    pub fn from_db(db: sled::Db) -> Result<SledStorage, StorageError> {
+        Ok(SledStorage {
+            transactions: db.open_tree("transactions").map_err(storage_error)?,
+            disputes: db.open_tree("disputes").map_err(storage_error)?,
+        })
+    }
+}
+
+fn storage_error(e: impl Display) -> StorageError {
+    StorageError(e.to_string())
+}
+
+impl Storage for SledStorage {
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        let Some(bytes) = self
+            .transactions
+            .get(tx.to_be_bytes())
+            .map_err(storage_error)?
+        else {
+            return Ok(None);
+        };
+        let (stored, _): (StoredRecord, usize) =
+            bincode::decode_from_slice(&bytes, config::standard()).map_err(storage_error)?;
+        Ok(Some(Record::try_from(stored).map_err(storage_error)?))
+    }
+
+    fn contains_transaction(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        self.transactions
+            .contains_key(tx.to_be_bytes())
+            .map_err(storage_error)
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        let bytes = bincode::encode_to_vec(StoredRecord::from(record), config::standard())
+            .map_err(storage_error)?;
+        self.transactions
+            .insert(record.tx.to_be_bytes(), bytes)
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        self.disputes
+            .contains_key(tx.to_be_bytes())
+            .map_err(storage_error)
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes
+            .insert(tx.to_be_bytes(), &[])
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes
+            .remove(tx.to_be_bytes())
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        Ok(self.disputes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, PaymentsEngine, TxType};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_disputes_are_resolved_from_sled() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let storage = SledStorage::from_db(db).unwrap();
+        let mut engine = PaymentsEngine::with_storage(storage, EngineConfig::default()).unwrap();
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+
+        for r in [
+            record(TxType::Deposit, 1, Some(Decimal::new(12345, 4))),
+            record(TxType::Deposit, 2, Some(Decimal::new(1, 0))),
+            record(TxType::Dispute, 1, None),
+        ] {
+            engine.process(&r).unwrap();
+        }
+        assert_eq!(engine.accounts()[&1].held(), Decimal::new(12345, 4));
+        assert_eq!(engine.open_disputes().unwrap(), 1);
+
+        engine
+            .process(&record(TxType::Chargeback, 1, None))
+            .unwrap();
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.total, Decimal::new(1, 0));
+        assert!(account.locked);
+        assert_eq!(engine.open_disputes().unwrap(), 0);
+    }
+}
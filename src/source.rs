@@ -0,0 +1,609 @@
+use clap::ValueEnum;
+use csv::{ByteRecord, Position, StringRecord};
+use exchange_test::{Record, TxType};
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::io::BufRead;
+use std::num::ParseIntError;
+use std::path::Path;
+use std::str::{self, FromStr};
+
+use crate::cli::InputArgs;
+use crate::input::{
+    is_corrupted_row, open_buffered, read_line_bounded, resumed_transaction_reader,
+    transaction_reader, TransactionReader,
+};
+
+// Supported encodings of the transactions input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    // One JSON object per line, with the same fields as the CSV columns
+    Jsonl,
+    // Length-delimited protobuf `Transaction` messages (see proto/transaction.proto);
+    // requires the `proto` feature
+    Proto,
+    // An Avro object container file; requires the `avro` feature
+    Avro,
+}
+
+// One row of input: either a parsed record, or the reason it could not be parsed
+pub enum Row {
+    Parsed(Record),
+    Invalid { code: &'static str, reason: String },
+}
+
+// How far into an input a source has read: the rows in the first `line` lines, `byte`
+// bytes long, have all been returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumePoint {
+    pub line: u64,
+    pub byte: u64,
+}
+
+// A stream of input rows tagged with their source line numbers
+pub trait RecordSource {
+    // Advances to the next row, returning None once the input is exhausted. Errors are
+    // reserved for failures that make the rest of the input unreadable.
+    fn next_row(&mut self) -> Result<Option<(u64, Row)>, Box<dyn Error>>;
+
+    // The original type, client, tx and amount fields of the row last returned, for reports
+    fn raw_fields(&self) -> Vec<String>;
+
+    // The value of the named column in the row last returned, for formats that carry
+    // columns beyond the record's own
+    fn field(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    // Where `resume_source` could pick up after the row last returned, if the source can
+    // tell right now
+    fn resume_point(&self) -> Option<ResumePoint> {
+        None
+    }
+}
+
+pub fn open_source(
+    input: &Path,
+    args: &InputArgs,
+) -> Result<Box<dyn RecordSource>, Box<dyn Error>> {
+    Ok(match args.input_format {
+        InputFormat::Csv => Box::new(CsvSource::new(
+            transaction_reader(input, args.delimiter, args.max_line_length, args.mmap)?,
+            args.max_field_size,
+        )?),
+        InputFormat::Jsonl => Box::new(JsonLinesSource {
+            reader: open_buffered(input, args.mmap)?,
+            max_line_length: args.max_line_length,
+            buf: Vec::new(),
+            line: 0,
+            last: None,
+        }),
+        #[cfg(feature = "proto")]
+        InputFormat::Proto => Box::new(crate::proto::ProtoSource::new(
+            open_buffered(input, args.mmap)?,
+            args.max_line_length,
+        )),
+        #[cfg(not(feature = "proto"))]
+        InputFormat::Proto => {
+            return Err("protobuf input requires building with the `proto` feature".into())
+        }
+        #[cfg(feature = "avro")]
+        InputFormat::Avro => Box::new(crate::avro::AvroSource::new(crate::input::open_input(
+            input,
+        )?)?),
+        #[cfg(not(feature = "avro"))]
+        InputFormat::Avro => {
+            return Err("Avro input requires building with the `avro` feature".into())
+        }
+    })
+}
+
+// Opens the input to continue reading where a source stopped at `point`, up to byte `end`
+// if given
+pub fn resume_source(
+    input: &Path,
+    args: &InputArgs,
+    point: ResumePoint,
+    end: Option<u64>,
+) -> Result<Box<dyn RecordSource>, Box<dyn Error>> {
+    if args.input_format != InputFormat::Csv {
+        return Err("resuming is only supported for CSV input".into());
+    }
+    let (headers, rdr) = resumed_transaction_reader(
+        input,
+        args.delimiter,
+        args.max_line_length,
+        point.line,
+        point.byte,
+        end,
+    )?;
+    Ok(Box::new(CsvSource::with_headers(
+        rdr,
+        headers,
+        args.max_field_size,
+    )))
+}
+
+// The outcome of the last read from the CSV reader, held until queued quarantined lines
+// that precede it have been returned
+enum CsvRead {
+    Row(u64),
+    Corrupted {
+        line: u64,
+        start: Option<Position>,
+        reason: String,
+    },
+    End,
+}
+
+pub struct CsvSource {
+    rdr: TransactionReader,
+    headers: StringRecord,
+    columns: Columns,
+    max_field_size: usize,
+    // Rows are read as bytes and only the record's own columns are parsed, without going
+    // through serde, so a row costs no allocations once the buffers have grown
+    row: ByteRecord,
+    pending: Option<CsvRead>,
+    // Whether `row` holds the fields of the row last returned
+    row_returned: bool,
+}
+
+impl CsvSource {
+    pub fn new(
+        mut rdr: TransactionReader,
+        max_field_size: usize,
+    ) -> Result<CsvSource, Box<dyn Error>> {
+        let headers = rdr.headers()?.clone();
+        Ok(CsvSource::with_headers(rdr, headers, max_field_size))
+    }
+
+    // A source over a reader positioned past the header row
+    pub fn with_headers(
+        rdr: TransactionReader,
+        headers: StringRecord,
+        max_field_size: usize,
+    ) -> CsvSource {
+        CsvSource {
+            rdr,
+            columns: Columns::new(&headers),
+            headers,
+            max_field_size,
+            row: ByteRecord::new(),
+            pending: None,
+            row_returned: false,
+        }
+    }
+
+    fn source_line(&self, position: Option<&Position>) -> u64 {
+        position.map_or(u64::MAX, |pos| self.rdr.get_ref().source_line(pos.line()))
+    }
+
+    fn read(&mut self) -> Result<CsvRead, Box<dyn Error>> {
+        match self.rdr.read_byte_record(&mut self.row) {
+            // Rows are still held to be UTF-8 throughout, as reports echo their fields
+            Ok(true) if str::from_utf8(self.row.as_slice()).is_err() => {
+                let error = StringRecord::from_byte_record(self.row.clone()).unwrap_err();
+                Ok(CsvRead::Corrupted {
+                    line: self.source_line(self.row.position()),
+                    start: self.row.position().cloned(),
+                    reason: error.utf8_error().to_string(),
+                })
+            }
+            Ok(true) => Ok(CsvRead::Row(self.source_line(self.row.position()))),
+            Ok(false) => Ok(CsvRead::End),
+            // A malformed row only loses that row; parsing resumes at the next line
+            Err(error) if is_corrupted_row(&error) => Ok(CsvRead::Corrupted {
+                line: self.source_line(error.position()),
+                start: error.position().cloned(),
+                reason: corruption_reason(&error),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl RecordSource for CsvSource {
+    fn next_row(&mut self) -> Result<Option<(u64, Row)>, Box<dyn Error>> {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => self.read()?,
+        };
+        let line = match &pending {
+            CsvRead::Row(line) | CsvRead::Corrupted { line, .. } => *line,
+            CsvRead::End => u64::MAX,
+        };
+
+        // Lines the filter kept away from the parser are reported in input order
+        if let Some(q) = self.rdr.get_mut().pop_quarantined(line) {
+            self.pending = Some(pending);
+            self.row_returned = false;
+            let reason = format!("bytes {}..{}: {}", q.bytes.start, q.bytes.end, q.reason);
+            return Ok(Some((q.line, corrupted_row(reason))));
+        }
+
+        self.row_returned = false;
+        match pending {
+            CsvRead::Row(line) => {
+                // Oversized fields are not echoed back into reports
+                if let Some((i, field)) = (self.row.iter().enumerate())
+                    .find(|(_, field)| field.len() > self.max_field_size)
+                {
+                    let reason = format!(
+                        "field {} is {} bytes, over the limit of {}",
+                        i + 1,
+                        field.len(),
+                        self.max_field_size
+                    );
+                    return Ok(Some((
+                        line,
+                        Row::Invalid {
+                            code: "field_too_large",
+                            reason,
+                        },
+                    )));
+                }
+
+                self.row_returned = true;
+                let row = match self.columns.parse(&self.row) {
+                    Ok(record) => Row::Parsed(record),
+                    // Rows with bad field values (e.g. an unknown transaction type) are
+                    // rejected like any other invalid transaction
+                    Err(reason) => Row::Invalid {
+                        code: "invalid_record",
+                        reason,
+                    },
+                };
+                Ok(Some((line, row)))
+            }
+            CsvRead::Corrupted {
+                line,
+                start,
+                reason,
+            } => {
+                let filter = self.rdr.get_ref();
+                let start = start.map_or(0, |pos| filter.source_byte(pos.byte()));
+                let end = filter.source_byte(self.rdr.position().byte());
+                let reason = format!("bytes {}..{}: {}", start, end, reason);
+                Ok(Some((line, corrupted_row(reason))))
+            }
+            CsvRead::End => Ok(None),
+        }
+    }
+
+    fn raw_fields(&self) -> Vec<String> {
+        if !self.row_returned {
+            return Vec::new();
+        }
+        self.row.iter().take(4).map(field_string).collect()
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        if !self.row_returned {
+            return None;
+        }
+        let i = self
+            .headers
+            .iter()
+            .position(|header| header.trim() == name)?;
+        self.row.get(i).map(field_string)
+    }
+
+    fn resume_point(&self) -> Option<ResumePoint> {
+        // A read ahead of quarantined lines still to be returned is past the point
+        if self.pending.is_some() {
+            return None;
+        }
+        // The reader stands at the start of the next row; the last line and byte before
+        // it map back to the source
+        let pos = self.rdr.position();
+        let filter = self.rdr.get_ref();
+        Some(ResumePoint {
+            line: filter.source_line(pos.line().checked_sub(1)?),
+            byte: filter.source_byte(pos.byte().checked_sub(1)?) + 1,
+        })
+    }
+}
+
+fn corrupted_row(reason: String) -> Row {
+    Row::Invalid {
+        code: "corrupted_row",
+        reason,
+    }
+}
+
+// Where the record's columns are in an input, found once from its header row
+struct Columns {
+    tx_type: Option<usize>,
+    client: Option<usize>,
+    tx: Option<usize>,
+    amount: Option<usize>,
+}
+
+impl Columns {
+    fn new(headers: &StringRecord) -> Columns {
+        let find = |name| headers.iter().position(|header| header == name);
+        Columns {
+            tx_type: find("type"),
+            client: find("client"),
+            tx: find("tx"),
+            amount: find("amount"),
+        }
+    }
+
+    // Parses a UTF-8 checked row, describing a bad field the way deserializing it would
+    fn parse(&self, row: &ByteRecord) -> Result<Record, String> {
+        let value = |i: usize| str::from_utf8(row.get(i).unwrap_or_default()).unwrap_or_default();
+        let field = |column: Option<usize>, name: &str| match column {
+            Some(i) => Ok((i, value(i))),
+            None => Err(format!("missing field `{}`", name)),
+        };
+        let (_, tx_type) = field(self.tx_type, "type")?;
+        Ok(Record {
+            tx_type: TxType::from_str(tx_type).map_err(|e| e.to_string())?,
+            client: parse_id(field(self.client, "client")?)?,
+            tx: parse_id(field(self.tx, "tx")?)?,
+            // Like the amount column's csv::invalid_option, anything that is not a number is
+            // no amount at all
+            amount: self.amount.and_then(|i| parse_amount(value(i))),
+        })
+    }
+}
+
+fn parse_id<T: FromStr<Err = ParseIntError>>((i, value): (usize, &str)) -> Result<T, String> {
+    value.parse().map_err(|e| format!("field {}: {}", i, e))
+}
+
+// Amounts parse exactly, in plain or scientific notation. Trailing zeros are dropped so that
+// `1.50000` counts as one decimal place, as it did when amounts were read through floats.
+fn parse_amount(value: &str) -> Option<Decimal> {
+    if value.is_empty() {
+        return None;
+    }
+    Decimal::from_str(value)
+        .or_else(|_| Decimal::from_scientific(value))
+        .ok()
+        .map(|amount| amount.normalize())
+}
+
+// A field of a UTF-8 checked row
+fn field_string(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).into_owned()
+}
+
+// Describes a row-level CSV error, without csv's own (filter-unaware) position
+fn corruption_reason(e: &csv::Error) -> String {
+    match e.kind() {
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!("expected {} fields, found {}", expected_len, len),
+        csv::ErrorKind::Utf8 { err, .. } => err.to_string(),
+        _ => e.to_string(),
+    }
+}
+
+// Reads newline-delimited JSON objects; blank lines and `#` comment lines are skipped
+pub struct JsonLinesSource {
+    reader: Box<dyn BufRead>,
+    max_line_length: u64,
+    buf: Vec<u8>,
+    line: u64,
+    last: Option<Record>,
+}
+
+impl RecordSource for JsonLinesSource {
+    fn next_row(&mut self) -> Result<Option<(u64, Row)>, Box<dyn Error>> {
+        loop {
+            self.buf.clear();
+            self.last = None;
+            let len = read_line_bounded(
+                &mut self.reader,
+                &mut self.buf,
+                self.max_line_length,
+                self.line + 1,
+            )?;
+            if len == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            let text = self.buf.trim_ascii();
+            if text.is_empty() || text.starts_with(b"#") {
+                continue;
+            }
+
+            let row = match serde_json::from_slice::<Record>(text) {
+                Ok(record) => {
+                    self.last = Some(record);
+                    Row::Parsed(record)
+                }
+                Err(e) => Row::Invalid {
+                    code: "invalid_record",
+                    reason: e.to_string(),
+                },
+            };
+            return Ok(Some((self.line, row)));
+        }
+    }
+
+    fn raw_fields(&self) -> Vec<String> {
+        self.last.as_ref().map_or_else(Vec::new, record_fields)
+    }
+}
+
+// The report fields of a record decoded from a format without a textual row to echo back
+pub fn record_fields(record: &Record) -> Vec<String> {
+    vec![
+        record.tx_type.as_str().to_string(),
+        record.client.to_string(),
+        record.tx.to_string(),
+        record.amount.map(|a| a.to_string()).unwrap_or_default(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::TxType;
+    use rust_decimal::Decimal;
+    use std::fs;
+
+    #[test]
+    fn test_json_lines_are_parsed_with_line_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transactions.jsonl");
+        fs::write(
+            &path,
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1.5\"}\n\
+             \n\
+             {\"type\": \"dispute\", \"client\": 1, \"tx\": 1}\n\
+             {\"type\": \"refund\", \"client\": 1, \"tx\": 2}\n",
+        )
+        .unwrap();
+
+        let args = InputArgs {
+            input_format: InputFormat::Jsonl,
+            ..InputArgs::default()
+        };
+        let mut source = open_source(&path, &args).unwrap();
+        let Some((1, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+            panic!("expected a deposit on line 1");
+        };
+        assert_eq!(deposit.amount, Some(Decimal::new(15, 1)));
+        assert_eq!(source.raw_fields(), ["deposit", "1", "1", "1.5"]);
+
+        let Some((3, Row::Parsed(dispute))) = source.next_row().unwrap() else {
+            panic!("expected a dispute on line 3");
+        };
+        assert_eq!(dispute.tx_type, TxType::Dispute);
+        assert_eq!(dispute.amount, None);
+
+        assert!(matches!(
+            source.next_row().unwrap(),
+            Some((
+                4,
+                Row::Invalid {
+                    code: "invalid_record",
+                    ..
+                }
+            ))
+        ));
+        assert!(source.next_row().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_csv_columns_are_parsed_by_header_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transactions.csv");
+        fs::write(
+            &path,
+            "memo,amount,tx,client,type\n\
+             rent,1.50000,7,3,Withdrawal\n\
+             ,2e2,8,3,DEPOSIT\n\
+             ,abc,9,3,deposit\n\
+             ,1,10,x,deposit\n",
+        )
+        .unwrap();
+
+        let mut source = open_source(&path, &InputArgs::default()).unwrap();
+        let Some((2, Row::Parsed(withdrawal))) = source.next_row().unwrap() else {
+            panic!("expected a withdrawal on line 2");
+        };
+        assert_eq!(withdrawal.tx_type, TxType::Withdrawal);
+        assert_eq!((withdrawal.client, withdrawal.tx), (3, 7));
+        assert_eq!(withdrawal.amount.unwrap().scale(), 1);
+        assert_eq!(source.field("memo").as_deref(), Some("rent"));
+
+        let Some((3, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+            panic!("expected a deposit on line 3");
+        };
+        assert_eq!(deposit.amount, Some(Decimal::new(200, 0)));
+        let Some((4, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+            panic!("expected a deposit on line 4");
+        };
+        assert_eq!(deposit.amount, None);
+        let Some((5, Row::Invalid { reason, .. })) = source.next_row().unwrap() else {
+            panic!("expected line 5 to be rejected");
+        };
+        assert_eq!(reason, "field 3: invalid digit found in string");
+
+        fs::write(&path, "type,client,amount\ndeposit,1,1.0\n").unwrap();
+        let mut source = open_source(&path, &InputArgs::default()).unwrap();
+        let Some((2, Row::Invalid { reason, .. })) = source.next_row().unwrap() else {
+            panic!("expected line 2 to be rejected");
+        };
+        assert_eq!(reason, "missing field `tx`");
+    }
+
+    #[test]
+    fn test_oversized_csv_fields_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transactions.csv");
+        fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.00000000000\n",
+        )
+        .unwrap();
+
+        let args = InputArgs {
+            max_field_size: 8,
+            ..InputArgs::default()
+        };
+        let mut source = open_source(&path, &args).unwrap();
+        assert!(matches!(
+            source.next_row().unwrap(),
+            Some((2, Row::Parsed(_)))
+        ));
+        let Some((3, Row::Invalid { code, reason })) = source.next_row().unwrap() else {
+            panic!("expected line 3 to be rejected");
+        };
+        assert_eq!(code, "field_too_large");
+        assert_eq!(reason, "field 4 is 13 bytes, over the limit of 8");
+        assert!(source.raw_fields().is_empty());
+    }
+
+    #[test]
+    fn test_tab_and_semicolon_delimiters() {
+        let dir = tempfile::tempdir().unwrap();
+        for (delimiter, data) in [
+            (
+                b'\t',
+                "type\tclient\ttx\tamount\n\"deposit\"\t1\t1\t\"2.5\"\n",
+            ),
+            (
+                b';',
+                "type;client;tx;amount\ndeposit;1;1;2.5\n\"deposit;x\";1;2;1.0\n",
+            ),
+        ] {
+            let path = dir.path().join("transactions.csv");
+            fs::write(&path, data).unwrap();
+            let args = InputArgs {
+                delimiter,
+                ..InputArgs::default()
+            };
+
+            let mut source = open_source(&path, &args).unwrap();
+            let Some((2, Row::Parsed(deposit))) = source.next_row().unwrap() else {
+                panic!("expected a deposit on line 2");
+            };
+            assert_eq!(deposit.amount, Some(Decimal::new(25, 1)));
+            if delimiter == b';' {
+                // The quoted delimiter stays inside the type field instead of splitting it
+                assert!(matches!(
+                    source.next_row().unwrap(),
+                    Some((
+                        3,
+                        Row::Invalid {
+                            code: "invalid_record",
+                            ..
+                        }
+                    ))
+                ));
+                assert_eq!(source.raw_fields(), ["deposit;x", "1", "2", "1.0"]);
+            }
+            assert!(source.next_row().unwrap().is_none());
+        }
+    }
+}
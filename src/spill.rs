@@ -0,0 +1,177 @@
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::storage::{StoredTx, TxKind};
+use crate::{HashState, Record, Storage, StorageError, TransactionId};
+
+// Bytes of a transaction's slot: its kind, client and amount
+const SLOT: u64 = 20;
+// Kind byte of a slot never written, as the holes of the sparse file read back
+const EMPTY: u8 = 0;
+const DEPOSIT: u8 = 1;
+const WITHDRAWAL: u8 = 2;
+
+// Keeps transactions in a temporary file indexed by tx id: transaction `tx` lives at byte
+// `tx * 20`, so any lookup is a single read and nothing but open disputes stays in memory.
+// The file is sparse, taking disk space only for the ids in use, and is deleted when the
+// storage is dropped. Meant as the overflow of a WindowedStorage, which serves the recent
+// transactions from memory.
+pub struct SpillStorage {
+    file: File,
+    disputes: HashSet<TransactionId, HashState>,
+}
+
+impl SpillStorage {
+    // Creates the file in the system's temporary directory
+    pub fn new() -> Result<SpillStorage, StorageError> {
+        SpillStorage::from_file(tempfile::tempfile())
+    }
+
+    pub fn new_in(dir: &Path) -> Result<SpillStorage, StorageError> {
+        SpillStorage::from_file(tempfile::tempfile_in(dir))
+    }
+
+    fn from_file(file: io::Result<File>) -> Result<SpillStorage, StorageError> {
+        Ok(SpillStorage {
+            file: file.map_err(storage_error)?,
+            disputes: HashSet::default(),
+        })
+    }
+
+    fn read_slot(&self, tx: TransactionId) -> Result<Option<StoredTx>, StorageError> {
+        let mut slot = [0; SLOT as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(u64::from(tx) * SLOT))
+            .map_err(storage_error)?;
+        match file.read_exact(&mut slot) {
+            Ok(()) => {}
+            // Past the end of the file, where no transaction was written yet
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(storage_error(e)),
+        }
+        let kind = match slot[0] {
+            EMPTY => return Ok(None),
+            DEPOSIT => TxKind::Deposit,
+            WITHDRAWAL => TxKind::Withdrawal,
+            kind => {
+                return Err(StorageError(format!(
+                    "invalid spilled transaction kind {}",
+                    kind
+                )))
+            }
+        };
+        Ok(Some(StoredTx {
+            client: u16::from_le_bytes([slot[1], slot[2]]),
+            amount: Decimal::deserialize(slot[3..19].try_into().unwrap()),
+            kind,
+        }))
+    }
+}
+
+fn storage_error(e: io::Error) -> StorageError {
+    StorageError(format!("spill file: {}", e))
+}
+
+impl Storage for SpillStorage {
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        Ok(self.read_slot(tx)?.map(|stored| stored.record(tx)))
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        let stored = StoredTx::storable(record)?;
+        let mut slot = [0; SLOT as usize];
+        slot[0] = match stored.kind {
+            TxKind::Deposit => DEPOSIT,
+            TxKind::Withdrawal => WITHDRAWAL,
+        };
+        slot[1..3].copy_from_slice(&stored.client.to_le_bytes());
+        slot[3..19].copy_from_slice(&stored.amount.serialize());
+        self.file
+            .seek(SeekFrom::Start(u64::from(record.tx) * SLOT))
+            .map_err(storage_error)?;
+        self.file.write_all(&slot).map_err(storage_error)
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.disputes.contains(&tx))
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.insert(tx);
+        Ok(())
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.remove(&tx);
+        Ok(())
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        Ok(self.disputes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DedupWindow, EngineConfig, EngineError, PaymentsEngine, TxType, WindowedStorage};
+
+    #[test]
+    fn test_spilled_transactions_are_fetched_back() {
+        let window = DedupWindow {
+            max_entries: Some(1),
+            ttl: None,
+        };
+        let storage = WindowedStorage::new(SpillStorage::new().unwrap(), window);
+        let mut engine = PaymentsEngine::with_storage(storage, EngineConfig::default()).unwrap();
+        let record = |tx_type, client, tx, amount| Record {
+            tx_type,
+            client,
+            tx,
+            amount,
+        };
+
+        engine
+            .process(&record(TxType::Deposit, 1, 7, Some(Decimal::new(125, 2))))
+            .unwrap();
+        engine
+            .process(&record(TxType::Deposit, 2, 3, Some(Decimal::TEN)))
+            .unwrap();
+        engine
+            .process(&record(TxType::Deposit, 3, 4_000_000, Some(Decimal::ONE)))
+            .unwrap();
+
+        // Tx 7 and 3 were moved to the file; ids around them are holes or past its end
+        assert_eq!(engine.storage.recent(), 1);
+        assert_eq!(
+            engine.process(&record(TxType::Deposit, 1, 7, Some(Decimal::ONE))),
+            Err(EngineError::DuplicateTx(7))
+        );
+        assert!(engine
+            .process(&record(TxType::Dispute, 1, 6, None))
+            .is_err());
+        assert!(engine
+            .process(&record(TxType::Dispute, 1, 5_000_000, None))
+            .is_err());
+
+        engine
+            .process(&record(TxType::Dispute, 1, 7, None))
+            .unwrap();
+        engine
+            .process(&record(TxType::Chargeback, 1, 7, None))
+            .unwrap();
+        engine
+            .process(&record(TxType::Dispute, 2, 3, None))
+            .unwrap();
+        assert_eq!(engine.open_disputes().unwrap(), 1);
+
+        let accounts = engine.finalize();
+        assert_eq!(accounts[&1].total, Decimal::ZERO);
+        assert!(accounts[&1].locked);
+        assert_eq!(accounts[&2].held(), Decimal::TEN);
+        assert_eq!(accounts[&3].total, Decimal::ONE);
+    }
+}
@@ -0,0 +1,85 @@
+use exchange_test::{Account, ClientId};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use rust_xlsxwriter::{Format, Workbook};
+use std::error::Error;
+use std::io::Write;
+
+use crate::output::AmountColumn;
+
+const HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+type AmountField<'a> = &'a dyn Fn(&Account) -> Decimal;
+
+// Writes the accounts, already in output order, as a single worksheet with numeric amount
+// cells shown to four decimal places and the header row frozen in place. The extra amount
+// columns follow `locked`.
+pub fn write_accounts_xlsx<W: Write>(
+    rows: &[(&ClientId, &Account)],
+    extra_columns: &[AmountColumn],
+    mut writer: W,
+) -> Result<(), Box<dyn Error>> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet().set_name("accounts")?;
+    let bold = Format::new().set_bold();
+    let amount_format = Format::new().set_num_format("0.0000");
+
+    // Amount columns by index; Excel numbers are doubles, so amounts beyond 15 significant
+    // digits lose precision
+    let mut amounts: Vec<(u16, AmountField)> = vec![
+        (1, &|a| a.available),
+        (2, &Account::held),
+        (3, &|a| a.total),
+    ];
+    let mut header = HEADER.to_vec();
+    for (col, (name, amount)) in (5..).zip(extra_columns) {
+        header.push(name);
+        amounts.push((col, amount));
+    }
+
+    for (col, title) in (0..).zip(header) {
+        sheet.write_string_with_format(0, col, title, &bold)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+
+    for (row, (client, account)) in (1..).zip(rows) {
+        sheet.write_number(row, 0, **client)?;
+        for &(col, amount) in &amounts {
+            let value = amount(account).to_f64().unwrap_or(f64::NAN);
+            sheet.write_number_with_format(row, col, value, &amount_format)?;
+        }
+        sheet.write_boolean(row, 4, account.locked)?;
+    }
+
+    writer.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange_test::{PaymentsEngine, Record, TxType};
+
+    #[test]
+    fn test_accounts_are_written_as_a_workbook() {
+        let mut engine = PaymentsEngine::new();
+        engine
+            .process(&Record {
+                tx_type: TxType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(Decimal::new(15, 1)),
+            })
+            .unwrap();
+        let accounts = engine.finalize();
+        let rows: Vec<_> = accounts.iter().collect();
+
+        let mut output = Vec::new();
+        write_accounts_xlsx(&rows, &[], &mut output).unwrap();
+
+        // An xlsx file is a zip archive whose entry names are stored uncompressed
+        assert!(output.starts_with(b"PK\x03\x04"));
+        let names = String::from_utf8_lossy(&output);
+        assert!(names.contains("xl/worksheets/sheet1.xml"));
+    }
+}
@@ -0,0 +1,239 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use rust_decimal::Decimal;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{
+    Account, Accounts, ClientId, Holds, Record, Storage, StorageError, TransactionId, TxType,
+};
+
+// Persists accounts, transactions and open disputes in a SQLite database. Changes are made
+// inside a database transaction that `commit` closes, so a crashed run leaves the database
+// as of its last commit. Amounts are stored as decimal text with four places, like the
+// accounts CSV.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        client INTEGER PRIMARY KEY,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        total TEXT NOT NULL,
+        locked INTEGER NOT NULL,
+        disputed_held TEXT NOT NULL,
+        reserve_held TEXT NOT NULL,
+        disputed_lifetime TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        tx INTEGER PRIMARY KEY,
+        type TEXT NOT NULL,
+        client INTEGER NOT NULL,
+        amount TEXT
+    );
+    CREATE TABLE IF NOT EXISTS disputes (
+        tx INTEGER PRIMARY KEY
+    );
+";
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<SqliteStorage, StorageError> {
+        SqliteStorage::from_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    pub fn from_connection(conn: Connection) -> Result<SqliteStorage, StorageError> {
+        conn.execute_batch(SCHEMA).map_err(storage_error)?;
+        conn.execute_batch("BEGIN").map_err(storage_error)?;
+        Ok(SqliteStorage { conn })
+    }
+}
+
+fn storage_error(e: impl Display) -> StorageError {
+    StorageError(e.to_string())
+}
+
+fn decimal(text: &str) -> Result<Decimal, StorageError> {
+    Decimal::from_str(text).map_err(storage_error)
+}
+
+impl Storage for SqliteStorage {
+    fn load_accounts(&self) -> Result<Accounts, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT client, available, total, locked, disputed_held, reserve_held,
+                        disputed_lifetime
+                 FROM accounts",
+            )
+            .map_err(storage_error)?;
+        let mut rows = stmt.query([]).map_err(storage_error)?;
+
+        let mut accounts = Accounts::default();
+        while let Some(row) = rows.next().map_err(storage_error)? {
+            let text = |i| row.get::<_, String>(i).map_err(storage_error);
+            let account = Account {
+                available: decimal(&text(1)?)?,
+                holds: Holds {
+                    dispute: decimal(&text(4)?)?,
+                    reserve: decimal(&text(5)?)?,
+                },
+                total: decimal(&text(2)?)?,
+                locked: row.get(3).map_err(storage_error)?,
+                disputed_lifetime: decimal(&text(6)?)?,
+            };
+            accounts.insert(row.get(0).map_err(storage_error)?, account);
+        }
+        Ok(accounts)
+    }
+
+    fn commit(&mut self, accounts: &Accounts) -> Result<(), StorageError> {
+        {
+            let mut stmt = self
+                .conn
+                .prepare_cached(
+                    "INSERT OR REPLACE INTO accounts (client, available, held, total, locked,
+                                                      disputed_held, reserve_held,
+                                                      disputed_lifetime)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                )
+                .map_err(storage_error)?;
+            for (client, account) in accounts {
+                stmt.execute(params![
+                    client,
+                    format!("{:.4}", account.available),
+                    format!("{:.4}", account.held()),
+                    format!("{:.4}", account.total),
+                    account.locked,
+                    format!("{:.4}", account.holds.dispute),
+                    format!("{:.4}", account.holds.reserve),
+                    format!("{:.4}", account.disputed_lifetime),
+                ])
+                .map_err(storage_error)?;
+            }
+        }
+        self.conn
+            .execute_batch("COMMIT; BEGIN")
+            .map_err(storage_error)
+    }
+
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT type, client, amount FROM transactions WHERE tx = ?1")
+            .map_err(storage_error)?;
+        let row = stmt
+            .query_row([tx], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, ClientId>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })
+            .optional()
+            .map_err(storage_error)?;
+
+        let Some((tx_type, client, amount)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(Record {
+            tx_type: TxType::from_str(&tx_type).map_err(storage_error)?,
+            client,
+            tx,
+            amount: amount.as_deref().map(decimal).transpose()?,
+        }))
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        self.conn
+            .prepare_cached("INSERT INTO transactions VALUES (?1, ?2, ?3, ?4)")
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    record.tx,
+                    record.tx_type.as_str(),
+                    record.client,
+                    record.amount.map(|amount| format!("{:.4}", amount)),
+                ])
+            })
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        self.conn
+            .prepare_cached("SELECT 1 FROM disputes WHERE tx = ?1")
+            .and_then(|mut stmt| stmt.exists([tx]))
+            .map_err(storage_error)
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.conn
+            .prepare_cached("INSERT OR IGNORE INTO disputes VALUES (?1)")
+            .and_then(|mut stmt| stmt.execute([tx]))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.conn
+            .prepare_cached("DELETE FROM disputes WHERE tx = ?1")
+            .and_then(|mut stmt| stmt.execute([tx]))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM disputes", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, PaymentsEngine};
+
+    #[test]
+    fn test_committed_state_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("engine.sqlite");
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+
+        let mut engine = PaymentsEngine::with_storage(
+            SqliteStorage::open(&path).unwrap(),
+            EngineConfig::default(),
+        )
+        .unwrap();
+        engine
+            .process(&record(TxType::Deposit, 1, Some(Decimal::new(12345, 4))))
+            .unwrap();
+        engine.process(&record(TxType::Dispute, 1, None)).unwrap();
+        engine.commit().unwrap();
+        // Never committed, so lost when the engine goes away
+        engine
+            .process(&record(TxType::Deposit, 2, Some(Decimal::ONE)))
+            .unwrap();
+        drop(engine);
+
+        let mut engine = PaymentsEngine::with_storage(
+            SqliteStorage::open(&path).unwrap(),
+            EngineConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(engine.accounts()[&1].held(), Decimal::new(12345, 4));
+        assert_eq!(engine.accounts()[&1].total, Decimal::new(12345, 4));
+        engine.process(&record(TxType::Resolve, 1, None)).unwrap();
+        engine
+            .process(&record(TxType::Deposit, 2, Some(Decimal::ONE)))
+            .unwrap();
+    }
+}
@@ -0,0 +1,632 @@
+use bincode::{Decode, Encode};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use crate::storage::StoredTx;
+use crate::{
+    Account, ClientId, EngineConfig, Holds, MemoryStorage, PaymentsEngine, Record, StateError,
+    TransactionId, TxType,
+};
+
+// Layout of a state file, integers little-endian:
+//
+//   magic     4 bytes, "PEST"
+//   version   u32
+//   length    u64, size of the payload (from version 4)
+//   checksum  u32, CRC-32 of the payload (from version 4)
+//   payload   the version's State struct, bincode encoded with the standard config
+//
+// Files from elsewhere or from a newer version are refused instead of misread. Every older
+// version stays loadable: `read_state` upgrades it to the current layout.
+const MAGIC: &[u8; 4] = b"PEST";
+pub const STATE_VERSION: u32 = 5;
+
+// The stored forms mirror the engine's types so the file format only changes deliberately.
+// Decimals are kept in rust_decimal's lossless 16-byte form.
+#[derive(Encode, Decode)]
+struct StoredAccount {
+    available: [u8; 16],
+    dispute_held: [u8; 16],
+    reserve_held: [u8; 16],
+    total: [u8; 16],
+    locked: bool,
+    disputed_lifetime: [u8; 16],
+}
+
+// Versions 1 to 4 also had an authorization hold, which nothing ever filled
+#[derive(Encode, Decode)]
+struct StoredAccountV1 {
+    available: [u8; 16],
+    dispute_held: [u8; 16],
+    authorization_held: [u8; 16],
+    reserve_held: [u8; 16],
+    total: [u8; 16],
+    locked: bool,
+    disputed_lifetime: [u8; 16],
+}
+
+#[derive(Encode, Decode)]
+pub(crate) struct StoredRecord {
+    tx_type: u8,
+    client: ClientId,
+    tx: TransactionId,
+    amount: Option<[u8; 16]>,
+}
+
+#[derive(Encode, Decode)]
+struct StateV5 {
+    accounts: HashMap<ClientId, StoredAccount>,
+    transactions: HashMap<TransactionId, StoredRecord>,
+    disputes: HashSet<TransactionId>,
+    rejected: HashSet<TransactionId>,
+    queued: HashMap<ClientId, Vec<StoredRecord>>,
+    aliases: HashMap<ClientId, ClientId>,
+}
+
+// Version 4 frames the version 3 payload with its length and checksum
+#[derive(Encode, Decode)]
+struct StateV3 {
+    accounts: HashMap<ClientId, StoredAccountV1>,
+    transactions: HashMap<TransactionId, StoredRecord>,
+    disputes: HashSet<TransactionId>,
+    rejected: HashSet<TransactionId>,
+    queued: HashMap<ClientId, Vec<StoredRecord>>,
+    aliases: HashMap<ClientId, ClientId>,
+}
+
+// Version 1 had no queued transactions, version 2 no aliases
+#[derive(Encode, Decode)]
+struct StateV1 {
+    accounts: HashMap<ClientId, StoredAccountV1>,
+    transactions: HashMap<TransactionId, StoredRecord>,
+    disputes: HashSet<TransactionId>,
+    rejected: HashSet<TransactionId>,
+}
+
+#[derive(Encode, Decode)]
+struct StateV2 {
+    accounts: HashMap<ClientId, StoredAccountV1>,
+    transactions: HashMap<TransactionId, StoredRecord>,
+    disputes: HashSet<TransactionId>,
+    rejected: HashSet<TransactionId>,
+    queued: HashMap<ClientId, Vec<StoredRecord>>,
+}
+
+impl From<StateV1> for StateV2 {
+    fn from(state: StateV1) -> StateV2 {
+        StateV2 {
+            accounts: state.accounts,
+            transactions: state.transactions,
+            disputes: state.disputes,
+            rejected: state.rejected,
+            queued: HashMap::new(),
+        }
+    }
+}
+
+impl From<StateV2> for StateV3 {
+    fn from(state: StateV2) -> StateV3 {
+        StateV3 {
+            accounts: state.accounts,
+            transactions: state.transactions,
+            disputes: state.disputes,
+            rejected: state.rejected,
+            queued: state.queued,
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl From<StateV3> for StateV5 {
+    fn from(state: StateV3) -> StateV5 {
+        StateV5 {
+            accounts: convert_accounts(state.accounts),
+            transactions: state.transactions,
+            disputes: state.disputes,
+            rejected: state.rejected,
+            queued: state.queued,
+            aliases: state.aliases,
+        }
+    }
+}
+
+impl From<StateV5> for StateV3 {
+    fn from(state: StateV5) -> StateV3 {
+        StateV3 {
+            accounts: convert_accounts(state.accounts),
+            transactions: state.transactions,
+            disputes: state.disputes,
+            rejected: state.rejected,
+            queued: state.queued,
+            aliases: state.aliases,
+        }
+    }
+}
+
+fn convert_accounts<A, B: From<A>>(accounts: HashMap<ClientId, A>) -> HashMap<ClientId, B> {
+    accounts
+        .into_iter()
+        .map(|(client, account)| (client, account.into()))
+        .collect()
+}
+
+// Anything held for authorizations is kept held, as a reserve
+impl From<StoredAccountV1> for StoredAccount {
+    fn from(stored: StoredAccountV1) -> StoredAccount {
+        let reserve = Decimal::deserialize(stored.authorization_held)
+            + Decimal::deserialize(stored.reserve_held);
+        StoredAccount {
+            available: stored.available,
+            dispute_held: stored.dispute_held,
+            reserve_held: reserve.serialize(),
+            total: stored.total,
+            locked: stored.locked,
+            disputed_lifetime: stored.disputed_lifetime,
+        }
+    }
+}
+
+impl From<StoredAccount> for StoredAccountV1 {
+    fn from(stored: StoredAccount) -> StoredAccountV1 {
+        StoredAccountV1 {
+            available: stored.available,
+            dispute_held: stored.dispute_held,
+            authorization_held: Decimal::ZERO.serialize(),
+            reserve_held: stored.reserve_held,
+            total: stored.total,
+            locked: stored.locked,
+            disputed_lifetime: stored.disputed_lifetime,
+        }
+    }
+}
+
+impl From<&Account> for StoredAccount {
+    fn from(account: &Account) -> StoredAccount {
+        StoredAccount {
+            available: account.available.serialize(),
+            dispute_held: account.holds.dispute.serialize(),
+            reserve_held: account.holds.reserve.serialize(),
+            total: account.total.serialize(),
+            locked: account.locked,
+            disputed_lifetime: account.disputed_lifetime.serialize(),
+        }
+    }
+}
+
+impl From<StoredAccount> for Account {
+    fn from(stored: StoredAccount) -> Account {
+        Account {
+            available: Decimal::deserialize(stored.available),
+            holds: Holds {
+                dispute: Decimal::deserialize(stored.dispute_held),
+                reserve: Decimal::deserialize(stored.reserve_held),
+            },
+            total: Decimal::deserialize(stored.total),
+            locked: stored.locked,
+            disputed_lifetime: Decimal::deserialize(stored.disputed_lifetime),
+        }
+    }
+}
+
+const TX_TYPES: [TxType; 5] = [
+    TxType::Deposit,
+    TxType::Withdrawal,
+    TxType::Dispute,
+    TxType::Resolve,
+    TxType::Chargeback,
+];
+
+impl From<&Record> for StoredRecord {
+    fn from(record: &Record) -> StoredRecord {
+        StoredRecord {
+            tx_type: TX_TYPES.iter().position(|t| *t == record.tx_type).unwrap() as u8,
+            client: record.client,
+            tx: record.tx,
+            amount: record.amount.map(|amount| amount.serialize()),
+        }
+    }
+}
+
+impl TryFrom<StoredRecord> for Record {
+    type Error = StateError;
+
+    fn try_from(stored: StoredRecord) -> Result<Record, StateError> {
+        let tx_type = *TX_TYPES
+            .get(usize::from(stored.tx_type))
+            .ok_or(StateError::Corrupt("invalid transaction type"))?;
+        Ok(Record {
+            tx_type,
+            client: stored.client,
+            tx: stored.tx,
+            amount: stored.amount.map(Decimal::deserialize),
+        })
+    }
+}
+
+impl PaymentsEngine {
+    // Writes the complete engine state (accounts, transactions, disputes, rejected ids,
+    // queued transactions and aliases) so a later run can continue from it with
+    // `load_state`. The config is not included.
+    pub fn save_state<W: Write>(&self, writer: W) -> Result<(), StateError> {
+        let state = StateV5 {
+            accounts: self
+                .accounts
+                .iter()
+                .map(|(client, account)| (*client, account.into()))
+                .collect(),
+            transactions: self
+                .storage
+                .transactions
+                .iter()
+                .map(|(tx, stored)| (*tx, (&stored.record(*tx)).into()))
+                .collect(),
+            disputes: self.storage.disputes.iter().copied().collect(),
+            rejected: self.rejected.iter().copied().collect(),
+            queued: self
+                .queued
+                .iter()
+                .map(|(client, records)| (*client, records.iter().map(Into::into).collect()))
+                .collect(),
+            aliases: self.aliases.clone(),
+        };
+
+        write_state(state, STATE_VERSION, writer)
+    }
+
+    // Restores an engine saved with `save_state`, to run with the given config
+    pub fn load_state<R: Read>(
+        reader: R,
+        config: EngineConfig,
+    ) -> Result<PaymentsEngine, StateError> {
+        let state = read_state(reader)?.state;
+        Ok(PaymentsEngine {
+            accounts: state
+                .accounts
+                .into_iter()
+                .map(|(client, account)| (client, account.into()))
+                .collect(),
+            storage: MemoryStorage {
+                transactions: state
+                    .transactions
+                    .into_iter()
+                    .map(|(tx, record)| {
+                        let record: Record = record.try_into()?;
+                        let stored = StoredTx::new(&record)
+                            .ok_or(StateError::Corrupt("invalid stored transaction"))?;
+                        Ok((tx, stored))
+                    })
+                    .collect::<Result<_, StateError>>()?,
+                disputes: state.disputes.into_iter().collect(),
+            },
+            rejected: state.rejected.into_iter().collect(),
+            queued: state
+                .queued
+                .into_iter()
+                .map(|(client, records)| {
+                    let records = records
+                        .into_iter()
+                        .map(Record::try_from)
+                        .collect::<Result<_, _>>()?;
+                    Ok((client, records))
+                })
+                .collect::<Result<_, StateError>>()?,
+            aliases: state.aliases,
+            events: Vec::new(),
+            config,
+        })
+    }
+}
+
+// Writes a state in the layout of the given version. Fields that version lacks are
+// dropped, so callers only pass a version at least as new as the state's source.
+fn write_state<W: Write>(state: StateV5, version: u32, mut writer: W) -> Result<(), StateError> {
+    let encoding = bincode::config::standard();
+    writer.write_all(MAGIC)?;
+    writer.write_all(&version.to_le_bytes())?;
+    if version == STATE_VERSION {
+        write_framed(state, &mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    let state = StateV3::from(state);
+    match version {
+        1 => {
+            let state = StateV1 {
+                accounts: state.accounts,
+                transactions: state.transactions,
+                disputes: state.disputes,
+                rejected: state.rejected,
+            };
+            bincode::encode_into_std_write(state, &mut writer, encoding)?;
+        }
+        2 => {
+            let state = StateV2 {
+                accounts: state.accounts,
+                transactions: state.transactions,
+                disputes: state.disputes,
+                rejected: state.rejected,
+                queued: state.queued,
+            };
+            bincode::encode_into_std_write(state, &mut writer, encoding)?;
+        }
+        3 => {
+            bincode::encode_into_std_write(state, &mut writer, encoding)?;
+        }
+        _ => write_framed(state, &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes a payload after its length and checksum, as versions from 4 on do
+fn write_framed<T: Encode, W: Write>(payload: T, writer: &mut W) -> Result<(), StateError> {
+    let payload = bincode::encode_to_vec(payload, bincode::config::standard())?;
+    writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+    writer.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+// Reads a payload written by `write_framed`, returning its checksum with it
+fn read_framed<T: Decode<()>, R: Read>(reader: &mut R) -> Result<(u32, T), StateError> {
+    let mut framing = [0; 12];
+    reader.read_exact(&mut framing)?;
+    let length = u64::from_le_bytes(framing[..8].try_into().unwrap());
+    let checksum = u32::from_le_bytes(framing[8..].try_into().unwrap());
+    let mut payload = Vec::new();
+    reader.take(length).read_to_end(&mut payload)?;
+    if payload.len() as u64 != length {
+        return Err(StateError::Corrupt("truncated"));
+    }
+    if crc32fast::hash(&payload) != checksum {
+        return Err(StateError::Corrupt("checksum mismatch"));
+    }
+    let (payload, _) = bincode::decode_from_slice(&payload, bincode::config::standard())?;
+    Ok((checksum, payload))
+}
+
+// A state file's contents upgraded to the current layout
+struct Loaded {
+    version: u32,
+    // Files before version 4 have none
+    checksum: Option<u32>,
+    state: StateV5,
+}
+
+fn read_state<R: Read>(mut reader: R) -> Result<Loaded, StateError> {
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(StateError::NotAStateFile);
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let encoding = bincode::config::standard();
+    let (checksum, state) = match version {
+        1 => {
+            let state: StateV1 = bincode::decode_from_std_read(&mut reader, encoding)?;
+            (None, StateV3::from(StateV2::from(state)).into())
+        }
+        2 => {
+            let state: StateV2 = bincode::decode_from_std_read(&mut reader, encoding)?;
+            (None, StateV3::from(state).into())
+        }
+        3 => {
+            let state: StateV3 = bincode::decode_from_std_read(&mut reader, encoding)?;
+            (None, state.into())
+        }
+        4 => {
+            let (checksum, state) = read_framed::<StateV3, _>(&mut reader)?;
+            (Some(checksum), state.into())
+        }
+        STATE_VERSION => {
+            let (checksum, state) = read_framed(&mut reader)?;
+            (Some(checksum), state)
+        }
+        _ => return Err(StateError::UnsupportedVersion(version)),
+    };
+    Ok(Loaded {
+        version,
+        checksum,
+        state,
+    })
+}
+
+// What a state file holds, without restoring an engine from it
+#[derive(Debug, Serialize)]
+pub struct StateSummary {
+    pub version: u32,
+    pub checksum: Option<u32>,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    pub transactions: usize,
+    pub open_disputes: usize,
+    pub rejected: usize,
+    pub queued: usize,
+    pub aliases: usize,
+}
+
+// Reads and verifies a state file saved with `save_state`, by this or an earlier version
+pub fn inspect_state<R: Read>(reader: R) -> Result<StateSummary, StateError> {
+    let Loaded {
+        version,
+        checksum,
+        state,
+    } = read_state(reader)?;
+    Ok(StateSummary {
+        version,
+        checksum,
+        accounts: state.accounts.len(),
+        locked_accounts: state.accounts.values().filter(|a| a.locked).count(),
+        transactions: state.transactions.len(),
+        open_disputes: state.disputes.len(),
+        rejected: state.rejected.len(),
+        queued: state.queued.values().map(Vec::len).sum(),
+        aliases: state.aliases.len(),
+    })
+}
+
+// Rewrites a state file of version `from` in the layout of version `to`. Only upgrades are
+// supported, as an older layout cannot hold everything a newer one may.
+pub fn migrate_state<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    from: u32,
+    to: u32,
+) -> Result<(), StateError> {
+    if from > to || to > STATE_VERSION {
+        return Err(StateError::UnsupportedMigration(from, to));
+    }
+    let loaded = read_state(reader)?;
+    if loaded.version != from {
+        return Err(StateError::VersionMismatch {
+            expected: from,
+            found: loaded.version,
+        });
+    }
+    write_state(loaded.state, to, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trips() {
+        let mut engine = PaymentsEngine::new();
+        let record = |tx_type, tx, amount| Record {
+            tx_type,
+            client: 1,
+            tx,
+            amount,
+        };
+        engine
+            .process(&record(TxType::Deposit, 1, Some(Decimal::new(15, 1))))
+            .unwrap();
+        engine.process(&record(TxType::Dispute, 1, None)).unwrap();
+
+        let mut saved = Vec::new();
+        engine.save_state(&mut saved).unwrap();
+        let mut restored =
+            PaymentsEngine::load_state(saved.as_slice(), EngineConfig::default()).unwrap();
+
+        assert_eq!(restored.accounts(), engine.accounts());
+        assert_eq!(restored.open_disputes().unwrap(), 1);
+        // The restored engine still knows tx 1 and that it is disputed
+        restored.process(&record(TxType::Resolve, 1, None)).unwrap();
+        assert_eq!(restored.accounts()[&1].available, Decimal::new(15, 1));
+
+        saved[4] = 9;
+        assert!(matches!(
+            PaymentsEngine::load_state(saved.as_slice(), EngineConfig::default()),
+            Err(StateError::UnsupportedVersion(9))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_state_is_refused() {
+        let mut engine = PaymentsEngine::new();
+        engine
+            .process(&Record {
+                tx_type: TxType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some(Decimal::new(15, 1)),
+            })
+            .unwrap();
+        let mut saved = Vec::new();
+        engine.save_state(&mut saved).unwrap();
+
+        let summary = inspect_state(saved.as_slice()).unwrap();
+        assert_eq!(summary.version, STATE_VERSION);
+        assert_eq!((summary.accounts, summary.transactions), (1, 1));
+
+        let last = saved.len() - 1;
+        saved[last] ^= 1;
+        assert!(matches!(
+            inspect_state(saved.as_slice()),
+            Err(StateError::Corrupt("checksum mismatch"))
+        ));
+        saved.pop();
+        assert!(matches!(
+            PaymentsEngine::load_state(saved.as_slice(), EngineConfig::default()),
+            Err(StateError::Corrupt("truncated"))
+        ));
+    }
+
+    // A version 1 file holding a deposit of 1.5 by client 1, with that account. The file
+    // holds 0.5 of it for authorizations, which loads as a reserve.
+    fn version_1_state() -> (Vec<u8>, Account, Record) {
+        let account = Account {
+            available: Decimal::ONE,
+            holds: Holds {
+                dispute: Decimal::ZERO,
+                reserve: Decimal::new(5, 1),
+            },
+            total: Decimal::new(15, 1),
+            ..Account::new()
+        };
+        let stored = StoredAccountV1 {
+            authorization_held: Decimal::new(5, 1).serialize(),
+            reserve_held: Decimal::ZERO.serialize(),
+            ..StoredAccount::from(&account).into()
+        };
+        let deposit = Record {
+            tx_type: TxType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(Decimal::new(15, 1)),
+        };
+        let state = StateV1 {
+            accounts: HashMap::from([(1, stored)]),
+            transactions: HashMap::from([(1, (&deposit).into())]),
+            disputes: HashSet::new(),
+            rejected: HashSet::new(),
+        };
+        let mut saved = MAGIC.to_vec();
+        saved.extend(1u32.to_le_bytes());
+        bincode::encode_into_std_write(state, &mut saved, bincode::config::standard()).unwrap();
+        (saved, account, deposit)
+    }
+
+    #[test]
+    fn test_version_1_state_loads() {
+        let (saved, account, deposit) = version_1_state();
+        let mut restored =
+            PaymentsEngine::load_state(saved.as_slice(), EngineConfig::default()).unwrap();
+        assert_eq!(restored.accounts()[&1], account);
+        assert!(matches!(
+            restored.process(&deposit),
+            Err(crate::EngineError::DuplicateTx(1))
+        ));
+    }
+
+    #[test]
+    fn test_version_1_state_migrates_to_the_current_version() {
+        let (saved, account, _) = version_1_state();
+        let mut migrated = Vec::new();
+        migrate_state(saved.as_slice(), &mut migrated, 1, STATE_VERSION).unwrap();
+
+        let summary = inspect_state(migrated.as_slice()).unwrap();
+        assert_eq!(summary.version, STATE_VERSION);
+        assert!(summary.checksum.is_some());
+        assert_eq!((summary.accounts, summary.transactions), (1, 1));
+        let restored =
+            PaymentsEngine::load_state(migrated.as_slice(), EngineConfig::default()).unwrap();
+        assert_eq!(restored.accounts()[&1], account);
+
+        // The file is not the version it was said to be, and downgrades are refused
+        assert!(matches!(
+            migrate_state(saved.as_slice(), Vec::new(), 2, STATE_VERSION),
+            Err(StateError::VersionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            migrate_state(migrated.as_slice(), Vec::new(), STATE_VERSION, 1),
+            Err(StateError::UnsupportedMigration(_, 1))
+        ));
+    }
+}
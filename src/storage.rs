@@ -0,0 +1,234 @@
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+
+use crate::{Accounts, ClientId, HashState, Record, StorageError, TransactionId, TxType};
+
+// Where the engine keeps processed transactions and open disputes, the collections that grow
+// with the input. Accounts are keyed by a u16 client id, so they always stay in memory; a
+// persistent storage may save them at each commit.
+// A storage error leaves the engine in an undefined state; processing should stop.
+pub trait Storage {
+    // The accounts saved by the last commit, for an engine starting on this storage
+    fn load_accounts(&self) -> Result<Accounts, StorageError> {
+        Ok(Accounts::default())
+    }
+
+    // Makes everything since the last commit durable, together with these account states
+    fn commit(&mut self, _accounts: &Accounts) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError>;
+
+    fn contains_transaction(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.transaction(tx)?.is_some())
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError>;
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError>;
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError>;
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError>;
+
+    fn open_disputes(&self) -> Result<usize, StorageError>;
+}
+
+// Keeps everything in HashMaps; the default, and the fastest while the input fits in RAM
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    pub(crate) transactions: HashMap<TransactionId, StoredTx, HashState>,
+    pub(crate) disputes: HashSet<TransactionId, HashState>,
+}
+
+// Only deposits and withdrawals are ever stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TxKind {
+    Deposit,
+    Withdrawal,
+}
+
+// A transaction as the memory storage keeps it: the map key is its id and it always has an
+// amount, which leaves 20 bytes against a full Record's 28
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StoredTx {
+    pub(crate) client: ClientId,
+    pub(crate) amount: Decimal,
+    pub(crate) kind: TxKind,
+}
+
+impl StoredTx {
+    // None for a record that is not a deposit or withdrawal with an amount
+    pub(crate) fn new(record: &Record) -> Option<StoredTx> {
+        let kind = match record.tx_type {
+            TxType::Deposit => TxKind::Deposit,
+            TxType::Withdrawal => TxKind::Withdrawal,
+            _ => return None,
+        };
+        Some(StoredTx {
+            client: record.client,
+            amount: record.amount?,
+            kind,
+        })
+    }
+
+    // Like `new`, for storages that must refuse anything else
+    pub(crate) fn storable(record: &Record) -> Result<StoredTx, StorageError> {
+        StoredTx::new(record).ok_or_else(|| {
+            StorageError(format!(
+                "{:?} transaction {} cannot be stored",
+                record.tx_type, record.tx
+            ))
+        })
+    }
+
+    pub(crate) fn record(self, tx: TransactionId) -> Record {
+        let tx_type = match self.kind {
+            TxKind::Deposit => TxType::Deposit,
+            TxKind::Withdrawal => TxType::Withdrawal,
+        };
+        Record {
+            tx_type,
+            client: self.client,
+            tx,
+            amount: Some(self.amount),
+        }
+    }
+}
+
+impl MemoryStorage {
+    // The ids of the stored transactions, in no particular order
+    pub fn transaction_ids(&self) -> impl Iterator<Item = TransactionId> + '_ {
+        self.transactions.keys().copied()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        Ok(self.transactions.get(&tx).map(|stored| stored.record(tx)))
+    }
+
+    fn contains_transaction(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.transactions.contains_key(&tx))
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        self.transactions
+            .insert(record.tx, StoredTx::storable(record)?);
+        Ok(())
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.disputes.contains(&tx))
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.insert(tx);
+        Ok(())
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.disputes.remove(&tx);
+        Ok(())
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        Ok(self.disputes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, EngineError, PaymentsEngine, TxType};
+    use rust_decimal::Decimal;
+
+    // Memory storage that refuses to hold more than one transaction
+    #[derive(Default)]
+    struct SmallStorage(MemoryStorage);
+
+    impl Storage for SmallStorage {
+        fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+            self.0.transaction(tx)
+        }
+
+        fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+            if !self.0.transactions.is_empty() {
+                return Err(StorageError("full".to_string()));
+            }
+            self.0.insert_transaction(record)
+        }
+
+        fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+            self.0.is_disputed(tx)
+        }
+
+        fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+            self.0.open_dispute(tx)
+        }
+
+        fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+            self.0.close_dispute(tx)
+        }
+
+        fn open_disputes(&self) -> Result<usize, StorageError> {
+            self.0.open_disputes()
+        }
+    }
+
+    #[test]
+    fn test_memory_storage_keeps_compact_transactions() {
+        assert_eq!(std::mem::size_of::<StoredTx>(), 20);
+        assert_eq!(std::mem::size_of::<Record>(), 28);
+
+        let mut storage = MemoryStorage::default();
+        let withdrawal = Record {
+            tx_type: TxType::Withdrawal,
+            client: 3,
+            tx: 9,
+            amount: Some(Decimal::new(125, 2)),
+        };
+        storage.insert_transaction(&withdrawal).unwrap();
+        let stored = storage.transaction(9).unwrap().unwrap();
+        assert_eq!(stored.tx_type, TxType::Withdrawal);
+        assert_eq!((stored.client, stored.tx), (3, 9));
+        assert_eq!(stored.amount, withdrawal.amount);
+
+        let dispute = Record {
+            tx_type: TxType::Dispute,
+            amount: None,
+            ..withdrawal
+        };
+        assert!(storage.insert_transaction(&dispute).is_err());
+    }
+
+    #[test]
+    fn test_engine_runs_on_custom_storage() {
+        let mut engine =
+            PaymentsEngine::with_storage(SmallStorage::default(), EngineConfig::default()).unwrap();
+        let deposit = |tx| Record {
+            tx_type: TxType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(Decimal::ONE),
+        };
+
+        engine.process(&deposit(1)).unwrap();
+        engine
+            .process(&Record {
+                tx_type: TxType::Dispute,
+                amount: None,
+                ..deposit(1)
+            })
+            .unwrap();
+        assert_eq!(engine.open_disputes().unwrap(), 1);
+        let before = engine.accounts()[&1].clone();
+        assert_eq!(
+            engine.process(&deposit(2)),
+            Err(EngineError::Storage("full".to_string()))
+        );
+        // The deposit storage refused is not credited either
+        assert_eq!(engine.accounts()[&1], before);
+    }
+}
@@ -0,0 +1,138 @@
+use futures_util::stream::{self, Stream, StreamExt};
+use std::io;
+use std::pin::pin;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+
+use crate::{EngineError, PaymentsEngine, Record, Storage, StorageError};
+
+impl<S: Storage> PaymentsEngine<S> {
+    // Processes records as the stream yields them, until it ends, for engines embedded in async
+    // services. Records the engine refuses are handed to `rejected` and processing goes on;
+    // only a storage failure stops it.
+    pub async fn process_stream<St>(
+        &mut self,
+        records: St,
+        mut rejected: impl FnMut(&Record, EngineError),
+    ) -> Result<(), StorageError>
+    where
+        St: Stream<Item = Record>,
+    {
+        let mut records = pin!(records);
+        while let Some(record) = records.next().await {
+            match self.process(&record) {
+                Ok(()) => {}
+                Err(EngineError::Storage(e)) => return Err(StorageError(e)),
+                Err(e) => rejected(&record, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Reads transaction records from a CSV with a `type,client,tx,amount` header, one record per
+// line as the transactions format has no multi-line fields. Fields are trimmed like the
+// synchronous reader does. A row that does not parse yields an InvalidData error naming its
+// line and the stream goes on; any other error ends it.
+pub fn read_records<R: AsyncBufRead + Unpin>(reader: R) -> impl Stream<Item = io::Result<Record>> {
+    struct State<R> {
+        lines: Lines<R>,
+        headers: Option<csv::StringRecord>,
+        line: u64,
+        failed: bool,
+    }
+
+    let state = State {
+        lines: reader.lines(),
+        headers: None,
+        line: 0,
+        failed: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.failed {
+            return None;
+        }
+        loop {
+            let text = match state.lines.next_line().await {
+                Ok(Some(text)) => text,
+                Ok(None) => return None,
+                Err(e) => {
+                    state.failed = true;
+                    return Some((Err(e), state));
+                }
+            };
+            state.line += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+            // csv's own position would be that within the single line it was given
+            let invalid = |e: csv::Error| {
+                let reason = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => e.to_string(),
+                };
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", state.line, reason),
+                )
+            };
+            let row = match parse_row(&text) {
+                Ok(row) => row,
+                Err(e) => return Some((Err(invalid(e)), state)),
+            };
+            let Some(headers) = &state.headers else {
+                state.headers = Some(row);
+                continue;
+            };
+            let record = row.deserialize(Some(headers)).map_err(invalid);
+            return Some((record, state));
+        }
+    })
+}
+
+fn parse_row(text: &str) -> Result<csv::StringRecord, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut row = csv::StringRecord::new();
+    rdr.read_record(&mut row)?;
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_executor::block_on;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_engine_processes_an_async_csv_stream() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 2, 2, 3.0\n\
+                     \n\
+                     bogus, 1, 3, 1.0\n\
+                     withdrawal, 1, 4, 9.0\n\
+                     dispute, 2, 2,\n";
+
+        let records: Vec<io::Result<Record>> = block_on(read_records(input.as_bytes()).collect());
+        assert_eq!(records.len(), 5);
+        let error = records[2].as_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "line 5: Unknown transaction type: \"bogus\""
+        );
+
+        let mut engine = PaymentsEngine::new();
+        let mut refused = Vec::new();
+        let records = stream::iter(records.into_iter().filter_map(Result::ok));
+        block_on(engine.process_stream(records, |record, e| refused.push((record.tx, e)))).unwrap();
+
+        assert_eq!(refused.len(), 1);
+        assert_eq!(refused[0].0, 4);
+        assert_eq!(engine.accounts()[&1].available, Decimal::new(5, 0));
+        assert_eq!(engine.accounts()[&2].held(), Decimal::new(3, 0));
+    }
+}
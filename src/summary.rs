@@ -0,0 +1,146 @@
+use exchange_test::{PaymentsEngine, Record, Storage, StorageError, TxType};
+use rust_decimal::Decimal;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Accepted/rejected counts for one transaction type, and how many took longer to apply than
+// the --slow-tx-ms threshold
+#[derive(Debug, Default, Serialize)]
+pub struct TypeStats {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub slow: usize,
+}
+
+// Counts and accepted deposit/withdrawal value for one input, so a feed behind a spike in
+// rejects stands out when several are merged
+#[derive(Debug, Default)]
+pub struct SourceStats {
+    pub processed: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub deposited: Decimal,
+    pub withdrawn: Decimal,
+}
+
+impl SourceStats {
+    pub fn reject_rate(&self) -> f64 {
+        if self.processed == 0 {
+            0.0
+        } else {
+            self.rejected as f64 / self.processed as f64
+        }
+    }
+}
+
+impl Serialize for SourceStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SourceStats", 6)?;
+        state.serialize_field("processed", &self.processed)?;
+        state.serialize_field("accepted", &self.accepted)?;
+        state.serialize_field("rejected", &self.rejected)?;
+        state.serialize_field("reject_rate", &self.reject_rate())?;
+        state.serialize_field("deposited", &format!("{:.4}", self.deposited))?;
+        state.serialize_field("withdrawn", &format!("{:.4}", self.withdrawn))?;
+        state.end()
+    }
+}
+
+// Counts of records seen during a run
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    pub processed: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    // Keyed by transaction type; rows that could not be parsed are counted under "invalid"
+    pub by_type: BTreeMap<&'static str, TypeStats>,
+    // Keyed by input path, in the form it was given
+    pub by_source: BTreeMap<String, SourceStats>,
+}
+
+impl RunStats {
+    // Counts one row of `source`; `record` is None when the row could not be parsed
+    pub fn record(&mut self, source: &Path, record: Option<&Record>, accepted: bool) {
+        let tx_type = record.map(|r| r.tx_type);
+        let by_type = self
+            .by_type
+            .entry(tx_type.map_or("invalid", |t| t.as_str()))
+            .or_default();
+        let by_source = self
+            .by_source
+            .entry(source.display().to_string())
+            .or_default();
+        self.processed += 1;
+        by_source.processed += 1;
+        if accepted {
+            self.accepted += 1;
+            by_type.accepted += 1;
+            by_source.accepted += 1;
+        } else {
+            self.rejected += 1;
+            by_type.rejected += 1;
+            by_source.rejected += 1;
+        }
+
+        if let Some(Record {
+            tx_type,
+            amount: Some(amount),
+            ..
+        }) = record.filter(|_| accepted)
+        {
+            match tx_type {
+                TxType::Deposit => by_source.deposited += amount,
+                TxType::Withdrawal => by_source.withdrawn += amount,
+                _ => {}
+            }
+        }
+    }
+
+    // Counts a record that was slow to apply
+    pub fn record_slow(&mut self, tx_type: TxType) {
+        self.by_type.entry(tx_type.as_str()).or_default().slow += 1;
+    }
+}
+
+// End-of-run summary for monitoring batch runs without parsing the account output
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    #[serde(flatten)]
+    pub stats: &'a RunStats,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    pub open_disputes: usize,
+    // Transactions queued for locked accounts that were never unlocked
+    pub unapplied_queued: usize,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+}
+
+impl<'a> Summary<'a> {
+    pub fn new<S: Storage>(
+        stats: &'a RunStats,
+        engine: &PaymentsEngine<S>,
+    ) -> Result<Summary<'a>, StorageError> {
+        let accounts = engine.accounts();
+        let (mut available, mut held, mut total) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+        for account in accounts.values() {
+            available += account.available;
+            held += account.held();
+            total += account.total;
+        }
+
+        Ok(Summary {
+            stats,
+            accounts: accounts.len(),
+            locked_accounts: accounts.values().filter(|a| a.locked).count(),
+            open_disputes: engine.open_disputes()?,
+            unapplied_queued: engine.queued().len(),
+            available: format!("{:.4}", available),
+            held: format!("{:.4}", held),
+            total: format!("{:.4}", total),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use rust_decimal::Decimal;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ClientId, EngineError, TransactionId};
+
+// Represents the different types of transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+impl TxType {
+    // The lowercase name used for the `type` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxType::Deposit => "deposit",
+            TxType::Withdrawal => "withdrawal",
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            TxType::Chargeback => "chargeback",
+        }
+    }
+}
+
+impl FromStr for TxType {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Compared in place rather than lowercased, so parsing a type never allocates
+        const TYPES: [TxType; 5] = [
+            TxType::Deposit,
+            TxType::Withdrawal,
+            TxType::Dispute,
+            TxType::Resolve,
+            TxType::Chargeback,
+        ];
+        TYPES
+            .into_iter()
+            .find(|tx_type| tx_type.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| EngineError::UnknownTxType(s.to_string()))
+    }
+}
+
+// Parses the `type` column straight into a TxType so records never carry the raw string
+impl<'de> Deserialize<'de> for TxType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TxTypeVisitor;
+
+        impl Visitor<'_> for TxTypeVisitor {
+            type Value = TxType;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a transaction type")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<TxType, E> {
+                TxType::from_str(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(TxTypeVisitor)
+    }
+}
+
+// Represents a transaction record parsed from the CSV input
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Record {
+    #[serde(rename = "type")]
+    pub tx_type: TxType,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    #[serde(default, deserialize_with = "csv::invalid_option")]
+    pub amount: Option<Decimal>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::ReaderBuilder;
+
+    #[test]
+    fn test_tx_type_deserialization() {
+        let data = "type,client,tx,amount\nDeposit,1,1,1.0\nDISPUTE,1,1,\nbogus,1,2,1.0\n";
+        let mut rdr = ReaderBuilder::new().from_reader(data.as_bytes());
+        let results: Vec<Result<Record, csv::Error>> = rdr.deserialize().collect();
+
+        assert_eq!(results[0].as_ref().unwrap().tx_type, TxType::Deposit);
+        assert_eq!(results[1].as_ref().unwrap().tx_type, TxType::Dispute);
+        assert!(results[2].is_err());
+    }
+}
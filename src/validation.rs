@@ -0,0 +1,149 @@
+use rust_decimal::Decimal;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::{EngineError, Record, MAX_PRECISION};
+
+// One rule a deposit or withdrawal amount must satisfy. Deployments can add their own rules
+// to the chain in EngineConfig without touching the transaction processing itself.
+pub trait Validator: Debug + Send + Sync {
+    fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError>;
+}
+
+// Refuses negative amounts
+#[derive(Debug)]
+pub struct NonNegative;
+
+impl Validator for NonNegative {
+    fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError> {
+        if amount.is_sign_negative() {
+            return Err(EngineError::NegativeAmount {
+                tx_type: record.tx_type,
+                tx: record.tx,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Refuses amounts with more decimal places than the given number
+#[derive(Debug)]
+pub struct MaxPrecision(pub u32);
+
+impl Validator for MaxPrecision {
+    fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError> {
+        // Scale gives the number of decimal places
+        if amount.scale() > self.0 {
+            return Err(EngineError::PrecisionExceeded {
+                tx_type: record.tx_type,
+                tx: record.tx,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Refuses amounts above a per-transaction limit
+#[derive(Debug)]
+pub struct MaxAmount(pub Decimal);
+
+impl Validator for MaxAmount {
+    fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError> {
+        if amount > self.0 {
+            return Err(EngineError::AmountLimitExceeded {
+                tx_type: record.tx_type,
+                tx: record.tx,
+                limit: self.0,
+            });
+        }
+        Ok(())
+    }
+}
+
+// The validators applied, in order, to every deposit and withdrawal amount; the first
+// failure rejects the transaction. The default chain enforces the original specification.
+#[derive(Debug, Clone)]
+pub struct Validators(Vec<Arc<dyn Validator>>);
+
+impl Validators {
+    // A chain without any rules
+    pub fn empty() -> Validators {
+        Validators(Vec::new())
+    }
+
+    pub fn push(&mut self, validator: impl Validator + 'static) {
+        self.0.push(Arc::new(validator));
+    }
+
+    pub(crate) fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError> {
+        self.0
+            .iter()
+            .try_for_each(|validator| validator.validate(record, amount))
+    }
+}
+
+impl Default for Validators {
+    fn default() -> Validators {
+        let mut validators = Validators::empty();
+        validators.push(NonNegative);
+        validators.push(MaxPrecision(MAX_PRECISION));
+        validators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, PaymentsEngine, TxType};
+
+    // A regional rule that is not part of the default chain
+    #[derive(Debug)]
+    struct WholeUnitsOnly;
+
+    impl Validator for WholeUnitsOnly {
+        fn validate(&self, record: &Record, amount: Decimal) -> Result<(), EngineError> {
+            if !amount.fract().is_zero() {
+                return Err(EngineError::PrecisionExceeded {
+                    tx_type: record.tx_type,
+                    tx: record.tx,
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_configured_validators_run_in_order() {
+        let mut validators = Validators::default();
+        validators.push(MaxAmount(Decimal::new(100, 0)));
+        validators.push(WholeUnitsOnly);
+        let mut engine = PaymentsEngine::with_config(EngineConfig {
+            validators,
+            ..EngineConfig::default()
+        });
+        let deposit = |tx, amount| Record {
+            tx_type: TxType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(amount),
+        };
+
+        engine.process(&deposit(1, Decimal::new(100, 0))).unwrap();
+        assert_eq!(
+            engine.process(&deposit(2, Decimal::new(-200, 0))),
+            Err(EngineError::NegativeAmount {
+                tx_type: TxType::Deposit,
+                tx: 2
+            })
+        );
+        assert_eq!(
+            engine.process(&deposit(3, Decimal::new(1001, 1))),
+            Err(EngineError::AmountLimitExceeded {
+                tx_type: TxType::Deposit,
+                tx: 3,
+                limit: Decimal::new(100, 0)
+            })
+        );
+        assert!(engine.process(&deposit(4, Decimal::new(15, 1))).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use exchange_test::Record;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use crate::source::record_fields;
+
+// Write-ahead log of a processing run: every parsed transaction is appended, in the
+// transactions CSV format, before the engine applies it. Rejected ones are logged too, so
+// that replaying the log on top of the run's starting state reproduces it exactly.
+pub struct WalWriter {
+    wtr: csv::Writer<File>,
+}
+
+impl WalWriter {
+    // Starts a new log, replacing whatever an earlier run left at `path`
+    pub fn create(path: &Path) -> Result<WalWriter, Box<dyn Error>> {
+        let mut wal = WalWriter {
+            wtr: csv::Writer::from_writer(File::create(path)?),
+        };
+        wal.wtr.write_record(["type", "client", "tx", "amount"])?;
+        wal.sync()?;
+        Ok(wal)
+    }
+
+    // Continues the log of a run resumed from a checkpoint taken when the log was `len` bytes
+    // long. The entries after that are dropped, as the resumed run logs those rows again.
+    pub fn resume(path: &Path, len: u64) -> Result<WalWriter, Box<dyn Error>> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        if file.metadata()?.len() < len {
+            return Err(format!(
+                "{} is shorter than when the checkpoint was taken",
+                path.display()
+            )
+            .into());
+        }
+        file.set_len(len)?;
+        file.sync_data()?;
+        Ok(WalWriter {
+            wtr: csv::Writer::from_writer(file),
+        })
+    }
+
+    // Syncs every entry to disk, so the log holds everything up to the record a crashed run
+    // was on
+    pub fn append(&mut self, record: &Record) -> Result<(), Box<dyn Error>> {
+        self.wtr.write_record(record_fields(record))?;
+        Ok(self.sync()?)
+    }
+
+    // The length of the log so far, which a checkpoint records
+    pub fn position(&self) -> io::Result<u64> {
+        Ok(self.wtr.get_ref().metadata()?.len())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.wtr.flush()?;
+        self.wtr.get_ref().sync_data()
+    }
+}
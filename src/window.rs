@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use crate::{Accounts, HashState, Record, Storage, StorageError, TransactionId};
+
+// Bounds on the recent transactions a WindowedStorage keeps in memory. Either bound, or
+// both, may be set; with neither the window grows like MemoryStorage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupWindow {
+    pub max_entries: Option<usize>,
+    // How long a transaction stays in memory after it was inserted
+    pub ttl: Option<Duration>,
+}
+
+impl DedupWindow {
+    // A window of as many transactions as take about `bytes` of memory in a WindowedStorage.
+    // The estimate is of each entry in the map and queue at their fullest before growing,
+    // so the real use may be up to twice that just after they grew.
+    pub fn for_memory(bytes: u64) -> DedupWindow {
+        let map_entry = (mem::size_of::<(TransactionId, Recent)>() + 1) * 8 / 7;
+        let queue_entry = mem::size_of::<(TransactionId, Instant)>();
+        DedupWindow {
+            max_entries: Some((bytes / (map_entry + queue_entry) as u64).max(1) as usize),
+            ttl: None,
+        }
+    }
+}
+
+// Keeps the most recent transactions in memory, where duplicates and disputes of them are
+// found fastest, and moves older ones to an overflow storage as the window bounds require.
+// Nothing is forgotten: lookups that miss the window go on to the overflow, so a replay of
+// old transactions is still caught as duplicate. Disputes live in the overflow.
+pub struct WindowedStorage<O> {
+    recent: HashMap<TransactionId, Recent, HashState>,
+    order: VecDeque<(TransactionId, Instant)>,
+    overflow: O,
+    window: DedupWindow,
+}
+
+struct Recent {
+    record: Record,
+    // Whether a commit already wrote it to the overflow
+    committed: bool,
+}
+
+impl<O: Storage> WindowedStorage<O> {
+    pub fn new(overflow: O, window: DedupWindow) -> WindowedStorage<O> {
+        WindowedStorage {
+            recent: HashMap::default(),
+            order: VecDeque::new(),
+            overflow,
+            window,
+        }
+    }
+
+    // The number of transactions held in memory
+    pub fn recent(&self) -> usize {
+        self.recent.len()
+    }
+
+    fn evict(&mut self) -> Result<(), StorageError> {
+        let now = Instant::now();
+        while let Some(&(tx, inserted)) = self.order.front() {
+            let over_count = self
+                .window
+                .max_entries
+                .is_some_and(|max| self.order.len() > max);
+            let expired = self
+                .window
+                .ttl
+                .is_some_and(|ttl| now.duration_since(inserted) >= ttl);
+            if !over_count && !expired {
+                break;
+            }
+            self.order.pop_front();
+            if let Some(recent) = self.recent.remove(&tx).filter(|r| !r.committed) {
+                self.overflow.insert_transaction(&recent.record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<O: Storage> Storage for WindowedStorage<O> {
+    fn load_accounts(&self) -> Result<Accounts, StorageError> {
+        self.overflow.load_accounts()
+    }
+
+    // Writes the window through to the overflow, so a persistent overflow holds the complete
+    // history once committed; the window keeps serving lookups
+    fn commit(&mut self, accounts: &Accounts) -> Result<(), StorageError> {
+        for (tx, _) in &self.order {
+            let recent = self.recent.get_mut(tx).unwrap();
+            if !recent.committed {
+                self.overflow.insert_transaction(&recent.record)?;
+                recent.committed = true;
+            }
+        }
+        self.overflow.commit(accounts)
+    }
+
+    fn transaction(&self, tx: TransactionId) -> Result<Option<Record>, StorageError> {
+        match self.recent.get(&tx) {
+            Some(recent) => Ok(Some(recent.record)),
+            None => self.overflow.transaction(tx),
+        }
+    }
+
+    fn contains_transaction(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        Ok(self.recent.contains_key(&tx) || self.overflow.contains_transaction(tx)?)
+    }
+
+    fn insert_transaction(&mut self, record: &Record) -> Result<(), StorageError> {
+        let recent = Recent {
+            record: *record,
+            committed: false,
+        };
+        self.recent.insert(record.tx, recent);
+        self.order.push_back((record.tx, Instant::now()));
+        self.evict()
+    }
+
+    fn is_disputed(&self, tx: TransactionId) -> Result<bool, StorageError> {
+        self.overflow.is_disputed(tx)
+    }
+
+    fn open_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.overflow.open_dispute(tx)
+    }
+
+    fn close_dispute(&mut self, tx: TransactionId) -> Result<(), StorageError> {
+        self.overflow.close_dispute(tx)
+    }
+
+    fn open_disputes(&self) -> Result<usize, StorageError> {
+        self.overflow.open_disputes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineConfig, EngineError, MemoryStorage, PaymentsEngine, TxType};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_window_spills_to_overflow_without_forgetting() {
+        let window = DedupWindow {
+            max_entries: Some(2),
+            ttl: None,
+        };
+        let storage = WindowedStorage::new(MemoryStorage::default(), window);
+        let mut engine = PaymentsEngine::with_storage(storage, EngineConfig::default()).unwrap();
+        let deposit = |tx| Record {
+            tx_type: TxType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(Decimal::ONE),
+        };
+
+        for tx in 1..=5 {
+            engine.process(&deposit(tx)).unwrap();
+        }
+        assert_eq!(engine.storage.recent(), 2);
+        assert_eq!(engine.storage.overflow.transactions.len(), 3);
+        // Evicted transactions are still duplicates, and can still be disputed
+        assert_eq!(
+            engine.process(&deposit(1)),
+            Err(EngineError::DuplicateTx(1))
+        );
+        engine
+            .process(&Record {
+                tx_type: TxType::Dispute,
+                amount: None,
+                ..deposit(1)
+            })
+            .unwrap();
+        assert_eq!(engine.accounts()[&1].held(), Decimal::ONE);
+
+        // A commit writes the window through; an expired window empties on the next insert
+        engine.commit().unwrap();
+        assert_eq!(engine.storage.overflow.transactions.len(), 5);
+        engine.storage.window.ttl = Some(Duration::ZERO);
+        engine.process(&deposit(6)).unwrap();
+        assert_eq!(engine.storage.recent(), 0);
+        assert_eq!(engine.storage.overflow.transactions.len(), 6);
+    }
+}